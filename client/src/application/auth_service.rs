@@ -1,46 +1,172 @@
+use crate::domain::errors::user_message;
 use crate::domain::models::User;
 use crate::infrastructure::api_client::ApiClient;
-use shared::api::auth::{LoginData, RegisterData};
+use shared::api::account::{PasswordResetData, PasswordResetRequestData, VerifyEmailData};
+use shared::api::auth::{LoginData, LoginResponse, MfaLoginData, RegisterData};
+use shared::api::error::ApiError;
+use shared::policy::RegistrationPolicy;
+use leptos::leptos_dom::helpers::IntervalHandle;
 use leptos::{RwSignal, SignalSet};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
 use web_sys;
 
+/// How often the access token is renewed, comfortably inside its 15 minute lifetime.
+const TOKEN_RENEWAL_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
 #[derive(Clone)]
 pub struct AuthService {
     api_client: ApiClient,
+    renewal: Rc<RefCell<Option<IntervalHandle>>>,
 }
 
 impl AuthService {
     pub fn new(api_client: ApiClient) -> Self {
-        Self { api_client }
+        Self {
+            api_client,
+            renewal: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    /// Logs in and starts keeping the session alive. Fails with
+    /// `ErrorCode::PasswordResetRequired` until `new_password` is given to an account
+    /// that has to change its password, and with `ErrorCode::MfaRequired` for accounts
+    /// that finish with `login_mfa`.
+    pub async fn login(
+        &self,
+        username: String,
+        password: String,
+        new_password: Option<String>,
+        user_signal: RwSignal<Option<User>>,
+    ) -> Result<(), ApiError> {
+        if let Some(new_password) = &new_password {
+            RegistrationPolicy::default().check_password(new_password, &username)?;
+        }
+        // The browser keeps the token in an HttpOnly cookie, it never needs to see it
+        let login_data = LoginData { username, password, include_token: false, new_password };
+        let result = self.api_client.login(login_data).await;
+        self.start_session(result, user_signal)
+    }
+
+    /// Finishes a login with the code from the authenticator app or a recovery code.
+    pub async fn login_mfa(
+        &self,
+        username: &str,
+        mfa_token: String,
+        code: String,
+        new_password: Option<String>,
+        user_signal: RwSignal<Option<User>>,
+    ) -> Result<(), ApiError> {
+        if let Some(new_password) = &new_password {
+            RegistrationPolicy::default().check_password(new_password, username)?;
+        }
+        let mfa_data = MfaLoginData { mfa_token, code, include_token: false, new_password };
+        let result = self.api_client.login_mfa(mfa_data).await;
+        self.start_session(result, user_signal)
     }
 
-    pub async fn login(&self, username: String, password: String, user_signal: RwSignal<Option<User>>) {
-        let login_data = LoginData { username: username.clone(), password };
-        match self.api_client.login(login_data).await {
-            Ok(token) => {
+    fn start_session(
+        &self,
+        result: Result<LoginResponse, ApiError>,
+        user_signal: RwSignal<Option<User>>,
+    ) -> Result<(), ApiError> {
+        match result {
+            Ok(response) => {
                 let user = User {
-                    username,
-                    token: Some(token),
+                    username: response.username,
                 };
                 // Update the user_signal
                 leptos::spawn_local(async move {
                     user_signal.set(Some(user));
                 });
+                self.start_token_renewal();
+                Ok(())
             }
             Err(err) => {
-                web_sys::console::error_1(&format!("Login failed: {}", err).into());
+                web_sys::console::error_1(&format!("Login failed: {:?}", err).into());
+                Err(err)
             }
         }
     }
 
-    pub async fn register(&self, username: String, password: String) {
-        let register_data = RegisterData { username, password };
+    pub async fn logout(&self, user_signal: RwSignal<Option<User>>) {
+        if let Some(handle) = self.renewal.borrow_mut().take() {
+            handle.clear();
+        }
+
+        if let Err(err) = self.api_client.logout().await {
+            web_sys::console::error_1(&format!("Logout failed: {}", err).into());
+        }
+
+        // Forget the user even if the request failed, the cookies are gone or useless
+        user_signal.set(None);
+    }
+
+    /// Registers the account, an email is optional and gets a verification mail.
+    pub async fn register(&self, username: String, password: String, email: Option<String>) -> Result<(), String> {
+        let register_data = RegisterData { username, password, email };
+        // Same rules as the service, caught without a round trip
+        register_data.validate(&RegistrationPolicy::default()).map_err(|err| user_message(&err))?;
         match self.api_client.register(register_data).await {
             Ok(_) => {
                 web_sys::console::log_1(&"Registration successful".into());
+                Ok(())
+            }
+            Err(err) => {
+                web_sys::console::error_1(&format!("Registration failed: {:?}", err).into());
+                Err(user_message(&err))
+            }
+        }
+    }
+
+    /// Confirms the email with the token from the link in the verification mail.
+    pub async fn verify_email(&self, token: String) -> Result<(), String> {
+        self.api_client.verify_email(VerifyEmailData { token }).await.map_err(|err| user_message(&err))
+    }
+
+    /// Asks for a reset link, which goes to the account's verified email if it has one.
+    pub async fn request_password_reset(&self, username: String) -> Result<(), String> {
+        self.api_client
+            .request_password_reset(PasswordResetRequestData { username })
+            .await
+            .map_err(|err| user_message(&err))
+    }
+
+    /// Sets a new password with the token from the link in the reset mail.
+    pub async fn reset_password(&self, token: String, new_password: String) -> Result<(), String> {
+        // The username is not known here, the service also checks the password against it
+        RegistrationPolicy::default().check_password(&new_password, "").map_err(|err| user_message(&err))?;
+        self.api_client
+            .confirm_password_reset(PasswordResetData { token, new_password })
+            .await
+            .map_err(|err| user_message(&err))
+    }
+
+    /// Silently exchanges the refresh token cookie for a new access token before the
+    /// current one expires, so players stay logged in.
+    fn start_token_renewal(&self) {
+        let api_client = self.api_client.clone();
+        let result = leptos::set_interval_with_handle(
+            move || {
+                let api_client = api_client.clone();
+                leptos::spawn_local(async move {
+                    if let Err(err) = api_client.refresh().await {
+                        web_sys::console::error_1(&format!("Token renewal failed: {}", err).into());
+                    }
+                });
+            },
+            TOKEN_RENEWAL_INTERVAL,
+        );
+
+        match result {
+            Ok(handle) => {
+                if let Some(previous) = self.renewal.borrow_mut().replace(handle) {
+                    previous.clear();
+                }
             }
             Err(err) => {
-                web_sys::console::error_1(&format!("Registration failed: {}", err).into());
+                web_sys::console::error_1(&format!("Failed to schedule token renewal: {:?}", err).into());
             }
         }
     }
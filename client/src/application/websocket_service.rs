@@ -4,12 +4,14 @@ use web_sys::{WebSocket, MessageEvent, Event, ErrorEvent, BinaryType};
 use std::rc::Rc;
 use std::cell::RefCell;
 
+type Callback = Rc<RefCell<Option<Box<dyn Fn()>>>>;
+
 #[derive(Clone)]
 pub struct WebSocketService {
     ws: Rc<WebSocket>,
     is_open: Rc<RefCell<bool>>,
     message_queue: Rc<RefCell<Vec<String>>>,
-    on_open: Rc<RefCell<Option<Box<dyn Fn()>>>>,
+    on_open: Callback,
 }
 
 impl WebSocketService {
@@ -116,4 +118,10 @@ impl WebSocketService {
     {
         *self.on_open.borrow_mut() = Some(Box::new(callback));
     }
+
+    pub fn close(&self) {
+        if let Err(err) = self.ws.close() {
+            web_sys::console::error_1(&format!("Failed to close WebSocket: {:?}", err).into());
+        }
+    }
 }
@@ -0,0 +1,23 @@
+use shared::api::error::{ApiError, ErrorCode};
+
+/// What to tell the player about a failed request.
+pub fn user_message(error: &ApiError) -> String {
+    match error.code {
+        ErrorCode::UsernameTaken => "That username is already taken".into(),
+        // A wrong password or code confirming something names its field, a failed login does not
+        ErrorCode::InvalidCredentials if error.field.is_some() => error.message.clone(),
+        ErrorCode::InvalidCredentials => "Wrong username or password".into(),
+        ErrorCode::Unauthorized => "Your session has expired, please log in again".into(),
+        ErrorCode::Forbidden => "You are not allowed to do that".into(),
+        ErrorCode::NotFound => "That does not exist any more".into(),
+        ErrorCode::PasswordResetRequired => "You need to choose a new password to continue".into(),
+        ErrorCode::MfaRequired => "Enter the code from your authenticator app".into(),
+        ErrorCode::ServiceBusy => "The server is busy, please try again in a moment".into(),
+        ErrorCode::Internal => "Something went wrong on our side, please try again later".into(),
+        // These carry the specifics, like which rule failed, how long to wait or why a ban was given
+        ErrorCode::InvalidInput | ErrorCode::WeakPassword | ErrorCode::RateLimited | ErrorCode::Banned => {
+            error.message.clone()
+        }
+        ErrorCode::Unknown => "Something went wrong, please try again".into(),
+    }
+}
@@ -1,5 +1,4 @@
 #[derive(Clone)]
 pub struct User {
     pub username: String,
-    pub token: Option<String>,
-}
\ No newline at end of file
+}
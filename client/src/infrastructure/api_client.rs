@@ -1,4 +1,6 @@
-use shared::api::auth::{LoginData, RegisterData};
+use shared::api::account::{PasswordResetData, PasswordResetRequestData, VerifyEmailData};
+use shared::api::auth::{LoginData, LoginResponse, MfaLoginData, RegisterData};
+use shared::api::error::{ApiError, ErrorCode};
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{RequestInit, RequestMode, RequestCredentials, Response};
 use wasm_bindgen::JsValue;
@@ -14,84 +16,115 @@ impl ApiClient {
         Self { base_url }
     }
 
-    pub async fn login(&self, login_data: LoginData) -> Result<String, String> {
-        let body = serde_json::to_string(&login_data).map_err(|e| e.to_string())?;
+    pub async fn login(&self, login_data: LoginData) -> Result<LoginResponse, ApiError> {
+        let body = serde_json::to_string(&login_data).map_err(|e| client_error(e.to_string()))?;
+        let resp = self.post("/login", Some(body)).await?;
+        login_response(resp).await
+    }
 
-        let opts = RequestInit::new();
-        opts.set_method("POST");
-        opts.set_body(&JsValue::from_str(&body));
-        opts.set_mode(RequestMode::Cors);
-        opts.set_credentials(RequestCredentials::Include);
+    /// The second step of a login that answered with `ErrorCode::MfaRequired`.
+    pub async fn login_mfa(&self, mfa_data: MfaLoginData) -> Result<LoginResponse, ApiError> {
+        let body = serde_json::to_string(&mfa_data).map_err(|e| client_error(e.to_string()))?;
+        let resp = self.post("/login/mfa", Some(body)).await?;
+        login_response(resp).await
+    }
 
-        let request = web_sys::Request::new_with_str_and_init(
-            &format!("{}/login", self.base_url),
-            &opts,
-        )
-        .map_err(|e| e.as_string().unwrap_or("Request creation failed".into()))?;
+    pub async fn register(&self, register_data: RegisterData) -> Result<(), ApiError> {
+        let body = serde_json::to_string(&register_data).map_err(|e| client_error(e.to_string()))?;
+        self.post("/register", Some(body)).await?;
+        Ok(())
+    }
 
-        request
-            .headers()
-            .set("Content-Type", "application/json")
-            .map_err(|e| e.as_string().unwrap_or("Header setting failed".into()))?;
+    pub async fn verify_email(&self, verify_data: VerifyEmailData) -> Result<(), ApiError> {
+        let body = serde_json::to_string(&verify_data).map_err(|e| client_error(e.to_string()))?;
+        self.post("/verify-email", Some(body)).await?;
+        Ok(())
+    }
 
-        let window = web_sys::window().ok_or("No global `window` exists")?;
-        let resp_value = JsFuture::from(window.fetch_with_request(&request))
-            .await
-            .map_err(|e| e.as_string().unwrap_or("Fetch failed".into()))?;
+    pub async fn request_password_reset(&self, request_data: PasswordResetRequestData) -> Result<(), ApiError> {
+        let body = serde_json::to_string(&request_data).map_err(|e| client_error(e.to_string()))?;
+        self.post("/password-reset/request", Some(body)).await?;
+        Ok(())
+    }
 
-        let resp: Response = resp_value
-            .dyn_into()
-            .map_err(|_| "Failed to cast to Response".to_string())?;
-        if resp.ok() {
-            let text_js = JsFuture::from(resp.text().map_err(|_| "Failed to get text".to_string())?)
-                .await
-                .map_err(|_| "Failed to await text".to_string())?;
-            let text = text_js.as_string().ok_or("Response text is not a string".to_string())?;
-            
-            // Assuming the server sends "Login successful, token: {token}"
-            if let Some(token_start) = text.find("token: ") {
-                let token = text[token_start + 7..].trim().to_string();
-                Ok(token)
-            } else {
-                Err("Token not found in response".into())
-            }
-        } else {
-            Err(format!("HTTP error: {}", resp.status()))
-        }
+    pub async fn confirm_password_reset(&self, reset_data: PasswordResetData) -> Result<(), ApiError> {
+        let body = serde_json::to_string(&reset_data).map_err(|e| client_error(e.to_string()))?;
+        self.post("/password-reset/confirm", Some(body)).await?;
+        Ok(())
+    }
+
+    pub async fn refresh(&self) -> Result<(), ApiError> {
+        self.post("/refresh", None).await?;
+        Ok(())
     }
 
-    pub async fn register(&self, register_data: RegisterData) -> Result<(), String> {
-        let body = serde_json::to_string(&register_data).map_err(|e| e.to_string())?;
+    pub async fn logout(&self) -> Result<(), ApiError> {
+        self.post("/logout", None).await?;
+        Ok(())
+    }
 
+    /// Sends a POST with the session cookies, turning any failure into an `ApiError`.
+    async fn post(&self, path: &str, json_body: Option<String>) -> Result<Response, ApiError> {
         let opts = RequestInit::new();
         opts.set_method("POST");
-        opts.set_body(&JsValue::from_str(&body));
         opts.set_mode(RequestMode::Cors);
         opts.set_credentials(RequestCredentials::Include);
+        if let Some(body) = &json_body {
+            opts.set_body(&JsValue::from_str(body));
+        }
 
         let request = web_sys::Request::new_with_str_and_init(
-            &format!("{}/register", self.base_url),
+            &format!("{}{}", self.base_url, path),
             &opts,
         )
-        .map_err(|e| e.as_string().unwrap_or("Request creation failed".into()))?;
+        .map_err(|e| client_error(e.as_string().unwrap_or("Request creation failed".into())))?;
 
-        request
-            .headers()
-            .set("Content-Type", "application/json")
-            .map_err(|e| e.as_string().unwrap_or("Header setting failed".into()))?;
+        if json_body.is_some() {
+            request
+                .headers()
+                .set("Content-Type", "application/json")
+                .map_err(|e| client_error(e.as_string().unwrap_or("Header setting failed".into())))?;
+        }
 
-        let window = web_sys::window().ok_or("No global `window` exists")?;
+        let window = web_sys::window().ok_or(client_error("No global `window` exists"))?;
         let resp_value = JsFuture::from(window.fetch_with_request(&request))
             .await
-            .map_err(|e| e.as_string().unwrap_or("Fetch failed".into()))?;
+            .map_err(|e| client_error(e.as_string().unwrap_or("Fetch failed".into())))?;
 
         let resp: Response = resp_value
             .dyn_into()
-            .map_err(|_| "Failed to cast to Response".to_string())?;
+            .map_err(|_| client_error("Failed to cast to Response"))?;
         if resp.ok() {
-            Ok(())
+            Ok(resp)
         } else {
-            Err(format!("HTTP error: {}", resp.status()))
+            Err(error_from_response(&resp).await)
         }
     }
 }
+
+async fn login_response(resp: Response) -> Result<LoginResponse, ApiError> {
+    let text_js = JsFuture::from(resp.text().map_err(|_| client_error("Failed to get text"))?)
+        .await
+        .map_err(|_| client_error("Failed to await text"))?;
+    let text = text_js.as_string().ok_or(client_error("Response text is not a string"))?;
+
+    serde_json::from_str(&text).map_err(|e| client_error(e.to_string()))
+}
+
+/// Reads the service's JSON error body, falling back to the status for anything else.
+async fn error_from_response(resp: &Response) -> ApiError {
+    let fallback = client_error(format!("HTTP error: {}", resp.status()));
+    let Ok(text) = resp.text() else {
+        return fallback;
+    };
+
+    match JsFuture::from(text).await.ok().and_then(|text| text.as_string()) {
+        Some(text) => serde_json::from_str(&text).unwrap_or(fallback),
+        None => fallback,
+    }
+}
+
+/// A failure on our side of the request, which never got an answer from the service.
+fn client_error(message: impl Into<String>) -> ApiError {
+    ApiError::new(ErrorCode::Unknown, message)
+}
@@ -2,6 +2,7 @@ use leptos::*;
 use leptos::html::Div;
 use web_sys::{console, KeyboardEvent};
 use crate::application::websocket_service::WebSocketService;
+use shared::api::game::{ClientMessage, ServerMessage};
 use std::collections::HashMap;
 
 const GRID_SIZE: i32 = 10;
@@ -42,53 +43,66 @@ pub fn GamePage(websocket_service: WebSocketService, username: String) -> impl I
 
         if dx != 0 || dy != 0 {
             // Send the movement command to the server
-            let message = serde_json::json!({
-                "action": "move",
-                "dx": dx,
-                "dy": dy,
-            });
-            if let Err(err) = ws_service_clone.send(&message.to_string()) {
-                console::error_1(&format!("Failed to send message: {}", err).into());
+            let message = ClientMessage::Move { dx, dy };
+            match serde_json::to_string(&message) {
+                Ok(text) => {
+                    if let Err(err) = ws_service_clone.send(&text) {
+                        console::error_1(&format!("Failed to send message: {}", err).into());
+                    }
+                }
+                Err(err) => {
+                    console::error_1(&format!("Failed to serialize message: {}", err).into());
+                }
             }
         }
     };
 
     // Handle incoming messages from the server
-    let player_x_clone = player_x.clone();
-    let player_y_clone = player_y.clone();
-    let other_players_clone = other_players.clone();
+    let player_x_clone = player_x;
+    let player_y_clone = player_y;
+    let other_players_clone = other_players;
     let username_clone = username.clone();
 
     // Set the on_message handler
     websocket_service.set_on_message(move |message| {
         console::log_1(&format!("Received message from server: {}", message).into());
         // Parse the message and prepare updates
-        if let Ok(positions) = serde_json::from_str::<Vec<(String, i32, i32)>>(&message) {
-            let mut new_other_players = HashMap::new();
-            let mut new_player_x = None;
-            let mut new_player_y = None;
-
-            for (player_username, x, y) in positions {
-                if player_username == username_clone {
-                    new_player_x = Some(x);
-                    new_player_y = Some(y);
-                } else {
-                    new_other_players.insert(player_username, (x, y));
+        match serde_json::from_str::<ServerMessage>(&message) {
+            Ok(ServerMessage::Snapshot { players }) => {
+                let mut new_other_players = HashMap::new();
+                let mut new_player_x = None;
+                let mut new_player_y = None;
+
+                for player in players {
+                    if player.username == username_clone {
+                        new_player_x = Some(player.x);
+                        new_player_y = Some(player.y);
+                    } else {
+                        new_other_players.insert(player.username, (player.x, player.y));
+                    }
                 }
-            }
 
-            // Update player position first
-            if let Some(x) = new_player_x {
-                player_x_clone.set(x);
+                // Update player position first
+                if let Some(x) = new_player_x {
+                    player_x_clone.set(x);
+                }
+                if let Some(y) = new_player_y {
+                    player_y_clone.set(y);
+                }
+
+                // Then update other players
+                other_players_clone.set(new_other_players);
             }
-            if let Some(y) = new_player_y {
-                player_y_clone.set(y);
+            Ok(ServerMessage::Chat { from, text }) => {
+                console::log_1(&format!("{}: {}", from, text).into());
+            }
+            Ok(ServerMessage::Error { message }) => {
+                console::error_1(&format!("Server error: {}", message).into());
+            }
+            Ok(_) => (),
+            Err(err) => {
+                console::error_1(&format!("Failed to parse message from server: {}", err).into());
             }
-
-            // Then update other players
-            other_players_clone.set(new_other_players);
-        } else {
-            console::error_1(&"Failed to parse positions from server".into());
         }
     });
 
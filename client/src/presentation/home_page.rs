@@ -1,6 +1,7 @@
 use leptos::*;
 use leptos::html::Div;
 use crate::presentation::login::LoginPage;
+use crate::presentation::password_reset::PasswordResetPage;
 use crate::presentation::register::RegisterPage;
 use crate::presentation::game::GamePage;
 use crate::application::auth_service::AuthService;
@@ -8,10 +9,29 @@ use crate::application::websocket_service::WebSocketService;
 use crate::domain::models::User;
 use web_sys::console;
 
+/// A parameter of the page's query string, which is how mailed links pass their token.
+fn query_parameter(name: &str) -> Option<String> {
+    let search = web_sys::window()?.location().search().ok()?;
+    web_sys::UrlSearchParams::new_with_str(&search).ok()?.get(name)
+}
+
 #[component]
 pub fn HomePage(auth_service: AuthService) -> impl IntoView {
-    let active_tab = create_rw_signal("login".to_string());
+    let reset_token = query_parameter("reset_password");
+    let active_tab = create_rw_signal(if reset_token.is_some() { "reset" } else { "login" }.to_string());
     let user = create_rw_signal(None::<User>);
+    let notice = create_rw_signal(None::<String>);
+
+    // Opened from the link in a verification mail
+    if let Some(token) = query_parameter("verify_email") {
+        let auth_service = auth_service.clone();
+        spawn_local(async move {
+            notice.set(Some(match auth_service.verify_email(token).await {
+                Ok(()) => "Your email is confirmed".to_string(),
+                Err(message) => message,
+            }));
+        });
+    }
 
     let websocket_service = create_rw_signal(None::<WebSocketService>);
 
@@ -28,10 +48,7 @@ pub fn HomePage(auth_service: AuthService) -> impl IntoView {
 
     // Establish WebSocket connection when the user logs in
     create_effect(move |_| {
-        if let Some(user_data) = user.get() {
-            // Log the token to get rid of the warning
-            console::log_1(&format!("User token: {:?}", user_data.token).into());
-
+        if user.get().is_some() {
             // User is logged in, connect to WebSocket
             let ws_url = "ws://innershelter.org:8081/ws";
             match WebSocketService::connect(ws_url) {
@@ -60,20 +77,35 @@ pub fn HomePage(auth_service: AuthService) -> impl IntoView {
 
     let select_login = move |_| active_tab.set("login".to_string());
     let select_create_account = move |_| active_tab.set("create".to_string());
+    let select_reset = move |_| active_tab.set("reset".to_string());
 
     let auth_service_clone = auth_service.clone();
-    let user_signal = user.clone();
-    let active_tab_signal = active_tab.clone();
+    let on_logout = move |_| {
+        // Leave the game right away, the server would drop the connection soon anyway
+        if let Some(ws_service) = websocket_service.get_untracked() {
+            ws_service.close();
+        }
+        websocket_service.set(None);
+
+        let auth_service = auth_service.clone();
+        spawn_local(async move {
+            auth_service.logout(user).await;
+        });
+    };
+    let user_signal = user;
+    let active_tab_signal = active_tab;
 
     view! {
         <div node_ref=container_ref tabindex="0">
             <h1>"Welcome to Inner Shelter"</h1>
+            {move || notice.get().map(|message| view! { <p class="notice">{message}</p> })}
             {move || if user.get().is_some() {
                 if let Some(ws_service) = websocket_service.get() {
                     // User is logged in, display the game page
                     view! {
                         <div>
                             <p>{format!("Logged in as {}", user.get().unwrap().username)}</p>
+                            <button on:click=on_logout.clone()>"Logout"</button>
                             <GamePage websocket_service=ws_service.clone() username=user.get().unwrap().username.clone() />
                         </div>
                     }.into_view()
@@ -100,13 +132,20 @@ pub fn HomePage(auth_service: AuthService) -> impl IntoView {
                             >
                                 "Create Account"
                             </button>
+                            <button
+                                class=move || if active_tab_signal.get() == "reset" { "active" } else { "" }
+                                on:click=select_reset
+                            >
+                                "Forgot Password"
+                            </button>
                         </div>
 
                         <div class="tab-content">
                             {match active_tab_signal.get().as_str() {
-                                "login" => view! { <LoginPage auth_service=auth_service_clone.clone() user_signal=user_signal.clone() /> }.into_view(),
+                                "login" => view! { <LoginPage auth_service=auth_service_clone.clone() user_signal=user_signal /> }.into_view(),
                                 "create" => view! { <RegisterPage auth_service=auth_service_clone.clone() /> }.into_view(),
-                                _ => view! { <LoginPage auth_service=auth_service_clone.clone() user_signal=user_signal.clone() /> }.into_view(),
+                                "reset" => view! { <PasswordResetPage auth_service=auth_service_clone.clone() token=reset_token.clone() /> }.into_view(),
+                                _ => view! { <LoginPage auth_service=auth_service_clone.clone() user_signal=user_signal /> }.into_view(),
                             }}
                         </div>
                     </div>
@@ -1,20 +1,42 @@
 use leptos::*;
+use shared::api::error::{ApiError, ErrorCode};
 use crate::application::auth_service::AuthService;
+use crate::domain::errors::user_message;
 use crate::domain::models::User;
 
 #[component]
 pub fn LoginPage(auth_service: AuthService, user_signal: RwSignal<Option<User>>) -> impl IntoView {
     let username = create_rw_signal(String::new());
     let password = create_rw_signal(String::new());
+    let new_password = create_rw_signal(String::new());
+    let code = create_rw_signal(String::new());
+    let error = create_rw_signal(None::<ApiError>);
+    // Once the service asked for a new password it stays asked for
+    let reset_required = create_rw_signal(false);
+    // The challenge token once the password was right but a second factor is needed
+    let mfa_token = create_rw_signal(None::<String>);
 
     let on_login = move |_| {
         let username = username.get().clone();
         let password = password.get().clone();
+        let new_password = reset_required.get().then(|| new_password.get().clone());
         let auth_service = auth_service.clone();
-        let user_signal = user_signal.clone();
 
         spawn_local(async move {
-            auth_service.login(username, password, user_signal).await;
+            let result = match mfa_token.get_untracked() {
+                Some(token) => auth_service.login_mfa(&username, token, code.get_untracked(), new_password, user_signal).await,
+                None => auth_service.login(username, password, new_password, user_signal).await,
+            };
+            if let Err(err) = &result {
+                match err.code {
+                    ErrorCode::PasswordResetRequired => reset_required.set(true),
+                    ErrorCode::MfaRequired => mfa_token.set(err.mfa_challenge.as_ref().map(|challenge| challenge.token.clone())),
+                    // The challenge ran out or the account changed, start over with the password
+                    ErrorCode::Unauthorized => mfa_token.set(None),
+                    _ => {}
+                }
+            }
+            error.set(result.err());
         });
     };
 
@@ -31,7 +53,23 @@ pub fn LoginPage(auth_service: AuthService, user_signal: RwSignal<Option<User>>)
                 placeholder="Password"
                 on:input=move |e| password.set(event_target_value(&e))
             />
+            <Show when=move || mfa_token.get().is_some()>
+                <input
+                    type="text"
+                    placeholder="Authenticator or recovery code"
+                    autocomplete="one-time-code"
+                    on:input=move |e| code.set(event_target_value(&e))
+                />
+            </Show>
+            <Show when=move || reset_required.get()>
+                <input
+                    type="password"
+                    placeholder="New password"
+                    on:input=move |e| new_password.set(event_target_value(&e))
+                />
+            </Show>
             <button on:click=on_login>"Login"</button>
+            {move || error.get().map(|err| view! { <p class="error">{user_message(&err)}</p> })}
         </div>
     }
 }
@@ -1,4 +1,5 @@
 pub mod home_page;
 pub mod login;
+pub mod password_reset;
 pub mod register;
 pub mod game;
\ No newline at end of file
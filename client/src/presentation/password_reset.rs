@@ -0,0 +1,64 @@
+use leptos::*;
+use crate::application::auth_service::AuthService;
+
+/// Asks for a reset link, or with the token from such a link sets the new password.
+#[component]
+pub fn PasswordResetPage(auth_service: AuthService, token: Option<String>) -> impl IntoView {
+    let username = create_rw_signal(String::new());
+    let new_password = create_rw_signal(String::new());
+    let notice = create_rw_signal(None::<String>);
+    let error = create_rw_signal(None::<String>);
+    let has_token = token.is_some();
+
+    let on_submit = move |_| {
+        let auth_service = auth_service.clone();
+        let token = token.clone();
+        let username = username.get().clone();
+        let new_password = new_password.get().clone();
+
+        spawn_local(async move {
+            let result = match token {
+                Some(token) => auth_service
+                    .reset_password(token, new_password)
+                    .await
+                    .map(|_| "Your password was changed, you can log in with it now"),
+                None => auth_service
+                    .request_password_reset(username)
+                    .await
+                    .map(|_| "If your account has a verified email, a reset link is on its way"),
+            };
+            match result {
+                Ok(message) => {
+                    notice.set(Some(message.to_string()));
+                    error.set(None);
+                }
+                Err(message) => error.set(Some(message)),
+            }
+        });
+    };
+
+    view! {
+        <div>
+            <h2>"Reset Password"</h2>
+            <Show
+                when=move || has_token
+                fallback=move || view! {
+                    <input
+                        type="text"
+                        placeholder="Username"
+                        on:input=move |e| username.set(event_target_value(&e))
+                    />
+                }
+            >
+                <input
+                    type="password"
+                    placeholder="New password"
+                    on:input=move |e| new_password.set(event_target_value(&e))
+                />
+            </Show>
+            <button on:click=on_submit>{if has_token { "Set New Password" } else { "Send Reset Link" }}</button>
+            {move || notice.get().map(|message| view! { <p class="notice">{message}</p> })}
+            {move || error.get().map(|message| view! { <p class="error">{message}</p> })}
+        </div>
+    }
+}
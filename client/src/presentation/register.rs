@@ -5,14 +5,17 @@ use crate::application::auth_service::AuthService;
 pub fn RegisterPage(auth_service: AuthService) -> impl IntoView {
     let username = create_rw_signal(String::new());
     let password = create_rw_signal(String::new());
+    let email = create_rw_signal(String::new());
+    let error = create_rw_signal(None::<String>);
 
     let on_register = move |_| {
         let username = username.get().clone();
         let password = password.get().clone();
+        let email = Some(email.get().trim().to_string()).filter(|email| !email.is_empty());
         let auth_service = auth_service.clone();
 
         spawn_local(async move {
-            auth_service.register(username, password).await;
+            error.set(auth_service.register(username, password, email).await.err());
         });
     };
 
@@ -29,7 +32,13 @@ pub fn RegisterPage(auth_service: AuthService) -> impl IntoView {
                 placeholder="Password"
                 on:input=move |e| password.set(event_target_value(&e))
             />
+            <input
+                type="email"
+                placeholder="Email (optional, to reset a forgotten password)"
+                on:input=move |e| email.set(event_target_value(&e))
+            />
             <button on:click=on_register>"Create Account"</button>
+            {move || error.get().map(|message| view! { <p class="error">{message}</p> })}
         </div>
     }
 }
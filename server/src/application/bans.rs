@@ -0,0 +1,60 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use shared::api::admin::BanUpdate;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::game::GameState;
+use crate::infrastructure::authentication::has_internal_token;
+use crate::infrastructure::bans::{fetch_bans, BanList};
+
+/// Spawns the task that keeps the ban list in sync with the auth service, catching bans
+/// whose push this server missed.
+pub fn spawn_ban_sync(
+    bans: Arc<RwLock<BanList>>,
+    game_state: Arc<Mutex<GameState>>,
+    auth_service_url: String,
+    internal_api_token: String,
+    poll_interval: Duration,
+) {
+    actix_rt::spawn(async move {
+        let client = reqwest::Client::new();
+        let mut interval = actix_rt::time::interval(poll_interval);
+
+        loop {
+            interval.tick().await;
+
+            let updates = match fetch_bans(&client, &auth_service_url, &internal_api_token).await {
+                Ok(updates) => updates,
+                Err(e) => {
+                    // Keep the last known list, banned players stay banned
+                    log::error!("Failed to fetch bans: {}", e);
+                    continue;
+                }
+            };
+
+            let mut list = bans.write().unwrap();
+            list.replace(updates);
+            game_state.lock().unwrap().disconnect_banned(&list);
+        }
+    });
+}
+
+/// Receives a ban or unban from the auth service and disconnects the player at once.
+pub async fn push_ban(
+    req: HttpRequest,
+    update: web::Json<BanUpdate>,
+    game_state: web::Data<Arc<Mutex<GameState>>>,
+    bans: web::Data<Arc<RwLock<BanList>>>,
+    config: web::Data<Config>,
+) -> HttpResponse {
+    if !has_internal_token(&req, &config) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let mut list = bans.write().unwrap();
+    list.apply(update.into_inner());
+    game_state.lock().unwrap().disconnect_banned(&list);
+
+    HttpResponse::Ok().finish()
+}
@@ -0,0 +1,32 @@
+use shared::token::VerificationKeys;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::infrastructure::keys::fetch_keys;
+
+/// Spawns the task that keeps the token verification keys in sync with the auth service.
+/// Until the first fetch succeeds no token validates.
+pub fn spawn_key_sync(keys: Arc<RwLock<VerificationKeys>>, auth_service_url: String, poll_interval: Duration) {
+    actix_rt::spawn(async move {
+        let client = reqwest::Client::new();
+        let mut interval = actix_rt::time::interval(poll_interval);
+
+        loop {
+            interval.tick().await;
+
+            let jwks = match fetch_keys(&client, &auth_service_url).await {
+                Ok(jwks) => jwks,
+                Err(e) => {
+                    // Keep the last known keys, the service may just be restarting
+                    log::error!("Failed to fetch token keys: {}", e);
+                    continue;
+                }
+            };
+
+            match VerificationKeys::from_jwks(&jwks) {
+                Ok(fetched) => *keys.write().unwrap() = fetched,
+                Err(e) => log::error!("Invalid token keys: {}", e),
+            }
+        }
+    });
+}
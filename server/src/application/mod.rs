@@ -1 +1,6 @@
+pub mod bans;
+pub mod keys;
+pub mod outbox;
+pub mod revocations;
+pub mod simulation;
 pub mod websocket;
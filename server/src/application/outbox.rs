@@ -0,0 +1,86 @@
+use actix_ws::Session;
+use shared::api::game::ServerMessage;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+
+use crate::game::GameState;
+
+/// What to do with a client whose outbox is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Drop the message that did not fit and keep the client connected.
+    DropMessage,
+    /// Disconnect the client, it is too far behind to catch up.
+    Disconnect,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop" => Ok(OverflowPolicy::DropMessage),
+            "disconnect" => Ok(OverflowPolicy::Disconnect),
+            other => Err(format!("Unknown overflow policy: {}", other)),
+        }
+    }
+}
+
+pub enum SendError {
+    /// The outbox is at capacity, the client is not reading fast enough.
+    Full,
+    /// The writer task has stopped, the connection is gone.
+    Closed,
+}
+
+/// The sending half of a connection's bounded message queue. Sending never waits:
+/// the connection's writer task is the only place that awaits the socket.
+#[derive(Clone)]
+pub struct Outbox {
+    sender: Sender<ServerMessage>,
+}
+
+impl Outbox {
+    pub fn new(capacity: usize) -> (Self, Receiver<ServerMessage>) {
+        let (sender, receiver) = mpsc::channel(capacity);
+        (Self { sender }, receiver)
+    }
+
+    pub fn send(&self, message: ServerMessage) -> Result<(), SendError> {
+        self.sender.try_send(message).map_err(|e| match e {
+            TrySendError::Full(_) => SendError::Full,
+            TrySendError::Closed(_) => SendError::Closed,
+        })
+    }
+}
+
+/// Drains a connection's outbox into its socket until every `Outbox` handle is dropped
+/// or the socket fails, then closes the socket. A failed socket takes the player out of
+/// the game, unless they have reconnected since.
+pub async fn write_outbox(
+    user_id: String,
+    connection_id: u64,
+    mut session: Session,
+    mut receiver: Receiver<ServerMessage>,
+    game_state: Arc<Mutex<GameState>>,
+) {
+    while let Some(message) = receiver.recv().await {
+        let text = match serde_json::to_string(&message) {
+            Ok(text) => text,
+            Err(e) => {
+                log::error!("Error serializing message for {}: {:?}", user_id, e);
+                continue;
+            }
+        };
+
+        if let Err(e) = session.text(text).await {
+            log::error!("Error sending message to {}: {:?}", user_id, e);
+            game_state.lock().unwrap().remove_connection(&user_id, connection_id);
+            return;
+        }
+    }
+
+    // The player was removed from the game, make sure the socket goes with it
+    let _ = session.close(None).await;
+}
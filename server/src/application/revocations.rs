@@ -0,0 +1,112 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use shared::api::auth::RevokedToken;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::game::GameState;
+use crate::infrastructure::authentication::has_internal_token;
+use crate::infrastructure::revocation::{fetch_revocations, RevocationList};
+
+/// Spawns the task that keeps the revocation list in sync with the auth service and
+/// disconnects players whose token was revoked after they connected.
+pub fn spawn_revocation_sync(
+    revocations: Arc<RwLock<RevocationList>>,
+    game_state: Arc<Mutex<GameState>>,
+    auth_service_url: String,
+    poll_interval: Duration,
+) {
+    actix_rt::spawn(async move {
+        let client = reqwest::Client::new();
+        let mut interval = actix_rt::time::interval(poll_interval);
+
+        loop {
+            interval.tick().await;
+
+            let tokens = match fetch_revocations(&client, &auth_service_url).await {
+                Ok(tokens) => tokens,
+                Err(e) => {
+                    // Keep the last known list, tokens do not become valid again
+                    log::error!("Failed to fetch revocations: {}", e);
+                    continue;
+                }
+            };
+
+            let mut list = revocations.write().unwrap();
+            list.replace(tokens);
+            game_state.lock().unwrap().disconnect_revoked(&list);
+        }
+    });
+}
+
+/// Receives a token or session the auth service just revoked and disconnects the players
+/// using it at once, ahead of the next poll.
+pub async fn push_revocation(
+    req: HttpRequest,
+    revocation: web::Json<RevokedToken>,
+    game_state: web::Data<Arc<Mutex<GameState>>>,
+    revocations: web::Data<Arc<RwLock<RevocationList>>>,
+    config: web::Data<Config>,
+) -> HttpResponse {
+    if !has_internal_token(&req, &config) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let mut list = revocations.write().unwrap();
+    list.apply(revocation.into_inner());
+    game_state.lock().unwrap().disconnect_revoked(&list);
+
+    HttpResponse::Ok().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::outbox::{OverflowPolicy, Outbox};
+    use crate::game::PlayerSession;
+    use actix_web::test::TestRequest;
+    use chrono::Utc;
+    use shared::api::admin::INTERNAL_TOKEN_HEADER;
+
+    fn config() -> Config {
+        Config {
+            tick_rate: 20,
+            outbox_capacity: 8,
+            outbox_overflow: OverflowPolicy::DropMessage,
+            auth_service_url: String::new(),
+            revocation_poll_interval: Duration::from_secs(10),
+            key_refresh_interval: Duration::from_secs(60),
+            internal_api_token: "internal".to_string(),
+            ban_poll_interval: Duration::from_secs(60),
+        }
+    }
+
+    #[actix_web::test]
+    async fn pushed_revocations_disconnect_at_once() {
+        let mut state = GameState::new(OverflowPolicy::DropMessage);
+        let (outbox, _receiver) = Outbox::new(8);
+        state.add_player("alice-id".to_string(), PlayerSession {
+            outbox,
+            username: "alice".to_string(),
+            token_id: "token".to_string(),
+            session_id: Some("session".to_string()),
+            connection_id: 0,
+        });
+        let game_state = web::Data::new(Arc::new(Mutex::new(state)));
+        let revocations = web::Data::new(Arc::new(RwLock::new(RevocationList::new())));
+        let revocation = RevokedToken { jti: "session".to_string(), expires_at: Utc::now().timestamp() + 60 };
+        let push = |token: &str| {
+            let req = TestRequest::post().insert_header((INTERNAL_TOKEN_HEADER, token)).to_http_request();
+            push_revocation(req, web::Json(revocation.clone()), game_state.clone(), revocations.clone(), web::Data::new(config()))
+        };
+
+        let resp = push("wrong").await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+        assert!(game_state.lock().unwrap().sessions.contains_key("alice-id"));
+
+        let resp = push("internal").await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+        assert!(game_state.lock().unwrap().sessions.is_empty());
+        assert!(revocations.read().unwrap().revokes("other-token", Some("session")));
+    }
+}
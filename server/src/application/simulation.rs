@@ -0,0 +1,29 @@
+use shared::api::game::ServerMessage;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+
+use crate::game::GameState;
+
+/// Spawns the task that advances the game world at `tick_rate` ticks per second,
+/// independently of when clients send messages.
+pub fn spawn_simulation(game_state: Arc<Mutex<GameState>>, tick_rate: u32) {
+    actix_rt::spawn(async move {
+        let mut interval = actix_rt::time::interval(Duration::from_secs_f64(1.0 / tick_rate as f64));
+        // A slow tick should not be followed by a burst of catch-up ticks
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            interval.tick().await;
+
+            // Advance the world and queue the snapshot for every connected client
+            let mut state = game_state.lock().unwrap();
+            state.tick();
+
+            if !state.sessions.is_empty() {
+                let players = state.get_positions();
+                state.broadcast(ServerMessage::Snapshot { players });
+            }
+        }
+    });
+}
@@ -1,58 +1,102 @@
+use actix_web::http::header;
 use actix_web::{HttpRequest, HttpResponse, Error, web};
 use actix_ws::{Message, Session, MessageStream};
 use futures_util::StreamExt;
-use serde_json::Value;
-use std::sync::{Arc, Mutex};
+use shared::api::game::{ClientMessage, ServerMessage, MAX_CHAT_LENGTH};
+use shared::token::{request_access_token, VerificationKeys, ACCESS_TOKEN_COOKIE};
+use std::sync::{Arc, Mutex, RwLock};
 
-use crate::game::GameState;
+use crate::application::outbox::{write_outbox, Outbox};
+use crate::config::Config;
+use crate::game::{GameState, PlayerSession};
 use crate::infrastructure::authentication::validate_token;
+use crate::infrastructure::bans::BanList;
+use crate::infrastructure::revocation::RevocationList;
+
+/// The access token from the browser's `access_token` cookie, or for native clients
+/// from an `Authorization: Bearer` header.
+fn access_token(req: &HttpRequest) -> Option<String> {
+    let cookie = req.cookie(ACCESS_TOKEN_COOKIE);
+    let authorization = req.headers().get(header::AUTHORIZATION).and_then(|value| value.to_str().ok());
+    request_access_token(cookie.as_ref().map(|cookie| cookie.value()), authorization)
+}
 
 pub async fn ws_handler(
     req: HttpRequest,
     stream: web::Payload,
     game_state: web::Data<Arc<Mutex<GameState>>>,
+    keys: web::Data<Arc<RwLock<VerificationKeys>>>,
+    revocations: web::Data<Arc<RwLock<RevocationList>>>,
+    bans: web::Data<Arc<RwLock<BanList>>>,
+    config: web::Data<Config>,
 ) -> Result<HttpResponse, Error> {
-    // Extract access_token cookie
-    let token = match req.cookie("access_token") {
-        Some(cookie) => cookie.value().to_string(),
+    let token = match access_token(&req) {
+        Some(token) => token,
         None => return Ok(HttpResponse::Unauthorized().finish()),
     };
 
     // Validate the token
-    let username = match validate_token(&token) {
-        Ok(username) => username,
+    let claims = match validate_token(&token, &keys.read().unwrap()) {
+        Ok(claims) => claims,
         Err(_) => return Ok(HttpResponse::Unauthorized().finish()),
     };
 
+    // Refuse tokens revoked by logging out, or whose session was signed out remotely
+    if revocations.read().unwrap().revokes(&claims.jti, claims.sid.as_deref()) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    // Tokens issued before a ban stay valid until they expire
+    if bans.read().unwrap().ban_of(&claims.sub).is_some() {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    // Players are told apart by account id, the username is only shown to others
+    let user_id = claims.sub;
+    let username = claims.username;
+
     let (response, session, msg_stream) = actix_ws::handle(&req, stream)?;
 
     let game_state = game_state.get_ref().clone();
+    let (outbox, receiver) = Outbox::new(config.outbox_capacity);
+
+    // Add player to the game state with their session
+    let connection_id = {
+        let mut state = game_state.lock().unwrap();
+        let connection_id = state.add_player(user_id.clone(), PlayerSession {
+            outbox,
+            username: username.clone(),
+            token_id: claims.jti,
+            session_id: claims.sid,
+            connection_id: 0,
+        });
+
+        // Greet the player so the client knows who it is playing as
+        state.send_to(&user_id, ServerMessage::Welcome { username });
+        connection_id
+    };
+
+    // The writer task owns the socket's sending side, everything else only enqueues
+    actix_rt::spawn(write_outbox(user_id.clone(), connection_id, session.clone(), receiver, game_state.clone()));
 
     // Spawn a task to handle the websocket connection
     actix_rt::spawn(async move {
-        // Add player to the game state with their session
-        {
-            let mut state = game_state.lock().unwrap();
-            state.add_player(username.clone(), session.clone());
-        }
-
         // Handle incoming messages
-        if let Err(e) = ws_session(username.clone(), game_state.clone(), session, msg_stream).await {
+        if let Err(e) = ws_session(&user_id, connection_id, game_state.clone(), session, msg_stream).await {
             log::error!("WebSocket session error: {:?}", e);
         }
 
-        // Remove player from the game state when the connection closes
-        {
-            let mut state = game_state.lock().unwrap();
-            state.remove_player(&username);
-        }
+        // Remove player from the game state when the connection closes, unless a newer
+        // connection has taken over
+        game_state.lock().unwrap().remove_connection(&user_id, connection_id);
     });
 
     Ok(response)
 }
 
 async fn ws_session(
-    username: String,
+    user_id: &str,
+    connection_id: u64,
     game_state: Arc<Mutex<GameState>>,
     session: Session,
     mut msg_stream: MessageStream,
@@ -65,10 +109,11 @@ async fn ws_session(
                 let text_str = text.to_string();
 
                 // Process the message
-                handle_message(&username, text_str, &game_state).await;
+                handle_message(user_id, connection_id, text_str, &game_state);
             }
             Message::Close(reason) => {
-                session.close(reason).await.unwrap();
+                // The writer may already have closed the socket
+                let _ = session.close(reason).await;
                 break;
             }
             _ => (),
@@ -78,28 +123,70 @@ async fn ws_session(
     Ok(())
 }
 
-async fn handle_message(
-    username: &str,
+fn handle_message(
+    user_id: &str,
+    connection_id: u64,
     msg: String,
     game_state: &Arc<Mutex<GameState>>,
 ) {
-    // Process input and update game state
-    let input: Value = serde_json::from_str(&msg).unwrap_or_default();
-
-    {
-        let mut state = game_state.lock().unwrap();
-        state.process_input(username, input);
+    let mut state = game_state.lock().unwrap();
 
-        // Retrieve updated positions
-        let positions = state.get_positions();
-        let positions_json = serde_json::to_string(&positions).unwrap();
+    // Disconnected or replaced connections may still have messages in flight
+    let username = match state.sessions.get(user_id) {
+        Some(session) if session.connection_id == connection_id => session.username.clone(),
+        _ => return,
+    };
 
-        // Broadcast updated positions to all connected clients
-        for (client_username, client_session) in &mut state.sessions {
-            // Send the positions JSON to each client
-            if let Err(e) = client_session.text(positions_json.clone()).await {
-                log::error!("Error sending message to {}: {:?}", client_username, e);
-            }
+    match serde_json::from_str::<ClientMessage>(&msg) {
+        Ok(input @ ClientMessage::Move { .. }) => {
+            // Applied on the next tick, which broadcasts the resulting snapshot
+            state.queue_input(user_id, input);
+        }
+        Ok(ClientMessage::Chat { text }) if text.chars().count() > MAX_CHAT_LENGTH => {
+            let message = format!("Chat messages are limited to {} characters", MAX_CHAT_LENGTH);
+            state.send_to(user_id, ServerMessage::Error { message });
         }
+        Ok(ClientMessage::Chat { text }) => {
+            state.broadcast(ServerMessage::Chat { from: username, text });
+        }
+        Ok(ClientMessage::Ping { nonce }) => {
+            state.send_to(user_id, ServerMessage::Pong { nonce });
+        }
+        Err(e) => {
+            log::warn!("Invalid message from {}: {}", username, e);
+            let message = format!("Invalid message: {}", e);
+            state.send_to(user_id, ServerMessage::Error { message });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::outbox::OverflowPolicy;
+
+    #[test]
+    fn overlong_chat_is_refused() {
+        let mut state = GameState::new(OverflowPolicy::DropMessage);
+        let (outbox, mut alice) = Outbox::new(8);
+        let session = PlayerSession {
+            outbox,
+            username: "alice".to_string(),
+            token_id: "alice-token".to_string(),
+            session_id: None,
+            connection_id: 0,
+        };
+        let connection_id = state.add_player("alice-id".to_string(), session);
+        let game_state = Arc::new(Mutex::new(state));
+        let chat = |text: String| serde_json::to_string(&ClientMessage::Chat { text }).unwrap();
+
+        handle_message("alice-id", connection_id, chat("a".repeat(MAX_CHAT_LENGTH + 1)), &game_state);
+        assert!(matches!(alice.try_recv(), Ok(ServerMessage::Error { .. })));
+        assert!(alice.try_recv().is_err());
+
+        // Counted in characters, not bytes
+        let text = "é".repeat(MAX_CHAT_LENGTH);
+        handle_message("alice-id", connection_id, chat(text.clone()), &game_state);
+        assert_eq!(alice.try_recv().unwrap(), ServerMessage::Chat { from: "alice".to_string(), text });
     }
 }
@@ -0,0 +1,66 @@
+use crate::application::outbox::OverflowPolicy;
+use std::time::Duration;
+
+#[derive(Clone)]
+pub struct Config {
+    pub tick_rate: u32,
+    pub outbox_capacity: usize,
+    pub outbox_overflow: OverflowPolicy,
+    pub auth_service_url: String,
+    pub revocation_poll_interval: Duration,
+    pub key_refresh_interval: Duration,
+    /// Shared with the auth service, which uses it to push bans and to let us poll them.
+    /// Required, without it bans would not be enforced.
+    pub internal_api_token: String,
+    pub ban_poll_interval: Duration,
+}
+
+impl Config {
+    pub fn new() -> Result<Self, String> {
+        let internal_api_token = std::env::var("INTERNAL_API_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty())
+            .ok_or("INTERNAL_API_TOKEN is not set, bans could not be enforced")?;
+
+        Ok(Self {
+            tick_rate: std::env::var("TICK_RATE")
+                .ok()
+                .and_then(|rate| rate.parse().ok())
+                .filter(|rate| *rate > 0)
+                .unwrap_or(20),
+            outbox_capacity: std::env::var("OUTBOX_CAPACITY")
+                .ok()
+                .and_then(|capacity| capacity.parse().ok())
+                .filter(|capacity| *capacity > 0)
+                .unwrap_or(64),
+            outbox_overflow: match std::env::var("OUTBOX_OVERFLOW") {
+                Ok(policy) => policy.parse().unwrap_or_else(|e| {
+                    log::warn!("{}, dropping messages that do not fit instead", e);
+                    OverflowPolicy::DropMessage
+                }),
+                Err(_) => OverflowPolicy::DropMessage,
+            },
+            auth_service_url: std::env::var("AUTH_SERVICE_URL")
+                .unwrap_or("http://127.0.0.1:8080".to_string()),
+            revocation_poll_interval: std::env::var("REVOCATION_POLL_INTERVAL")
+                .ok()
+                .and_then(|secs| secs.parse().ok())
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs)
+                .unwrap_or(Duration::from_secs(10)),
+            key_refresh_interval: std::env::var("KEY_REFRESH_INTERVAL")
+                .ok()
+                .and_then(|secs| secs.parse().ok())
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs)
+                .unwrap_or(Duration::from_secs(60)),
+            internal_api_token,
+            ban_poll_interval: std::env::var("BAN_POLL_INTERVAL")
+                .ok()
+                .and_then(|secs| secs.parse().ok())
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs)
+                .unwrap_or(Duration::from_secs(60)),
+        })
+    }
+}
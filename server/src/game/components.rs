@@ -14,5 +14,8 @@ pub struct Velocity {
 
 #[derive(Component)]
 pub struct Player {
+    /// Account id, the token's `sub`.
+    pub user_id: String,
+    /// Shown to other players, never used to tell players apart.
     pub username: String,
 }
@@ -4,17 +4,40 @@ pub mod systems;
 use bevy_ecs::prelude::*;
 use components::*;
 use systems::*;
-use actix_ws::Session;
-use std::collections::HashMap;
+use crate::application::outbox::{Outbox, OverflowPolicy, SendError};
+use crate::infrastructure::bans::BanList;
+use crate::infrastructure::revocation::RevocationList;
+use shared::api::game::{ClientMessage, PlayerState, ServerMessage};
+use std::collections::{HashMap, VecDeque};
+
+/// Most inputs one player may have waiting for the next tick. A client sending faster
+/// than the tick rate loses its oldest ones, which only ever costs itself.
+const MAX_QUEUED_INPUTS: usize = 8;
+
+/// A connected player's way back to their client and the token they connected with.
+pub struct PlayerSession {
+    pub outbox: Outbox,
+    /// Display name, usernames can change while ids cannot.
+    pub username: String,
+    /// `jti` of the access token used to open the connection.
+    pub token_id: String,
+    /// `sid` of that token, the login session it belongs to.
+    pub session_id: Option<String>,
+    /// Tells this connection apart from earlier ones of the same player, set by `add_player`.
+    pub connection_id: u64,
+}
 
 pub struct GameState {
     pub world: World,
     pub schedule: Schedule,
-    pub sessions: HashMap<String, Session>, // New field to store sessions by username
+    pub sessions: HashMap<String, PlayerSession>, // Sessions of the connected players by user id
+    inputs: HashMap<String, VecDeque<ClientMessage>>, // Inputs waiting for the next tick by user id
+    overflow_policy: OverflowPolicy,
+    next_connection_id: u64,
 }
 
 impl GameState {
-    pub fn new() -> Self {
+    pub fn new(overflow_policy: OverflowPolicy) -> Self {
         let world = World::new();
         let mut schedule = Schedule::default();
 
@@ -25,28 +48,49 @@ impl GameState {
             world,
             schedule,
             sessions: HashMap::new(), // Initialize the sessions HashMap
+            inputs: HashMap::new(),
+            overflow_policy,
+            next_connection_id: 0,
         }
     }
 
-    pub fn add_player(&mut self, username: String, session: Session) {
+    /// Adds a player for a new connection and returns the connection's id. A player who
+    /// connects again replaces their older connection, which is told why and closed.
+    pub fn add_player(&mut self, user_id: String, mut session: PlayerSession) -> u64 {
+        if self.sessions.contains_key(&user_id) {
+            self.disconnect(&user_id, "Connected from elsewhere");
+        }
+
+        self.next_connection_id += 1;
+        session.connection_id = self.next_connection_id;
+
         // Add a new player entity
         self.world.spawn((
-            Player { username: username.clone() },
+            Player { user_id: user_id.clone(), username: session.username.clone() },
             Position { x: 1.0, y: 1.0 },
             Velocity { x: 0.0, y: 0.0 },
         ));
 
         // Store the session
-        self.sessions.insert(username, session);
+        self.sessions.insert(user_id, session);
+        self.next_connection_id
     }
 
-    pub fn remove_player(&mut self, username: &str) {
+    /// Removes the player if they are still on the given connection. A connection that
+    /// was replaced by a newer one must not take the newer one down when it closes.
+    pub fn remove_connection(&mut self, user_id: &str, connection_id: u64) {
+        if self.sessions.get(user_id).is_some_and(|session| session.connection_id == connection_id) {
+            self.remove_player(user_id);
+        }
+    }
+
+    pub fn remove_player(&mut self, user_id: &str) {
         // Remove the player entity
         let entities: Vec<_> = self
             .world
             .query::<(Entity, &Player)>()
             .iter(&self.world)
-            .filter(|(_, player)| player.username == username)
+            .filter(|(_, player)| player.user_id == user_id)
             .map(|(entity, _)| entity)
             .collect();
 
@@ -54,52 +98,288 @@ impl GameState {
             self.world.despawn(entity);
         }
 
-        // Remove the session
-        self.sessions.remove(username);
+        // Remove the session and any input it left behind, dropping the outbox closes the socket
+        self.sessions.remove(user_id);
+        self.inputs.remove(user_id);
+    }
+
+    pub fn queue_input(&mut self, user_id: &str, input: ClientMessage) {
+        let queued = self.inputs.entry(user_id.to_string()).or_default();
+        if queued.len() >= MAX_QUEUED_INPUTS {
+            queued.pop_front();
+        }
+        queued.push_back(input);
+    }
+
+    /// Enqueues a message for one player.
+    pub fn send_to(&mut self, user_id: &str, message: ServerMessage) {
+        let result = match self.sessions.get(user_id) {
+            Some(session) => session.outbox.send(message),
+            None => return,
+        };
+
+        if let Err(e) = result {
+            self.handle_send_error(user_id, e);
+        }
+    }
+
+    /// Enqueues a message for every connected player.
+    pub fn broadcast(&mut self, message: ServerMessage) {
+        let failed: Vec<_> = self
+            .sessions
+            .iter()
+            .filter_map(|(user_id, session)| {
+                session.outbox.send(message.clone()).err().map(|e| (user_id.clone(), e))
+            })
+            .collect();
+
+        for (user_id, e) in failed {
+            self.handle_send_error(&user_id, e);
+        }
+    }
+
+    /// Tells a player why they are being disconnected and removes them from the game.
+    /// Their socket closes once the notice has been written.
+    pub fn disconnect(&mut self, user_id: &str, reason: &str) {
+        self.send_to(user_id, ServerMessage::Disconnected { reason: reason.to_string() });
+        self.remove_player(user_id);
+    }
+
+    /// Disconnects every player whose access token or session has been revoked.
+    pub fn disconnect_revoked(&mut self, revocations: &RevocationList) {
+        let revoked: Vec<_> = self
+            .sessions
+            .iter()
+            .filter(|(_, session)| revocations.revokes(&session.token_id, session.session_id.as_deref()))
+            .map(|(user_id, session)| (user_id.clone(), session.username.clone()))
+            .collect();
+
+        for (user_id, username) in revoked {
+            log::info!("Disconnecting {}, their token was revoked", username);
+            self.disconnect(&user_id, "Session revoked");
+        }
+    }
+
+    /// Disconnects every player who is now banned, telling them why.
+    pub fn disconnect_banned(&mut self, bans: &BanList) {
+        let banned: Vec<_> = self
+            .sessions
+            .iter()
+            .filter_map(|(user_id, session)| {
+                bans.ban_of(user_id).map(|ban| (user_id.clone(), session.username.clone(), format!("Banned: {}", ban.reason)))
+            })
+            .collect();
+
+        for (user_id, username, reason) in banned {
+            log::info!("Disconnecting {}, they were banned", username);
+            self.disconnect(&user_id, &reason);
+        }
+    }
+
+    fn handle_send_error(&mut self, user_id: &str, error: SendError) {
+        match (error, self.overflow_policy) {
+            (SendError::Full, OverflowPolicy::DropMessage) => {
+                log::warn!("Outbox of {} is full, dropping message", user_id);
+            }
+            (SendError::Full, OverflowPolicy::Disconnect) => {
+                log::warn!("Outbox of {} is full, disconnecting", user_id);
+                self.remove_player(user_id);
+            }
+            // The connection is already going away and will remove the player itself
+            (SendError::Closed, _) => (),
+        }
+    }
+
+    /// Advances the simulation by one step: applies every queued input, then runs the systems.
+    pub fn tick(&mut self) {
+        for (user_id, inputs) in std::mem::take(&mut self.inputs) {
+            for input in inputs {
+                self.process_input(&user_id, &input);
+            }
+        }
+
+        // Run systems
+        self.schedule.run(&mut self.world);
     }
 
-    pub fn process_input(&mut self, username: &str, input: serde_json::Value) {
+    fn process_input(&mut self, user_id: &str, input: &ClientMessage) {
         // Update player's position based on input
         let mut query = self.world.query::<(&Player, &mut Position)>();
 
         for (player, mut position) in query.iter_mut(&mut self.world) {
-            if player.username == username {
-                if let Some(action) = input.get("action").and_then(|a| a.as_str()) {
-                    match action {
-                        "move" => {
-                            if let Some(dx) = input.get("dx").and_then(|v| v.as_i64()) {
-                                position.x += dx as f64;
-                            }
-                            if let Some(dy) = input.get("dy").and_then(|v| v.as_i64()) {
-                                position.y += dy as f64;
-                            }
-
-                            // Ensure the position stays within the grid bounds (1 to 10)
-                            position.x = position.x.clamp(1.0, 10.0);
-                            position.y = position.y.clamp(1.0, 10.0);
-                        }
-                        _ => (),
-                    }
+            if player.user_id == user_id {
+                if let ClientMessage::Move { dx, dy } = input {
+                    position.x += *dx as f64;
+                    position.y += *dy as f64;
+
+                    // Ensure the position stays within the grid bounds (1 to 10)
+                    position.x = position.x.clamp(1.0, 10.0);
+                    position.y = position.y.clamp(1.0, 10.0);
                 }
             }
         }
-
-        // Run systems
-        self.schedule.run(&mut self.world);
     }
 
-    pub fn get_positions(&mut self) -> Vec<(String, i32, i32)> {
+    pub fn get_positions(&mut self) -> Vec<PlayerState> {
         // Get positions of all players
         let mut positions = Vec::new();
         let mut query = self.world.query::<(&Player, &Position)>();
 
         for (player, position) in query.iter(&self.world) {
             // Ensure positions are within the grid bounds and are integers
-            let x = position.x.round() as i32;
-            let y = position.y.round() as i32;
-            positions.push((player.username.clone(), x, y));
+            positions.push(PlayerState {
+                username: player.username.clone(),
+                x: position.x.round() as i32,
+                y: position.y.round() as i32,
+            });
         }
 
         positions
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use shared::api::admin::{Ban, BanUpdate};
+    use shared::api::auth::RevokedToken;
+    use tokio::sync::mpsc::Receiver;
+
+    fn connect(state: &mut GameState, username: &str, token_id: &str, session_id: &str) -> Receiver<ServerMessage> {
+        let (outbox, receiver) = Outbox::new(8);
+        let session = PlayerSession {
+            outbox,
+            username: username.to_string(),
+            token_id: token_id.to_string(),
+            session_id: Some(session_id.to_string()),
+            connection_id: 0,
+        };
+        state.add_player(format!("{}-id", username), session);
+        receiver
+    }
+
+    #[test]
+    fn revoked_sessions_are_disconnected() {
+        let mut state = GameState::new(OverflowPolicy::DropMessage);
+        let mut alice = connect(&mut state, "alice", "alice-token", "alice-session");
+        connect(&mut state, "bob", "bob-token", "bob-session");
+
+        // A token from before a refresh, revoked along with its session
+        let mut revocations = RevocationList::new();
+        revocations.apply(RevokedToken { jti: "alice-session".to_string(), expires_at: Utc::now().timestamp() + 60 });
+        state.disconnect_revoked(&revocations);
+
+        assert!(!state.sessions.contains_key("alice-id"));
+        assert!(state.sessions.contains_key("bob-id"));
+        assert_eq!(state.get_positions().len(), 1);
+        assert!(matches!(alice.try_recv(), Ok(ServerMessage::Disconnected { .. })));
+    }
+    #[test]
+    fn banned_players_are_disconnected_with_the_reason() {
+        let mut state = GameState::new(OverflowPolicy::DropMessage);
+        let mut alice = connect(&mut state, "alice", "alice-token", "alice-session");
+        connect(&mut state, "bob", "bob-token", "bob-session");
+
+        let mut bans = BanList::new();
+        bans.apply(BanUpdate {
+            user_id: "alice-id".to_string(),
+            ban: Some(Ban { reason: "cheating".to_string(), banned_at: 0, until: None }),
+        });
+        state.disconnect_banned(&bans);
+
+        assert!(!state.sessions.contains_key("alice-id"));
+        assert!(state.sessions.contains_key("bob-id"));
+        match alice.try_recv() {
+            Ok(ServerMessage::Disconnected { reason }) => assert_eq!(reason, "Banned: cheating"),
+            _ => panic!("alice was not told why"),
+        }
+    }
+
+    #[test]
+    fn players_are_told_apart_by_id_not_name() {
+        let mut state = GameState::new(OverflowPolicy::DropMessage);
+        let mut first = connect(&mut state, "alice", "first-token", "first-session");
+        // Another account that took the name after a rename
+        let (outbox, _second) = Outbox::new(8);
+        state.add_player("second-id".to_string(), PlayerSession {
+            outbox,
+            username: "alice".to_string(),
+            token_id: "second-token".to_string(),
+            session_id: None,
+            connection_id: 0,
+        });
+
+        state.queue_input("alice-id", ClientMessage::Move { dx: 3, dy: 0 });
+        state.tick();
+        state.send_to("alice-id", ServerMessage::Pong { nonce: 1 });
+        state.remove_player("second-id");
+
+        assert_eq!(state.sessions.len(), 1);
+        let positions = state.get_positions();
+        assert_eq!(positions.len(), 1);
+        assert_eq!((positions[0].x, positions[0].y), (4, 1));
+        assert!(matches!(first.try_recv(), Ok(ServerMessage::Pong { nonce: 1 })));
+    }
+
+    #[test]
+    fn reconnecting_replaces_the_old_connection() {
+        let mut state = GameState::new(OverflowPolicy::DropMessage);
+        let mut old = connect(&mut state, "alice", "old-token", "session");
+        let old_connection = state.sessions["alice-id"].connection_id;
+        let mut new = connect(&mut state, "alice", "new-token", "session");
+        let new_connection = state.sessions["alice-id"].connection_id;
+        assert_ne!(old_connection, new_connection);
+
+        // The old client is told, and only one player is left in the world
+        assert!(matches!(old.try_recv(), Ok(ServerMessage::Disconnected { .. })));
+        assert_eq!(state.get_positions().len(), 1);
+
+        // The old socket closing afterwards leaves the new connection alone
+        state.remove_connection("alice-id", old_connection);
+        assert_eq!(state.sessions["alice-id"].token_id, "new-token");
+        state.send_to("alice-id", ServerMessage::Pong { nonce: 1 });
+        assert!(matches!(new.try_recv(), Ok(ServerMessage::Pong { nonce: 1 })));
+
+        state.remove_connection("alice-id", new_connection);
+        assert!(state.sessions.is_empty());
+        assert!(state.get_positions().is_empty());
+    }
+
+    #[test]
+    fn full_outboxes_follow_the_overflow_policy() {
+        let mut state = GameState::new(OverflowPolicy::Disconnect);
+        let _alice = connect(&mut state, "alice", "alice-token", "alice-session");
+        for nonce in 0..8 {
+            state.send_to("alice-id", ServerMessage::Pong { nonce });
+        }
+        assert!(state.sessions.contains_key("alice-id"));
+
+        // The ninth message does not fit in the outbox
+        state.broadcast(ServerMessage::Pong { nonce: 8 });
+        assert!(state.sessions.is_empty());
+    }
+
+    #[test]
+    fn queued_inputs_are_capped_per_player() {
+        let mut state = GameState::new(OverflowPolicy::DropMessage);
+        connect(&mut state, "alice", "alice-token", "alice-session");
+        connect(&mut state, "bob", "bob-token", "bob-session");
+
+        for _ in 0..20 {
+            state.queue_input("alice-id", ClientMessage::Move { dx: 1, dy: 0 });
+        }
+        state.queue_input("bob-id", ClientMessage::Move { dx: 0, dy: 2 });
+        state.tick();
+
+        let mut positions = state.get_positions();
+        positions.sort_by(|a, b| a.username.cmp(&b.username));
+        let moved: Vec<_> = positions.iter().map(|player| (player.x, player.y)).collect();
+        assert_eq!(moved, vec![(1 + MAX_QUEUED_INPUTS as i32, 1), (1, 3)]);
+
+        // Nothing is left over for the next tick
+        state.tick();
+        let alice = state.get_positions().into_iter().find(|player| player.username == "alice").unwrap();
+        assert_eq!(alice.x, 1 + MAX_QUEUED_INPUTS as i32);
+    }
+}
@@ -1,21 +1,18 @@
-use jsonwebtoken::{decode, DecodingKey, Validation, Algorithm};
-use serde::{Deserialize, Serialize};
-use std::env;
+use actix_web::HttpRequest;
+use shared::api::admin::INTERNAL_TOKEN_HEADER;
+use shared::token::{self, Claims, VerificationKeys};
 
-#[derive(Debug, Deserialize, Serialize)]
-struct Claims {
-    sub: String,
-    exp: usize,
-}
+use crate::config::Config;
 
-pub fn validate_token(token: &str) -> Result<String, ()> {
-    let secret = env::var("JWT_SECRET").unwrap_or_else(|_| "my_secret_key".into());
-    let validation = Validation::new(Algorithm::HS256);
-    let token_data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_bytes()),
-        &validation,
-    ).map_err(|_| ())?;
+pub fn validate_token(token: &str, keys: &VerificationKeys) -> Result<Claims, ()> {
+    token::validate(token, keys).map_err(|_| ())
+}
 
-    Ok(token_data.claims.sub)
+/// Whether the request comes from the auth service, i.e. carries the shared internal
+/// token.
+pub fn has_internal_token(req: &HttpRequest, config: &Config) -> bool {
+    let presented = req.headers()
+        .get(INTERNAL_TOKEN_HEADER)
+        .and_then(|value| value.to_str().ok());
+    presented == Some(config.internal_api_token.as_str())
 }
@@ -0,0 +1,87 @@
+use chrono::Utc;
+use shared::api::admin::{Ban, BanUpdate, INTERNAL_TOKEN_HEADER};
+use std::collections::HashMap;
+
+/// The players the auth service has banned, by user id.
+#[derive(Default)]
+pub struct BanList {
+    banned: HashMap<String, Ban>,
+}
+
+impl BanList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The user's ban, unless they have none or it has run out.
+    pub fn ban_of(&self, user_id: &str) -> Option<&Ban> {
+        let now = Utc::now().timestamp();
+        self.banned.get(user_id).filter(|ban| ban.is_active(now))
+    }
+
+    /// Replaces the list with the service's current one.
+    pub fn replace(&mut self, updates: Vec<BanUpdate>) {
+        self.banned = updates
+            .into_iter()
+            .filter_map(|update| Some((update.user_id, update.ban?)))
+            .collect();
+    }
+
+    /// Applies a single ban or unban pushed by the service.
+    pub fn apply(&mut self, update: BanUpdate) {
+        match update.ban {
+            Some(ban) => self.banned.insert(update.user_id, ban),
+            None => self.banned.remove(&update.user_id),
+        };
+    }
+}
+
+/// Fetches every active ban from the auth service.
+pub async fn fetch_bans(
+    client: &reqwest::Client,
+    auth_service_url: &str,
+    internal_api_token: &str,
+) -> Result<Vec<BanUpdate>, reqwest::Error> {
+    client
+        .get(format!("{}/internal/bans", auth_service_url))
+        .header(INTERNAL_TOKEN_HEADER, internal_api_token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(user_id: &str, until: Option<i64>) -> BanUpdate {
+        BanUpdate {
+            user_id: user_id.to_string(),
+            ban: Some(Ban { reason: "cheating".to_string(), banned_at: 0, until }),
+        }
+    }
+
+    #[test]
+    fn bans_run_out() {
+        let now = Utc::now().timestamp();
+        let mut list = BanList::new();
+        list.replace(vec![update("alice", Some(now + 60)), update("bob", Some(now - 1)), update("carol", None)]);
+
+        assert!(list.ban_of("alice").is_some());
+        assert!(list.ban_of("bob").is_none());
+        assert!(list.ban_of("carol").is_some());
+        assert!(list.ban_of("dave").is_none());
+    }
+
+    #[test]
+    fn pushed_updates_ban_and_unban() {
+        let mut list = BanList::new();
+        list.apply(update("alice", None));
+        assert!(list.ban_of("alice").is_some());
+
+        list.apply(BanUpdate { user_id: "alice".to_string(), ban: None });
+        assert!(list.ban_of("alice").is_none());
+    }
+}
@@ -0,0 +1,12 @@
+use shared::token::JwkSet;
+
+/// Fetches the auth service's published token verification keys.
+pub async fn fetch_keys(client: &reqwest::Client, auth_service_url: &str) -> Result<JwkSet, reqwest::Error> {
+    client
+        .get(format!("{}/.well-known/jwks.json", auth_service_url))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
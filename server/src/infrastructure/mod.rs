@@ -1 +1,4 @@
 pub mod authentication;
+pub mod bans;
+pub mod keys;
+pub mod revocation;
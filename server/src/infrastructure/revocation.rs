@@ -0,0 +1,83 @@
+use chrono::Utc;
+use shared::api::auth::RevokedToken;
+use std::collections::HashMap;
+
+/// The access tokens the auth service has revoked, by `jti`, and the sessions whose
+/// tokens all are, by `sid`, with their expiry.
+#[derive(Default)]
+pub struct RevocationList {
+    revoked: HashMap<String, i64>,
+}
+
+impl RevocationList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn is_revoked(&self, token_id: &str) -> bool {
+        self.revoked.contains_key(token_id)
+    }
+
+    /// Whether the token with `token_id`, issued for `session_id`, has been revoked
+    /// itself or along with its session.
+    pub fn revokes(&self, token_id: &str, session_id: Option<&str>) -> bool {
+        self.is_revoked(token_id) || session_id.is_some_and(|session_id| self.is_revoked(session_id))
+    }
+
+    /// Adds a revocation pushed by the auth service.
+    pub fn apply(&mut self, token: RevokedToken) {
+        if token.expires_at > Utc::now().timestamp() {
+            self.revoked.insert(token.jti, token.expires_at);
+        }
+    }
+
+    /// Replaces the list with the service's current one, expired tokens are left out.
+    pub fn replace(&mut self, tokens: Vec<RevokedToken>) {
+        let now = Utc::now().timestamp();
+        self.revoked = tokens
+            .into_iter()
+            .filter(|token| token.expires_at > now)
+            .map(|token| (token.jti, token.expires_at))
+            .collect();
+    }
+}
+
+/// Fetches the current revocation list from the auth service.
+pub async fn fetch_revocations(client: &reqwest::Client, auth_service_url: &str) -> Result<Vec<RevokedToken>, reqwest::Error> {
+    client
+        .get(format!("{}/revocations", auth_service_url))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn revoked(jti: &str, expires_at: i64) -> RevokedToken {
+        RevokedToken { jti: jti.to_string(), expires_at }
+    }
+
+    #[test]
+    fn revokes_tokens_by_id_or_session() {
+        let mut list = RevocationList::new();
+        let later = Utc::now().timestamp() + 60;
+        list.replace(vec![revoked("token", later)]);
+        list.apply(revoked("session", later));
+
+        assert!(list.revokes("token", None));
+        assert!(list.revokes("other-token", Some("session")));
+        assert!(!list.revokes("other-token", Some("other-session")));
+        assert!(!list.revokes("other-token", None));
+    }
+
+    #[test]
+    fn expired_revocations_are_left_out() {
+        let mut list = RevocationList::new();
+        list.apply(revoked("session", Utc::now().timestamp() - 1));
+        assert!(!list.revokes("token", Some("session")));
+    }
+}
@@ -2,26 +2,75 @@ use actix_web::{web, App, HttpServer};
 use actix_web::middleware::Logger;
 
 mod application;
+mod config;
 mod infrastructure;
 mod game;
 
+use application::bans::{push_ban, spawn_ban_sync};
+use application::keys::spawn_key_sync;
+use application::revocations::{push_revocation, spawn_revocation_sync};
+use application::simulation::spawn_simulation;
 use application::websocket::ws_handler;
-use std::sync::{Arc, Mutex};
+use config::Config;
+use infrastructure::bans::BanList;
+use infrastructure::revocation::RevocationList;
+use shared::token::VerificationKeys;
+use std::sync::{Arc, Mutex, RwLock};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Initialize logger
     env_logger::init();
 
+    let config = Config::new().map_err(std::io::Error::other)?;
+
     // Initialize shared game state
-    let game_state = web::Data::new(Arc::new(Mutex::new(game::GameState::new())));
+    let game_state = Arc::new(Mutex::new(game::GameState::new(config.outbox_overflow)));
+
+    // Advance the world at a fixed rate, independently of incoming messages
+    spawn_simulation(game_state.clone(), config.tick_rate);
+
+    // Keep up with tokens revoked by the auth service
+    let revocations = Arc::new(RwLock::new(RevocationList::new()));
+    spawn_revocation_sync(
+        revocations.clone(),
+        game_state.clone(),
+        config.auth_service_url.clone(),
+        config.revocation_poll_interval,
+    );
+
+    // Keep up with bans, which the auth service also pushes as they happen
+    let bans = Arc::new(RwLock::new(BanList::new()));
+    spawn_ban_sync(
+        bans.clone(),
+        game_state.clone(),
+        config.auth_service_url.clone(),
+        config.internal_api_token.clone(),
+        config.ban_poll_interval,
+    );
+
+    // Trust whichever keys the auth service currently publishes, so it can rotate them
+    let keys = Arc::new(RwLock::new(VerificationKeys::default()));
+    spawn_key_sync(keys.clone(), config.auth_service_url.clone(), config.key_refresh_interval);
+
+    let game_state = web::Data::new(game_state);
+    let keys = web::Data::new(keys);
+    let revocations = web::Data::new(revocations);
+    let bans = web::Data::new(bans);
+    let config = web::Data::new(config);
 
     HttpServer::new(move || {
         App::new()
             .wrap(Logger::default())
             .app_data(game_state.clone())
+            .app_data(keys.clone())
+            .app_data(revocations.clone())
+            .app_data(bans.clone())
+            .app_data(config.clone())
             .wrap(actix_cors::Cors::default().allow_any_origin())
             .route("/ws", web::get().to(ws_handler))
+            .route("/internal/bans", web::post().to(push_ban))
+            .route("/internal/revocations", web::post().to(push_revocation))
     })
     .bind(("127.0.0.1", 8081))?
     .run()
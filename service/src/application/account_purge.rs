@@ -0,0 +1,42 @@
+use chrono::Utc;
+use crate::domain::user_repository::UserRepository;
+use crate::errors::AppError;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How many users are looked at per page while purging.
+const PAGE_SIZE: usize = 100;
+
+/// Removes every account whose deletion grace period is over as of `now`, which frees
+/// their usernames. Returns how many were removed.
+pub async fn purge_deleted_accounts(users: &dyn UserRepository, now: i64) -> Result<usize, AppError> {
+    let mut purged = 0;
+    let mut after = None;
+    loop {
+        let page = users.list_users(after.as_deref(), PAGE_SIZE).await?;
+        for user in page.iter().filter(|user| user.is_deleted(now)) {
+            users.delete_user(user.id).await?;
+            purged += 1;
+        }
+
+        match page.last() {
+            Some(last) if page.len() == PAGE_SIZE => after = Some(last.username.clone()),
+            _ => return Ok(purged),
+        }
+    }
+}
+
+/// Spawns the task that purges deleted accounts every `interval`.
+pub fn spawn_account_purge(users: Arc<dyn UserRepository>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            match purge_deleted_accounts(users.as_ref(), Utc::now().timestamp()).await {
+                Ok(0) => (),
+                Ok(purged) => log::info!("Purged {} deleted accounts", purged),
+                Err(e) => log::error!("Purging deleted accounts failed: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,278 @@
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+use chrono::Utc;
+use shared::api::admin::{
+    Ban, BanData, BanUpdate, ListUsersQuery, SetRolesData, UserPage, UserSummary, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE,
+};
+use shared::api::auth::Role;
+use crate::application::authenticated::{Admin, Authenticated, Moderator};
+use crate::application::session;
+use crate::config::Config;
+use crate::domain::refresh_token_repository::RefreshTokenRepository;
+use crate::domain::revoked_token_repository::RevokedTokenRepository;
+use crate::domain::user_repository::{User, UserRepository};
+use crate::errors::AppError;
+use crate::infrastructure::game_servers::GameServers;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Staff endpoints, mounted under `/admin`. Moderators manage players, only admins
+/// manage roles, and nobody can act on themselves or on staff at their own level.
+pub fn scope() -> actix_web::Scope {
+    web::scope("/admin")
+        .service(list_users)
+        .service(get_user)
+        .service(ban)
+        .service(unban)
+        .service(disable)
+        .service(enable)
+        .service(require_password_reset)
+        .service(revoke_sessions)
+        .service(set_roles)
+}
+
+#[get("/users")]
+pub async fn list_users(
+    _staff: Moderator,
+    query: web::Query<ListUsersQuery>,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+) -> Result<impl Responder, AppError> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let users = user_repo.list_users(query.after.as_deref(), limit).await?;
+
+    // A full page may have more behind it, a short one is the last
+    let next = (users.len() == limit)
+        .then(|| users.last().map(|user| user.username.clone()))
+        .flatten();
+    Ok(HttpResponse::Ok().json(UserPage {
+        users: users.into_iter().map(to_summary).collect(),
+        next,
+    }))
+}
+
+#[get("/users/{id}")]
+pub async fn get_user(
+    _staff: Moderator,
+    id: web::Path<Uuid>,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+) -> Result<impl Responder, AppError> {
+    let user = find_target(*id, user_repo.get_ref().as_ref()).await?;
+    Ok(HttpResponse::Ok().json(to_summary(user)))
+}
+
+/// Bans the user, for the given duration or for good, replacing any earlier ban. Ends
+/// their sessions and has the game servers drop them right away.
+#[post("/users/{id}/ban")]
+#[allow(clippy::too_many_arguments)]
+pub async fn ban(
+    Moderator(staff): Moderator,
+    id: web::Path<Uuid>,
+    ban_data: web::Json<BanData>,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+    refresh_token_repo: web::Data<Arc<dyn RefreshTokenRepository>>,
+    revoked_token_repo: web::Data<Arc<dyn RevokedTokenRepository>>,
+    game_servers: web::Data<GameServers>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, AppError> {
+    ban_data.validate().map_err(AppError::ValidationError)?;
+
+    let mut user = find_target(*id, user_repo.get_ref().as_ref()).await?;
+    authorize_over(&staff, &user)?;
+
+    let now = Utc::now().timestamp();
+    let ban = Ban {
+        reason: ban_data.reason.trim().to_string(),
+        banned_at: now,
+        until: ban_data.duration.map(|duration| now + duration),
+    };
+    user_repo.set_ban(user.id, Some(ban.clone())).await?;
+    session::end_user_sessions(
+        user.id,
+        None,
+        refresh_token_repo.get_ref().as_ref(),
+        revoked_token_repo.get_ref().as_ref(),
+        &game_servers,
+        &config,
+    ).await?;
+    game_servers.push_ban(BanUpdate { user_id: user.id.to_string(), ban: Some(ban.clone()) });
+    log::info!("{} banned {} until {:?}: {}", staff.claims.username, user.username, ban.until, ban.reason);
+
+    user.ban = Some(ban);
+    Ok(HttpResponse::Ok().json(to_summary(user)))
+}
+
+#[post("/users/{id}/unban")]
+pub async fn unban(
+    Moderator(staff): Moderator,
+    id: web::Path<Uuid>,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+    game_servers: web::Data<GameServers>,
+) -> Result<impl Responder, AppError> {
+    let mut user = find_target(*id, user_repo.get_ref().as_ref()).await?;
+    authorize_over(&staff, &user)?;
+
+    user_repo.set_ban(user.id, None).await?;
+    game_servers.push_ban(BanUpdate { user_id: user.id.to_string(), ban: None });
+    log::info!("{} unbanned {}", staff.claims.username, user.username);
+
+    user.ban = None;
+    Ok(HttpResponse::Ok().json(to_summary(user)))
+}
+
+/// Disables the account until it is enabled again, ending its sessions. Unlike a ban it
+/// comes without a reason for the player.
+#[post("/users/{id}/disable")]
+pub async fn disable(
+    Moderator(staff): Moderator,
+    id: web::Path<Uuid>,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+    refresh_token_repo: web::Data<Arc<dyn RefreshTokenRepository>>,
+    revoked_token_repo: web::Data<Arc<dyn RevokedTokenRepository>>,
+    game_servers: web::Data<GameServers>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, AppError> {
+    let mut user = find_target(*id, user_repo.get_ref().as_ref()).await?;
+    authorize_over(&staff, &user)?;
+
+    user_repo.set_disabled(user.id, true).await?;
+    session::end_user_sessions(
+        user.id,
+        None,
+        refresh_token_repo.get_ref().as_ref(),
+        revoked_token_repo.get_ref().as_ref(),
+        &game_servers,
+        &config,
+    ).await?;
+    log::info!("{} disabled {}", staff.claims.username, user.username);
+
+    user.disabled = true;
+    Ok(HttpResponse::Ok().json(to_summary(user)))
+}
+
+#[post("/users/{id}/enable")]
+pub async fn enable(
+    Moderator(staff): Moderator,
+    id: web::Path<Uuid>,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+) -> Result<impl Responder, AppError> {
+    let mut user = find_target(*id, user_repo.get_ref().as_ref()).await?;
+    authorize_over(&staff, &user)?;
+
+    user_repo.set_disabled(user.id, false).await?;
+    log::info!("{} enabled {}", staff.claims.username, user.username);
+
+    user.disabled = false;
+    Ok(HttpResponse::Ok().json(to_summary(user)))
+}
+
+/// Makes the user choose a new password at their next login, ending their sessions so
+/// nobody holding the old one stays logged in.
+#[post("/users/{id}/password-reset")]
+pub async fn require_password_reset(
+    Moderator(staff): Moderator,
+    id: web::Path<Uuid>,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+    refresh_token_repo: web::Data<Arc<dyn RefreshTokenRepository>>,
+    revoked_token_repo: web::Data<Arc<dyn RevokedTokenRepository>>,
+    game_servers: web::Data<GameServers>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, AppError> {
+    let mut user = find_target(*id, user_repo.get_ref().as_ref()).await?;
+    authorize_over(&staff, &user)?;
+
+    user_repo.require_password_reset(user.id).await?;
+    session::end_user_sessions(
+        user.id,
+        None,
+        refresh_token_repo.get_ref().as_ref(),
+        revoked_token_repo.get_ref().as_ref(),
+        &game_servers,
+        &config,
+    ).await?;
+    log::info!("{} required a password reset of {}", staff.claims.username, user.username);
+
+    user.password_reset_required = true;
+    Ok(HttpResponse::Ok().json(to_summary(user)))
+}
+
+/// Ends every session of the user, including the access tokens already handed out and
+/// their game connections.
+#[delete("/users/{id}/sessions")]
+pub async fn revoke_sessions(
+    Moderator(staff): Moderator,
+    id: web::Path<Uuid>,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+    refresh_token_repo: web::Data<Arc<dyn RefreshTokenRepository>>,
+    revoked_token_repo: web::Data<Arc<dyn RevokedTokenRepository>>,
+    game_servers: web::Data<GameServers>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, AppError> {
+    let user = find_target(*id, user_repo.get_ref().as_ref()).await?;
+    authorize_over(&staff, &user)?;
+
+    session::end_user_sessions(
+        user.id,
+        None,
+        refresh_token_repo.get_ref().as_ref(),
+        revoked_token_repo.get_ref().as_ref(),
+        &game_servers,
+        &config,
+    ).await?;
+    log::info!("{} revoked the sessions of {}", staff.claims.username, user.username);
+
+    Ok(HttpResponse::Ok().body("Sessions revoked"))
+}
+
+/// Replaces the user's roles. The staff API goes by them at once, access tokens carry
+/// them once they are next renewed.
+#[put("/users/{id}/roles")]
+pub async fn set_roles(
+    Admin(admin): Admin,
+    id: web::Path<Uuid>,
+    roles_data: web::Json<SetRolesData>,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+) -> Result<impl Responder, AppError> {
+    let mut user = find_target(*id, user_repo.get_ref().as_ref()).await?;
+    authorize_over(&admin, &user)?;
+
+    let mut roles: Vec<Role> = roles_data.roles.clone();
+    roles.sort();
+    roles.dedup();
+    user.roles = roles.iter().map(|role| role.as_str().to_string()).collect();
+    user_repo.set_roles(user.id, user.roles.clone()).await?;
+    log::info!("{} set the roles of {} to {:?}", admin.claims.username, user.username, user.roles);
+
+    Ok(HttpResponse::Ok().json(to_summary(user)))
+}
+
+async fn find_target(id: Uuid, user_repo: &dyn UserRepository) -> Result<User, AppError> {
+    user_repo.find_user_by_id(id).await?
+        .ok_or_else(|| AppError::NotFoundError(format!("User {}", id)))
+}
+
+/// Staff may act on accounts below their own role, admins on anyone but themselves.
+fn authorize_over(staff: &Authenticated, user: &User) -> Result<(), AppError> {
+    if staff.user_id == user.id {
+        return Err(AppError::ForbiddenError(format!("{} tried to act on themselves", staff.claims.username)));
+    }
+    let staff_role = staff.role();
+    if staff_role == Some(Role::Admin) || staff_role > Role::highest(&user.roles) {
+        Ok(())
+    } else {
+        Err(AppError::ForbiddenError(format!("{} outranks {}", user.username, staff.claims.username)))
+    }
+}
+
+fn to_summary(user: User) -> UserSummary {
+    let active_ban = user.active_ban(Utc::now().timestamp()).cloned();
+    UserSummary {
+        id: user.id.to_string(),
+        username: user.username,
+        display_name: user.display_name,
+        roles: user.roles,
+        created_at: user.created_at,
+        last_login_at: user.last_login_at,
+        disabled: user.disabled,
+        password_reset_required: user.password_reset_required,
+        ban: active_ban,
+    }
+}
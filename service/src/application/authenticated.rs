@@ -0,0 +1,145 @@
+use actix_web::dev::Payload;
+use actix_web::{web, FromRequest, HttpRequest};
+use chrono::Utc;
+use futures_util::future::{ready, LocalBoxFuture, Ready};
+use shared::api::admin::INTERNAL_TOKEN_HEADER;
+use shared::api::auth::Role;
+use shared::token::Claims;
+use crate::application::session;
+use crate::config::Config;
+use crate::domain::revoked_token_repository::RevokedTokenRepository;
+use crate::domain::user_repository::UserRepository;
+use crate::errors::AppError;
+use crate::infrastructure::authentication;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// The caller of an endpoint that needs a session, extracted from a valid, unrevoked
+/// access token. Handlers taking it answer 401 to everybody else.
+pub struct Authenticated {
+    pub user_id: Uuid,
+    pub claims: Claims,
+    /// The roles the token grants, as of when it was issued. The staff extractors replace
+    /// them with the account's current ones.
+    pub roles: Vec<String>,
+}
+
+impl Authenticated {
+    /// The most powerful role of the caller, see `roles`.
+    pub fn role(&self) -> Option<Role> {
+        Role::highest(&self.roles)
+    }
+
+    /// Refuses callers without `role` or a more powerful one.
+    pub fn require(&self, role: Role) -> Result<(), AppError> {
+        if self.role() >= Some(role) {
+            Ok(())
+        } else {
+            Err(AppError::ForbiddenError(format!("{} is not a {}", self.claims.username, role)))
+        }
+    }
+
+    /// Validates the request's access token, the shared part of every extractor here.
+    async fn extract(req: HttpRequest) -> Result<Self, AppError> {
+        let config = req.app_data::<web::Data<Config>>()
+            .ok_or_else(|| AppError::ConfigError("Config is not registered".into()))?;
+        let revoked_token_repo = req.app_data::<web::Data<Arc<dyn RevokedTokenRepository>>>()
+            .ok_or_else(|| AppError::ConfigError("Revoked token repository is not registered".into()))?;
+
+        let token = session::access_token(&req)
+            .ok_or_else(|| AppError::AuthError("Missing access token".into()))?;
+        let claims = authentication::decode_jwt(&token, &config.keys.verification_keys)?;
+        if revoked_token_repo.is_revoked(&claims.jti).await? {
+            return Err(AppError::AuthError(format!("Access token {} is revoked", claims.jti)));
+        }
+        // Signing out a session remotely revokes its id, and with it every token it holds
+        if let Some(session_id) = &claims.sid {
+            if revoked_token_repo.is_revoked(session_id).await? {
+                return Err(AppError::AuthError(format!("Session {} is revoked", session_id)));
+            }
+        }
+        let user_id = Uuid::parse_str(&claims.sub)
+            .map_err(|_| AppError::AuthError(format!("Malformed subject {}", claims.sub)))?;
+
+        Ok(Authenticated { user_id, roles: claims.roles.clone(), claims })
+    }
+
+    /// Like `extract`, then requires `role` of the account as stored rather than as in the
+    /// token, so demoting, disabling, banning or deleting staff takes effect at once.
+    async fn extract_staff(req: HttpRequest, role: Role) -> Result<Self, AppError> {
+        let user_repo = req.app_data::<web::Data<Arc<dyn UserRepository>>>()
+            .ok_or_else(|| AppError::ConfigError("User repository is not registered".into()))?
+            .clone();
+        let mut auth = Self::extract(req).await?;
+
+        let now = Utc::now().timestamp();
+        let user = user_repo.find_user_by_id(auth.user_id).await?
+            .filter(|user| user.is_active(now))
+            .ok_or_else(|| AppError::AuthError(format!("User {} is gone, disabled or banned", auth.user_id)))?;
+        auth.roles = user.roles;
+        auth.require(role)?;
+        Ok(auth)
+    }
+}
+
+impl FromRequest for Authenticated {
+    type Error = AppError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        Box::pin(Self::extract(req.clone()))
+    }
+}
+
+/// A caller with at least the moderator role, see `Authenticated`. Answers 403 to
+/// players with a valid session.
+pub struct Moderator(pub Authenticated);
+
+impl FromRequest for Moderator {
+    type Error = AppError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            Ok(Moderator(Authenticated::extract_staff(req, Role::Moderator).await?))
+        })
+    }
+}
+
+/// A caller with the admin role, see `Moderator`.
+pub struct Admin(pub Authenticated);
+
+impl FromRequest for Admin {
+    type Error = AppError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            Ok(Admin(Authenticated::extract_staff(req, Role::Admin).await?))
+        })
+    }
+}
+
+/// Another part of the deployment, such as a game server, presenting the shared
+/// `INTERNAL_API_TOKEN`. Refused with 403 when the service has none configured.
+pub struct InternalCaller;
+
+impl FromRequest for InternalCaller {
+    type Error = AppError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let expected = req.app_data::<web::Data<Config>>()
+            .and_then(|config| config.internal_api_token.clone());
+        let presented = req.headers()
+            .get(INTERNAL_TOKEN_HEADER)
+            .and_then(|value| value.to_str().ok());
+
+        ready(match (expected, presented) {
+            (Some(expected), Some(presented)) if expected == presented => Ok(InternalCaller),
+            _ => Err(AppError::ForbiddenError("Missing or wrong internal token".into())),
+        })
+    }
+}
@@ -0,0 +1,26 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use chrono::Utc;
+use shared::api::admin::BanUpdate;
+use crate::application::authenticated::InternalCaller;
+use crate::domain::user_repository::UserRepository;
+use crate::errors::AppError;
+use std::sync::Arc;
+
+/// Endpoints for the game servers, mounted under `/internal`.
+pub fn scope() -> actix_web::Scope {
+    web::scope("/internal").service(bans)
+}
+
+/// Every active ban, polled by the game servers so they also learn about bans whose push
+/// they missed, e.g. while restarting.
+#[get("/bans")]
+pub async fn bans(
+    _caller: InternalCaller,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+) -> Result<impl Responder, AppError> {
+    let bans: Vec<BanUpdate> = user_repo.list_active_bans(Utc::now().timestamp()).await?
+        .into_iter()
+        .map(|(user_id, ban)| BanUpdate { user_id: user_id.to_string(), ban: Some(ban) })
+        .collect();
+    Ok(HttpResponse::Ok().json(bans))
+}
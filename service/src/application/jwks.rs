@@ -0,0 +1,9 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use crate::config::Config;
+
+/// Publishes the public keys access tokens can be verified against, so the game server
+/// can check tokens without being able to mint them.
+#[get("/.well-known/jwks.json")]
+pub async fn jwks(config: web::Data<Config>) -> impl Responder {
+    HttpResponse::Ok().json(&config.keys.jwks)
+}
@@ -1,46 +1,261 @@
-use actix_web::{post, web, HttpResponse, Responder};
-use actix_web::cookie::{Cookie, SameSite, time::Duration};
-use shared::api::auth::LoginData;
-use crate::infrastructure::authentication;
-use crate::domain::user_repository::UserRepository;
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use shared::api::auth::{LoginData, LoginResponse, MfaChallenge, MfaLoginData};
+use shared::api::error::{ApiError, ErrorCode};
+use crate::application::{session, throttle};
+use crate::infrastructure::authentication::{
+    self, decode_purpose_token, generate_purpose_token, password_fingerprint, TokenPurpose,
+};
+use crate::infrastructure::hashing_pool::HashingPool;
+use crate::infrastructure::totp;
+use crate::domain::login_attempt_repository::LoginAttemptRepository;
+use crate::domain::refresh_token_repository::RefreshTokenRepository;
+use crate::domain::used_challenge_repository::UsedChallengeRepository;
+use crate::domain::user_repository::{User, UserRepository};
 use crate::config::Config;
 use crate::errors::AppError;
 use std::sync::Arc;
 
 #[post("/login")]
 pub async fn login(
+    req: HttpRequest,
     login_data: web::Json<LoginData>,
     user_repo: web::Data<Arc<dyn UserRepository>>,
+    refresh_token_repo: web::Data<Arc<dyn RefreshTokenRepository>>,
+    login_attempt_repo: web::Data<Arc<dyn LoginAttemptRepository>>,
+    hashing: web::Data<HashingPool>,
     config: web::Data<Config>,
 ) -> Result<impl Responder, AppError> {
     // Validate login data
-    login_data.validate().map_err(|e| AppError::ValidationError(e))?;
+    login_data.validate().map_err(AppError::ValidationError)?;
 
     let username = login_data.username.clone();
     let password = login_data.password.clone();
 
-    let stored_password = match user_repo.find_user_by_username(&username).await? {
-        Some(pw) => pw,
-        None => return Err(AppError::AuthError("Invalid credentials".into())),
+    // Throttle guessing, per account and per client, before doing any work
+    let client_ip = req.peer_addr().map(|addr| addr.ip().to_string()).unwrap_or_default();
+    let throttle_keys = throttle::login_keys(&username, &client_ip, &config);
+    let attempts = login_attempt_repo.get_ref().as_ref();
+    throttle::check(&throttle_keys, attempts).await?;
+
+    let user = user_repo.find_user_by_username(&username).await?;
+    // Unknown users take the same path, see `HashingPool::verify_login`
+    let stored_hash = user.as_ref().map(|user| user.password_hash.as_str());
+    let is_valid = hashing.verify_login(&password, stored_hash).await?;
+
+    // Once the deletion grace period is over the account is gone
+    let now = Utc::now().timestamp();
+    let mut user = match user {
+        Some(user) if is_valid && !user.is_deleted(now) => user,
+        _ => {
+            throttle::record_failure(&throttle_keys, attempts).await?;
+            return Err(AppError::CredentialsError);
+        }
     };
 
-    let is_valid = authentication::verify_password(&password, &stored_password)
-        .map_err(|e| AppError::AuthError(e.to_string()))?;
-
-    if is_valid {
-        let token = authentication::generate_jwt(&username, config.jwt_secret.as_bytes())?;
-        let token_clone = token.clone();
-        let cookie = Cookie::build("access_token", token)
-            .http_only(true)
-            .secure(false)
-            .same_site(SameSite::Lax)
-            .path("/")
-            .max_age(Duration::seconds(3600))
-            .finish();
-        Ok(HttpResponse::Ok()
-        .cookie(cookie)
-        .body(format!("Login successful, token: {}", token_clone)))
+    check_allowed(&user, now)?;
+
+    // A success only clears the account, the address keeps counting its failures. With a
+    // second factor the login has not succeeded yet, see `login_mfa`
+    if !user.has_totp() {
+        attempts.clear(&throttle_keys[0].0).await?;
+    }
+
+    if !user.password_reset_required && hashing.needs_rehash(&user.password_hash) {
+        // Upgrade old hashes while the plain password is at hand, without failing the login
+        let upgrade = async {
+            let hash = hashing.hash(&password).await?;
+            user_repo.update_password(user.id, hash.clone()).await.map(|()| hash)
+        };
+        match upgrade.await {
+            Ok(hash) => user.password_hash = hash,
+            Err(e) => log::error!("Failed to upgrade the password hash of {}: {}", user.username, e),
+        }
+    }
+
+    if user.has_totp() {
+        // Bound to the password just proven, so changing it voids challenges in flight
+        let token = generate_purpose_token(
+            user.id,
+            TokenPurpose::MfaChallenge,
+            password_fingerprint(&user.password_hash),
+            config.mfa_challenge_ttl,
+            &config.keys.signing_key,
+        )?;
+        return Err(AppError::MfaRequired(MfaChallenge { token, expires_at: now + config.mfa_challenge_ttl }));
+    }
+
+    let new_password = required_new_password(&user, login_data.new_password.as_deref(), &hashing, &config).await?;
+    let response = complete_login(
+        &req,
+        user,
+        new_password,
+        login_data.include_token,
+        user_repo.get_ref().as_ref(),
+        refresh_token_repo.get_ref().as_ref(),
+        &hashing,
+        &config,
+    ).await?;
+    Ok(response)
+}
+
+/// The second step of a login for accounts with two-factor authentication, taking the
+/// challenge from the first step and a TOTP or recovery code. Wrong codes count towards
+/// login throttling like wrong passwords. Each code and each challenge works once.
+#[post("/login/mfa")]
+#[allow(clippy::too_many_arguments)]
+pub async fn login_mfa(
+    req: HttpRequest,
+    mfa_data: web::Json<MfaLoginData>,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+    refresh_token_repo: web::Data<Arc<dyn RefreshTokenRepository>>,
+    used_challenge_repo: web::Data<Arc<dyn UsedChallengeRepository>>,
+    login_attempt_repo: web::Data<Arc<dyn LoginAttemptRepository>>,
+    hashing: web::Data<HashingPool>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, AppError> {
+    mfa_data.validate().map_err(AppError::ValidationError)?;
+
+    let claims = decode_purpose_token(&mfa_data.mfa_token, TokenPurpose::MfaChallenge, &config.keys.verification_keys)
+        .map_err(|_| AppError::AuthError("Invalid or expired second factor challenge".to_string()))?;
+    let user_id = claims.user_id()
+        .map_err(|_| AppError::AuthError("Second factor challenge without a user".to_string()))?;
+
+    // The account may have changed since the password was checked
+    let now = Utc::now().timestamp();
+    let user = user_repo.find_user_by_id(user_id).await?
+        .filter(|user| !user.is_deleted(now) && password_fingerprint(&user.password_hash) == claims.binding)
+        .ok_or_else(|| AppError::AuthError(format!("Second factor challenge of {} is outdated", user_id)))?;
+    check_allowed(&user, now)?;
+    let secret = user.totp.as_ref()
+        .filter(|totp| totp.enabled)
+        .map(|totp| totp.secret.clone())
+        .ok_or_else(|| AppError::AuthError(format!("{} has no second factor anymore", user.username)))?;
+
+    let client_ip = req.peer_addr().map(|addr| addr.ip().to_string()).unwrap_or_default();
+    let throttle_keys = throttle::login_keys(&user.username, &client_ip, &config);
+    let attempts = login_attempt_repo.get_ref().as_ref();
+    throttle::check(&throttle_keys, attempts).await?;
+
+    // Checked before the code, so a recovery code is not spent on a login that fails anyway
+    let new_password = required_new_password(&user, mfa_data.new_password.as_deref(), &hashing, &config).await?;
+
+    // Claimed in one step before the code is spent, of two racing requests only one gets on
+    let used_challenges = used_challenge_repo.get_ref().as_ref();
+    if !used_challenges.use_challenge(&claims.jti, claims.exp).await? {
+        return Err(AppError::AuthError(format!("Second factor challenge {} was already used", claims.jti)));
+    }
+
+    let code: String = mfa_data.code.chars().filter(|c| !c.is_whitespace()).collect();
+    let is_valid = if totp::is_totp_code(&code) {
+        // A code seen before, even if still current, is refused like a wrong one
+        match totp::matching_step(&secret, &user.username, &code, now)? {
+            Some(step) => user_repo.use_totp_step(user.id, step).await?,
+            None => false,
+        }
     } else {
-        Err(AppError::AuthError("Invalid credentials".into()))
+        let used = user_repo.use_recovery_code(user.id, &totp::hash_recovery_code(&code)).await?;
+        if used {
+            log::info!("{} logged in with a recovery code", user.username);
+        }
+        used
+    };
+    if !is_valid {
+        // A mistyped code does not cost the challenge
+        used_challenges.release_challenge(&claims.jti).await?;
+        throttle::record_failure(&throttle_keys, attempts).await?;
+        return Err(AppError::invalid_code());
+    }
+    attempts.clear(&throttle_keys[0].0).await?;
+
+    let response = complete_login(
+        &req,
+        user,
+        new_password,
+        mfa_data.include_token,
+        user_repo.get_ref().as_ref(),
+        refresh_token_repo.get_ref().as_ref(),
+        &hashing,
+        &config,
+    ).await?;
+    Ok(response)
+}
+
+/// Refuses disabled and banned accounts, whose credentials are right.
+fn check_allowed(user: &User, now: i64) -> Result<(), AppError> {
+    if user.disabled {
+        return Err(AppError::AuthError(format!("Account {} is disabled", user.username)));
+    }
+    if let Some(ban) = user.active_ban(now) {
+        return Err(AppError::BannedError(ban.clone()));
     }
+    Ok(())
+}
+
+/// For an account that has to choose a new password, the one sent with the login, once
+/// it passes the policy. The new password comes with the login, so the reset needs no
+/// session of its own.
+async fn required_new_password<'a>(
+    user: &User,
+    new_password: Option<&'a str>,
+    hashing: &HashingPool,
+    config: &Config,
+) -> Result<Option<&'a str>, AppError> {
+    if !user.password_reset_required {
+        return Ok(None);
+    }
+
+    let new_password = new_password.ok_or(AppError::PasswordResetRequired)?;
+    config.registration_policy
+        .check_password(new_password, &user.username)
+        .map_err(|error| AppError::ValidationError(error.with_field("new_password")))?;
+    if hashing.verify(new_password, &user.password_hash).await? {
+        return Err(AppError::ValidationError(
+            ApiError::new(ErrorCode::WeakPassword, "Choose a password different from the old one")
+                .with_field("new_password"),
+        ));
+    }
+    Ok(Some(new_password))
+}
+
+/// Everything after the user proved who they are: the required new password, a pending
+/// deletion, and finally the session.
+#[allow(clippy::too_many_arguments)]
+async fn complete_login(
+    req: &HttpRequest,
+    user: User,
+    new_password: Option<&str>,
+    include_token: bool,
+    user_repo: &dyn UserRepository,
+    refresh_token_repo: &dyn RefreshTokenRepository,
+    hashing: &HashingPool,
+    config: &Config,
+) -> Result<HttpResponse, AppError> {
+    if let Some(new_password) = new_password {
+        let hash = hashing.hash(new_password).await?;
+        user_repo.update_password(user.id, hash).await?;
+        log::info!("{} chose a new password as required", user.username);
+    }
+    // Coming back within the grace period keeps the account
+    if user.deletion_scheduled_at.is_some() {
+        log::info!("Cancelling the scheduled deletion of {}", user.username);
+        user_repo.schedule_deletion(user.id, None).await?;
+    }
+    user_repo.record_login(user.id, Utc::now().timestamp()).await?;
+
+    let (session_id, refresh_token) = session::start_refresh_family(user.id, req, refresh_token_repo, config).await?;
+    let (token, claims) =
+        authentication::generate_jwt(&user, &session_id, &config.keys.signing_key, config.access_token_ttl)?;
+    let body = LoginResponse {
+        user_id: user.id.to_string(),
+        username: user.username,
+        display_name: user.display_name,
+        expires_at: claims.exp,
+        roles: claims.roles,
+        token: include_token.then(|| token.clone()),
+    };
+    Ok(HttpResponse::Ok()
+        .cookie(session::access_token_cookie(token, config))
+        .cookie(session::refresh_token_cookie(refresh_token, config))
+        .json(body))
 }
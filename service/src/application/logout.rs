@@ -0,0 +1,53 @@
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use shared::api::auth::RevokedToken;
+use crate::application::session;
+use crate::infrastructure::authentication;
+use crate::domain::refresh_token_repository::RefreshTokenRepository;
+use crate::domain::revoked_token_repository::RevokedTokenRepository;
+use crate::infrastructure::game_servers::GameServers;
+use crate::config::Config;
+use crate::errors::AppError;
+use std::sync::Arc;
+
+/// Ends the caller's session: revokes the access token and every other one of the same
+/// session so the game servers drop them, revokes the refresh token family so it cannot
+/// mint new ones, and clears both cookies. The session is only ended on proof of holding it:
+/// the refresh token's secret or a valid access token, from the cookie or a bearer header.
+/// Succeeds even without a valid session, there is nothing left to log out of.
+#[post("/logout")]
+pub async fn logout(
+    req: HttpRequest,
+    refresh_token_repo: web::Data<Arc<dyn RefreshTokenRepository>>,
+    revoked_token_repo: web::Data<Arc<dyn RevokedTokenRepository>>,
+    game_servers: web::Data<GameServers>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, AppError> {
+    let refresh_tokens = refresh_token_repo.get_ref().as_ref();
+    let revoked_tokens = revoked_token_repo.get_ref().as_ref();
+
+    // An expired or forged token does not need revoking
+    let claims = session::access_token(&req)
+        .and_then(|token| authentication::decode_jwt(&token, &config.keys.verification_keys).ok());
+    if let Some(claims) = &claims {
+        let revocation = RevokedToken {
+            jti: claims.jti.clone(),
+            expires_at: claims.exp,
+        };
+        revoked_tokens.revoke(&revocation).await?;
+        game_servers.push_revocation(revocation);
+    }
+
+    // Game connections opened before a refresh hold older tokens of the same session
+    let session_id = match session::refresh_session_id(&req, refresh_tokens).await? {
+        Some(session_id) => Some(session_id),
+        None => claims.and_then(|claims| claims.sid),
+    };
+    if let Some(session_id) = session_id {
+        session::end_session(&session_id, refresh_tokens, revoked_tokens, &game_servers, &config).await?;
+    }
+
+    Ok(HttpResponse::Ok()
+        .cookie(session::removal_cookie(session::ACCESS_TOKEN_COOKIE))
+        .cookie(session::removal_cookie(session::REFRESH_TOKEN_COOKIE))
+        .body("Logged out"))
+}
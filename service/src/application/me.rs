@@ -0,0 +1,350 @@
+use actix_web::{delete, get, patch, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use shared::api::account::{
+    AccountDeletion, ChangePasswordData, ConfirmTotpData, DeleteAccountData, Profile, SessionInfo,
+    TotpEnrolment, TotpPasswordData, UpdateProfileData,
+};
+use shared::api::auth::RevokedToken;
+use shared::api::error::{ApiError, ErrorCode};
+use crate::application::authenticated::Authenticated;
+use crate::application::verify_email::send_verification_mail;
+use crate::application::{session, throttle};
+use crate::config::Config;
+use crate::domain::login_attempt_repository::LoginAttemptRepository;
+use crate::domain::mailer::Mailer;
+use crate::domain::refresh_token_repository::RefreshTokenRepository;
+use crate::domain::revoked_token_repository::RevokedTokenRepository;
+use crate::domain::user_repository::{Totp, User, UserRepository};
+use crate::errors::AppError;
+use crate::infrastructure::game_servers::GameServers;
+use crate::infrastructure::hashing_pool::HashingPool;
+use crate::infrastructure::totp;
+use std::sync::Arc;
+
+/// The caller's own profile.
+#[get("/me")]
+pub async fn profile(
+    auth: Authenticated,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+) -> Result<impl Responder, AppError> {
+    let user = current_user(&auth, user_repo.get_ref().as_ref()).await?;
+    Ok(HttpResponse::Ok().json(to_profile(user)))
+}
+
+/// Changes the caller's display name or email, answering with the updated profile. A new
+/// email takes the password and is mailed a verification link, password resets are only
+/// sent there once it is followed.
+#[patch("/me")]
+#[allow(clippy::too_many_arguments)]
+pub async fn update_profile(
+    req: HttpRequest,
+    auth: Authenticated,
+    profile_data: web::Json<UpdateProfileData>,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+    login_attempt_repo: web::Data<Arc<dyn LoginAttemptRepository>>,
+    hashing: web::Data<HashingPool>,
+    mailer: web::Data<Arc<dyn Mailer>>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, AppError> {
+    profile_data.validate().map_err(AppError::ValidationError)?;
+
+    let mut user = current_user(&auth, user_repo.get_ref().as_ref()).await?;
+
+    let new_email = profile_data.email.as_ref().filter(|email| user.email.as_ref() != Some(*email));
+    if let (Some(email), Some(password)) = (new_email, &profile_data.password) {
+        let attempts = login_attempt_repo.get_ref().as_ref();
+        confirm_password(&req, &user, password, "password", attempts, &hashing, &config).await?;
+
+        user_repo.update_email(user.id, email.clone()).await?;
+        user.email = Some(email.clone());
+        user.email_verified = false;
+        send_verification_mail(&user, email, mailer.get_ref(), &config)?;
+    }
+
+    if let Some(display_name) = &profile_data.display_name {
+        user.display_name = display_name.trim().to_string();
+        user_repo.update_display_name(user.id, user.display_name.clone()).await?;
+    }
+
+    Ok(HttpResponse::Ok().json(to_profile(user)))
+}
+
+/// Replaces the caller's password after checking the current one, and ends every other
+/// session so whoever else knew the old password is logged out.
+#[post("/me/password")]
+#[allow(clippy::too_many_arguments)]
+pub async fn change_password(
+    req: HttpRequest,
+    auth: Authenticated,
+    password_data: web::Json<ChangePasswordData>,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+    refresh_token_repo: web::Data<Arc<dyn RefreshTokenRepository>>,
+    revoked_token_repo: web::Data<Arc<dyn RevokedTokenRepository>>,
+    login_attempt_repo: web::Data<Arc<dyn LoginAttemptRepository>>,
+    game_servers: web::Data<GameServers>,
+    hashing: web::Data<HashingPool>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, AppError> {
+    let user = current_user(&auth, user_repo.get_ref().as_ref()).await?;
+    let attempts = login_attempt_repo.get_ref().as_ref();
+    confirm_password(&req, &user, &password_data.current_password, "current_password", attempts, &hashing, &config).await?;
+
+    config.registration_policy
+        .check_password(&password_data.new_password, &user.username)
+        .map_err(|error| AppError::ValidationError(error.with_field("new_password")))?;
+
+    let hash = hashing.hash(&password_data.new_password).await?;
+    user_repo.update_password(user.id, hash).await?;
+
+    let refresh_tokens = refresh_token_repo.get_ref().as_ref();
+    let current_session = match auth.claims.sid.clone() {
+        Some(session_id) => Some(session_id),
+        None => session::refresh_session_id(&req, refresh_tokens).await?,
+    };
+    session::end_user_sessions(
+        user.id,
+        current_session.as_deref(),
+        refresh_tokens,
+        revoked_token_repo.get_ref().as_ref(),
+        &game_servers,
+        &config,
+    ).await?;
+
+    Ok(HttpResponse::Ok().body("Password changed"))
+}
+
+/// Schedules the caller's account for deletion after the grace period and logs them out
+/// everywhere. Logging in again before it ends keeps the account.
+#[delete("/me")]
+#[allow(clippy::too_many_arguments)]
+pub async fn delete_account(
+    req: HttpRequest,
+    auth: Authenticated,
+    delete_data: web::Json<DeleteAccountData>,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+    refresh_token_repo: web::Data<Arc<dyn RefreshTokenRepository>>,
+    revoked_token_repo: web::Data<Arc<dyn RevokedTokenRepository>>,
+    login_attempt_repo: web::Data<Arc<dyn LoginAttemptRepository>>,
+    game_servers: web::Data<GameServers>,
+    hashing: web::Data<HashingPool>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, AppError> {
+    let user = current_user(&auth, user_repo.get_ref().as_ref()).await?;
+    let attempts = login_attempt_repo.get_ref().as_ref();
+    confirm_password(&req, &user, &delete_data.password, "password", attempts, &hashing, &config).await?;
+
+    let deleted_at = Utc::now().timestamp() + config.account_deletion_grace;
+    user_repo.schedule_deletion(user.id, Some(deleted_at)).await?;
+
+    session::end_user_sessions(
+        user.id,
+        None,
+        refresh_token_repo.get_ref().as_ref(),
+        revoked_token_repo.get_ref().as_ref(),
+        &game_servers,
+        &config,
+    ).await?;
+    revoked_token_repo.revoke(&RevokedToken {
+        jti: auth.claims.jti,
+        expires_at: auth.claims.exp,
+    }).await?;
+
+    Ok(HttpResponse::Ok()
+        .cookie(session::removal_cookie(session::ACCESS_TOKEN_COOKIE))
+        .cookie(session::removal_cookie(session::REFRESH_TOKEN_COOKIE))
+        .json(AccountDeletion { deleted_at }))
+}
+
+/// Starts enrolling the caller in two-factor authentication, replacing an enrolment that
+/// was never confirmed. Answers with the secret and recovery codes, which are not shown
+/// again. Logins only ask for codes once the enrolment is confirmed.
+#[post("/me/totp")]
+pub async fn enrol_totp(
+    req: HttpRequest,
+    auth: Authenticated,
+    password_data: web::Json<TotpPasswordData>,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+    login_attempt_repo: web::Data<Arc<dyn LoginAttemptRepository>>,
+    hashing: web::Data<HashingPool>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, AppError> {
+    let user = current_user(&auth, user_repo.get_ref().as_ref()).await?;
+    let attempts = login_attempt_repo.get_ref().as_ref();
+    confirm_password(&req, &user, &password_data.password, "password", attempts, &hashing, &config).await?;
+    if user.has_totp() {
+        return Err(AppError::ConflictError(ApiError::new(
+            ErrorCode::InvalidInput,
+            "Two-factor authentication is already enabled",
+        )));
+    }
+
+    let secret = totp::generate_secret();
+    let recovery_codes = totp::generate_recovery_codes();
+    let enrolment = TotpEnrolment {
+        otpauth_uri: totp::otpauth_uri(&secret, &user.username)?,
+        secret: secret.clone(),
+        recovery_codes: recovery_codes.clone(),
+    };
+    user_repo.set_totp(user.id, Some(Totp {
+        secret,
+        enabled: false,
+        recovery_codes: recovery_codes.iter().map(|code| totp::hash_recovery_code(code)).collect(),
+        last_used_step: None,
+    })).await?;
+
+    Ok(HttpResponse::Ok().json(enrolment))
+}
+
+/// Turns two-factor authentication on with a first code from the app, which proves it
+/// was set up right.
+#[post("/me/totp/confirm")]
+pub async fn confirm_totp(
+    auth: Authenticated,
+    confirm_data: web::Json<ConfirmTotpData>,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+) -> Result<impl Responder, AppError> {
+    let user = current_user(&auth, user_repo.get_ref().as_ref()).await?;
+    let Some(pending) = user.totp.filter(|totp| !totp.enabled) else {
+        return Err(AppError::ValidationError(ApiError::invalid("code", "There is no enrolment to confirm")));
+    };
+
+    let code: String = confirm_data.code.chars().filter(|c| !c.is_whitespace()).collect();
+    let Some(step) = totp::matching_step(&pending.secret, &user.username, &code, Utc::now().timestamp())? else {
+        return Err(AppError::invalid_code());
+    };
+    // The confirming code is spent, it cannot be replayed to log in
+    user_repo.set_totp(user.id, Some(Totp { enabled: true, last_used_step: Some(step), ..pending })).await?;
+    log::info!("{} enabled two-factor authentication", user.username);
+
+    Ok(HttpResponse::Ok().body("Two-factor authentication enabled"))
+}
+
+/// Turns two-factor authentication off, or drops an unconfirmed enrolment.
+#[delete("/me/totp")]
+pub async fn disable_totp(
+    req: HttpRequest,
+    auth: Authenticated,
+    password_data: web::Json<TotpPasswordData>,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+    login_attempt_repo: web::Data<Arc<dyn LoginAttemptRepository>>,
+    hashing: web::Data<HashingPool>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, AppError> {
+    let user = current_user(&auth, user_repo.get_ref().as_ref()).await?;
+    let attempts = login_attempt_repo.get_ref().as_ref();
+    confirm_password(&req, &user, &password_data.password, "password", attempts, &hashing, &config).await?;
+
+    user_repo.set_totp(user.id, None).await?;
+    log::info!("{} disabled two-factor authentication", user.username);
+
+    Ok(HttpResponse::Ok().body("Two-factor authentication disabled"))
+}
+
+/// Where the caller is logged in, most recently active first. Revoked and expired
+/// sessions are left out.
+#[get("/me/sessions")]
+pub async fn sessions(
+    auth: Authenticated,
+    refresh_token_repo: web::Data<Arc<dyn RefreshTokenRepository>>,
+) -> Result<impl Responder, AppError> {
+    let now = Utc::now().timestamp();
+    let mut sessions: Vec<SessionInfo> = refresh_token_repo.list_user_families(auth.user_id).await?
+        .into_iter()
+        .filter(|family| !family.revoked && family.expires_at > now)
+        .map(|family| SessionInfo {
+            current: auth.claims.sid.as_deref() == Some(family.family_id.as_str()),
+            id: family.family_id,
+            user_agent: family.user_agent,
+            ip: family.ip,
+            created_at: family.created_at,
+            last_seen_at: family.last_seen_at,
+        })
+        .collect();
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.last_seen_at));
+
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+/// Signs one of the caller's sessions out: its refresh token stops working at once, and
+/// so do its access tokens, which the game servers are told about to close its
+/// connections. Revoking the current session logs the caller out.
+#[delete("/me/sessions/{id}")]
+pub async fn revoke_session(
+    auth: Authenticated,
+    id: web::Path<String>,
+    refresh_token_repo: web::Data<Arc<dyn RefreshTokenRepository>>,
+    revoked_token_repo: web::Data<Arc<dyn RevokedTokenRepository>>,
+    game_servers: web::Data<GameServers>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, AppError> {
+    let session_id = id.into_inner();
+    // Someone else's session is as good as no session
+    refresh_token_repo.find_family(&session_id).await?
+        .filter(|family| family.user_id == auth.user_id)
+        .ok_or_else(|| AppError::NotFoundError(format!("Session {}", session_id)))?;
+
+    session::end_session(
+        &session_id,
+        refresh_token_repo.get_ref().as_ref(),
+        revoked_token_repo.get_ref().as_ref(),
+        &game_servers,
+        &config,
+    ).await?;
+    log::info!("User {} revoked session {}", auth.user_id, session_id);
+
+    let mut response = HttpResponse::Ok();
+    if auth.claims.sid.as_deref() == Some(session_id.as_str()) {
+        response
+            .cookie(session::removal_cookie(session::ACCESS_TOKEN_COOKIE))
+            .cookie(session::removal_cookie(session::REFRESH_TOKEN_COOKIE));
+    }
+    Ok(response.body("Session revoked"))
+}
+
+/// The account behind the session, which may have been disabled, banned or deleted since
+/// the token was issued.
+async fn current_user(auth: &Authenticated, user_repo: &dyn UserRepository) -> Result<User, AppError> {
+    let now = Utc::now().timestamp();
+    user_repo.find_user_by_id(auth.user_id).await?
+        .filter(|user| user.is_active(now))
+        .ok_or_else(|| AppError::AuthError(format!("User {} is gone, disabled or banned", auth.user_id)))
+}
+
+/// Checks a password the user typed again to confirm a sensitive change. Failures count
+/// towards login throttling, so a hijacked session cannot be used to guess it.
+async fn confirm_password(
+    req: &HttpRequest,
+    user: &User,
+    password: &str,
+    field: &str,
+    attempts: &dyn LoginAttemptRepository,
+    hashing: &HashingPool,
+    config: &Config,
+) -> Result<(), AppError> {
+    let client_ip = req.peer_addr().map(|addr| addr.ip().to_string()).unwrap_or_default();
+    let throttle_keys = throttle::login_keys(&user.username, &client_ip, config);
+    throttle::check(&throttle_keys, attempts).await?;
+
+    if !hashing.verify(password, &user.password_hash).await? {
+        throttle::record_failure(&throttle_keys, attempts).await?;
+        return Err(AppError::ValidationError(
+            ApiError::new(ErrorCode::InvalidCredentials, "Password is incorrect").with_field(field),
+        ));
+    }
+    Ok(())
+}
+
+fn to_profile(user: User) -> Profile {
+    let totp_enabled = user.has_totp();
+    Profile {
+        id: user.id.to_string(),
+        username: user.username,
+        display_name: user.display_name,
+        roles: user.roles,
+        created_at: user.created_at,
+        last_login_at: user.last_login_at,
+        email: user.email,
+        email_verified: user.email_verified,
+        totp_enabled,
+    }
+}
@@ -1,2 +1,15 @@
+pub mod account_purge;
+pub mod admin;
+pub mod authenticated;
+pub mod internal;
+pub mod jwks;
 pub mod login;
+pub mod logout;
+pub mod password_reset;
+pub mod me;
+pub mod refresh;
 pub mod register;
+pub mod revocations;
+pub mod session;
+pub mod throttle;
+pub mod verify_email;
@@ -0,0 +1,105 @@
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use shared::api::account::{PasswordResetData, PasswordResetRequestData};
+use crate::application::{session, throttle};
+use crate::config::Config;
+use crate::domain::login_attempt_repository::LoginAttemptRepository;
+use crate::domain::mailer::{Mail, Mailer};
+use crate::domain::refresh_token_repository::RefreshTokenRepository;
+use crate::domain::revoked_token_repository::RevokedTokenRepository;
+use crate::domain::user_repository::UserRepository;
+use crate::errors::AppError;
+use crate::infrastructure::authentication::{
+    decode_purpose_token, generate_purpose_token, password_fingerprint, TokenPurpose,
+};
+use crate::infrastructure::game_servers::GameServers;
+use crate::infrastructure::hashing_pool::HashingPool;
+use crate::infrastructure::mailer::send_in_background;
+use std::sync::Arc;
+
+/// Mails a reset link to the account's verified email. Answers the same whether or not
+/// a mail went out, so it cannot be used to find accounts or their addresses, and every
+/// request is throttled so it cannot be used to flood an inbox either.
+#[post("/password-reset/request")]
+pub async fn request_password_reset(
+    req: HttpRequest,
+    request_data: web::Json<PasswordResetRequestData>,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+    login_attempt_repo: web::Data<Arc<dyn LoginAttemptRepository>>,
+    mailer: web::Data<Arc<dyn Mailer>>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, AppError> {
+    let client_ip = req.peer_addr().map(|addr| addr.ip().to_string()).unwrap_or_default();
+    let throttle_keys = throttle::password_reset_keys(&request_data.username, &client_ip, &config);
+    let attempts = login_attempt_repo.get_ref().as_ref();
+    throttle::check(&throttle_keys, attempts).await?;
+    throttle::record_failure(&throttle_keys, attempts).await?;
+
+    let now = Utc::now().timestamp();
+    let recipient = user_repo.find_user_by_username(&request_data.username).await?
+        .filter(|user| !user.is_deleted(now) && user.email_verified)
+        .and_then(|user| Some((user.email.clone()?, user)));
+    if let Some((email, user)) = recipient {
+        // Bound to the current password, so the link stops working once it was used
+        let token = generate_purpose_token(
+            user.id,
+            TokenPurpose::PasswordReset,
+            password_fingerprint(&user.password_hash),
+            config.password_reset_ttl,
+            &config.keys.signing_key,
+        )?;
+        send_in_background(mailer.get_ref(), Mail {
+            to: email,
+            subject: "Reset your Inner Shelter password".to_string(),
+            body: format!(
+                "Hi {},\n\nopen this link to choose a new password:\n\n{}/?reset_password={}\n\n\
+                 The link works once, for {} minutes. If you did not ask for it, you can ignore this mail.",
+                user.display_name, config.public_url, token, config.password_reset_ttl / 60,
+            ),
+        });
+    }
+
+    Ok(HttpResponse::Accepted().body("If the account has a verified email, a reset link is on its way"))
+}
+
+/// Sets the new password from a reset link, which also satisfies a reset required by
+/// staff, and ends every session of the account. The account's login throttling starts
+/// over, so the owner is not locked out by whoever guessed at the old password.
+#[post("/password-reset/confirm")]
+#[allow(clippy::too_many_arguments)]
+pub async fn confirm_password_reset(
+    reset_data: web::Json<PasswordResetData>,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+    refresh_token_repo: web::Data<Arc<dyn RefreshTokenRepository>>,
+    revoked_token_repo: web::Data<Arc<dyn RevokedTokenRepository>>,
+    login_attempt_repo: web::Data<Arc<dyn LoginAttemptRepository>>,
+    game_servers: web::Data<GameServers>,
+    hashing: web::Data<HashingPool>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, AppError> {
+    let claims = decode_purpose_token(&reset_data.token, TokenPurpose::PasswordReset, &config.keys.verification_keys)?;
+
+    let now = Utc::now().timestamp();
+    let user = user_repo.find_user_by_id(claims.user_id()?).await?
+        .filter(|user| !user.is_deleted(now))
+        .filter(|user| password_fingerprint(&user.password_hash) == claims.binding)
+        .ok_or_else(AppError::invalid_token)?;
+
+    config.registration_policy
+        .check_password(&reset_data.new_password, &user.username)
+        .map_err(|error| AppError::ValidationError(error.with_field("new_password")))?;
+
+    let hash = hashing.hash(&reset_data.new_password).await?;
+    user_repo.update_password(user.id, hash).await?;
+    login_attempt_repo.clear(&throttle::account_key(&user.username)).await?;
+    session::end_user_sessions(
+        user.id,
+        None,
+        refresh_token_repo.get_ref().as_ref(),
+        revoked_token_repo.get_ref().as_ref(),
+        &game_servers,
+        &config,
+    ).await?;
+
+    Ok(HttpResponse::Ok().body("Password changed"))
+}
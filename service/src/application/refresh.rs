@@ -0,0 +1,94 @@
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use crate::application::session;
+use crate::infrastructure::authentication;
+use crate::domain::refresh_token_repository::RefreshTokenRepository;
+use crate::domain::revoked_token_repository::RevokedTokenRepository;
+use crate::domain::user_repository::UserRepository;
+use crate::config::Config;
+use crate::errors::AppError;
+use crate::infrastructure::game_servers::GameServers;
+use std::sync::Arc;
+
+/// Exchanges the `refresh_token` cookie for a new access token and a new refresh token.
+/// Each refresh token works once: presenting one that was already exchanged ends the
+/// session, revoking every token descending from the same login, access tokens
+/// included, unless it was exchanged within the last `Config::refresh_reuse_grace`
+/// seconds.
+#[post("/refresh")]
+pub async fn refresh(
+    req: HttpRequest,
+    refresh_token_repo: web::Data<Arc<dyn RefreshTokenRepository>>,
+    revoked_token_repo: web::Data<Arc<dyn RevokedTokenRepository>>,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+    game_servers: web::Data<GameServers>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, AppError> {
+    let cookie = req.cookie(session::REFRESH_TOKEN_COOKIE)
+        .ok_or_else(|| AppError::AuthError("Missing refresh token".into()))?;
+    let (family_id, secret) = session::parse_refresh_token(cookie.value())
+        .ok_or_else(|| AppError::AuthError("Malformed refresh token".into()))?;
+
+    let family = refresh_token_repo.find_family(family_id).await?
+        .ok_or_else(|| AppError::AuthError("Unknown refresh token".into()))?;
+
+    if family.revoked {
+        return Err(AppError::AuthError("Refresh token revoked".into()));
+    }
+    if family.expires_at <= Utc::now().timestamp() {
+        return Err(AppError::AuthError("Refresh token expired".into()));
+    }
+
+    let presented_hash = authentication::hash_refresh_secret(secret);
+    let new_secret = authentication::generate_refresh_secret();
+    let new_hash = authentication::hash_refresh_secret(&new_secret);
+    let now = Utc::now().timestamp();
+    let expires_at = now + config.refresh_token_ttl;
+
+    // Fails if the presented token is not the current one, including when a concurrent
+    // request presenting the same token won the race
+    let rotated = family.current_token_hash == presented_hash
+        && refresh_token_repo.rotate(family_id, &presented_hash, &new_hash, expires_at, now).await?;
+
+    let refresh_tokens = refresh_token_repo.get_ref().as_ref();
+    if !rotated && !raced_another_refresh(family_id, &presented_hash, refresh_tokens, &config).await? {
+        log::warn!("Refresh token reuse detected for {}, revoking family {}", family.user_id, family_id);
+        // Whoever holds the stolen token may hold access tokens of the session too
+        let revoked_tokens = revoked_token_repo.get_ref().as_ref();
+        session::end_session(family_id, refresh_tokens, revoked_tokens, &game_servers, &config).await?;
+        return Err(AppError::AuthError("Refresh token reuse detected".into()));
+    }
+
+    // Fresh from the store, so role changes and disabled, banned or deleted accounts apply
+    // on the next refresh
+    let user = user_repo.find_user_by_id(family.user_id).await?
+        .filter(|user| user.is_active(now))
+        .ok_or_else(|| AppError::AuthError(format!("User {} is gone, disabled or banned", family.user_id)))?;
+
+    let (token, _) = authentication::generate_jwt(&user, family_id, &config.keys.signing_key, config.access_token_ttl)?;
+    let mut response = HttpResponse::Ok();
+    response.cookie(session::access_token_cookie(token, &config));
+    // The loser of a race only gets an access token, its browser already holds the
+    // refresh token the winner was given
+    if rotated {
+        response.cookie(session::refresh_token_cookie(format!("{}.{}", family_id, new_secret), &config));
+    }
+    Ok(response.body("Token refreshed"))
+}
+
+/// Whether the presented token was current until a moment ago, because another request,
+/// typically another tab, refreshed with the same token at the same time.
+async fn raced_another_refresh(
+    family_id: &str,
+    presented_hash: &str,
+    refresh_tokens: &dyn RefreshTokenRepository,
+    config: &Config,
+) -> Result<bool, AppError> {
+    // Read again, the rotation that beat this request may have happened after the first read
+    let now = Utc::now().timestamp();
+    Ok(refresh_tokens.find_family(family_id).await?.is_some_and(|family| {
+        !family.revoked
+            && family.previous_token_hash.as_deref() == Some(presented_hash)
+            && now < family.last_seen_at + config.refresh_reuse_grace
+    }))
+}
@@ -1,7 +1,10 @@
 use actix_web::{post, web, HttpResponse, Responder};
 use shared::api::auth::RegisterData;
-use crate::infrastructure::authentication;
-use crate::domain::user_repository::UserRepository;
+use crate::application::verify_email::send_verification_mail;
+use crate::infrastructure::hashing_pool::HashingPool;
+use crate::domain::mailer::Mailer;
+use crate::domain::user_repository::{User, UserRepository};
+use crate::config::Config;
 use crate::errors::AppError;
 use std::sync::Arc;
 
@@ -9,21 +12,24 @@ use std::sync::Arc;
 pub async fn register(
     register_data: web::Json<RegisterData>,
     user_repo: web::Data<Arc<dyn UserRepository>>,
+    hashing: web::Data<HashingPool>,
+    mailer: web::Data<Arc<dyn Mailer>>,
+    config: web::Data<Config>,
 ) -> Result<impl Responder, AppError> {
     // Validate registration data
-    register_data.validate().map_err(|e| AppError::ValidationError(e))?;
+    register_data.validate(&config.registration_policy).map_err(AppError::ValidationError)?;
 
     let username = register_data.username.clone();
-    let password = authentication::hash_password(&register_data.password)
-        .map_err(|e| AppError::AuthError(e.to_string()))?;
+    let password = hashing.hash(&register_data.password).await?;
 
-    // Check if user already exists
-    if user_repo.find_user_by_username(&username).await?.is_some() {
-        return Err(AppError::ValidationError("Username already taken".into()));
-    }
+    // Create new user, fails with a conflict if the username is already taken
+    let mut user = User::new(&username, password);
+    user.email = register_data.email.clone();
+    user_repo.create_user(&user).await?;
 
-    // Create new user
-    user_repo.create_user(&username, password).await?;
+    if let Some(email) = &user.email {
+        send_verification_mail(&user, email, mailer.get_ref(), &config)?;
+    }
 
     Ok(HttpResponse::Ok().body("User created successfully"))
 }
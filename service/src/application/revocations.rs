@@ -0,0 +1,14 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use crate::domain::revoked_token_repository::RevokedTokenRepository;
+use crate::errors::AppError;
+use std::sync::Arc;
+
+/// Lists the access tokens revoked before their expiry, polled by the game server so it
+/// can refuse and disconnect them. Only token ids are exposed, never whose they are.
+#[get("/revocations")]
+pub async fn revocations(
+    revoked_token_repo: web::Data<Arc<dyn RevokedTokenRepository>>,
+) -> Result<impl Responder, AppError> {
+    let revoked = revoked_token_repo.list_revoked().await?;
+    Ok(HttpResponse::Ok().json(revoked))
+}
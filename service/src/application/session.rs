@@ -0,0 +1,148 @@
+use actix_web::cookie::{Cookie, SameSite, time::Duration};
+use actix_web::http::header;
+use actix_web::HttpRequest;
+use chrono::Utc;
+use crate::config::Config;
+use crate::domain::refresh_token_repository::{RefreshTokenFamily, RefreshTokenRepository};
+use crate::domain::revoked_token_repository::RevokedTokenRepository;
+use crate::errors::AppError;
+use crate::infrastructure::authentication;
+use crate::infrastructure::game_servers::GameServers;
+use shared::api::auth::RevokedToken;
+use uuid::Uuid;
+
+pub use shared::token::ACCESS_TOKEN_COOKIE;
+pub const REFRESH_TOKEN_COOKIE: &str = "refresh_token";
+
+/// Longer `User-Agent` headers are cut, they are only shown to tell sessions apart.
+const MAX_USER_AGENT_LENGTH: usize = 256;
+
+/// Starts a new refresh token family, i.e. a session, for a freshly authenticated user,
+/// noting the device and address of the request. Returns the family id together with
+/// the refresh token to hand out, formatted as `<family id>.<secret>`.
+pub async fn start_refresh_family(
+    user_id: Uuid,
+    req: &HttpRequest,
+    refresh_tokens: &dyn RefreshTokenRepository,
+    config: &Config,
+) -> Result<(String, String), AppError> {
+    let family_id = uuid::Uuid::new_v4().to_string();
+    let secret = authentication::generate_refresh_secret();
+    let user_agent = req.headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+        .map(|value| value.chars().take(MAX_USER_AGENT_LENGTH).collect());
+
+    let now = Utc::now().timestamp();
+    refresh_tokens.create_family(&RefreshTokenFamily {
+        family_id: family_id.clone(),
+        user_id,
+        current_token_hash: authentication::hash_refresh_secret(&secret),
+        previous_token_hash: None,
+        revoked: false,
+        expires_at: now + config.refresh_token_ttl,
+        created_at: now,
+        last_seen_at: now,
+        user_agent,
+        ip: req.peer_addr().map(|addr| addr.ip().to_string()),
+    }).await?;
+
+    let refresh_token = format!("{}.{}", family_id, secret);
+    Ok((family_id, refresh_token))
+}
+
+/// Ends a session for good: its refresh token stops working, the access tokens it holds
+/// are revoked by their `sid`, and the game servers drop its connections.
+pub async fn end_session(
+    session_id: &str,
+    refresh_tokens: &dyn RefreshTokenRepository,
+    revoked_tokens: &dyn RevokedTokenRepository,
+    game_servers: &GameServers,
+    config: &Config,
+) -> Result<(), AppError> {
+    refresh_tokens.revoke_family(session_id).await?;
+    // Listed until the last access token the session could hold has expired
+    let revocation = RevokedToken {
+        jti: session_id.to_string(),
+        expires_at: Utc::now().timestamp() + config.access_token_ttl,
+    };
+    revoked_tokens.revoke(&revocation).await?;
+    game_servers.push_revocation(revocation);
+    Ok(())
+}
+
+/// `end_session` for every session of the user except `keep`.
+pub async fn end_user_sessions(
+    user_id: Uuid,
+    keep: Option<&str>,
+    refresh_tokens: &dyn RefreshTokenRepository,
+    revoked_tokens: &dyn RevokedTokenRepository,
+    game_servers: &GameServers,
+    config: &Config,
+) -> Result<(), AppError> {
+    let families = refresh_tokens.list_user_families(user_id).await?;
+    for family in families.iter().filter(|family| !family.revoked && Some(family.family_id.as_str()) != keep) {
+        end_session(&family.family_id, refresh_tokens, revoked_tokens, game_servers, config).await?;
+    }
+    // Also catches families the listing missed, and lets stores forget revoked ones
+    refresh_tokens.revoke_user_families(user_id, keep).await
+}
+
+/// The access token the request carries, see `shared::token::request_access_token`.
+pub fn access_token(req: &HttpRequest) -> Option<String> {
+    let cookie = req.cookie(ACCESS_TOKEN_COOKIE);
+    let authorization = req.headers().get(header::AUTHORIZATION).and_then(|value| value.to_str().ok());
+    shared::token::request_access_token(cookie.as_ref().map(|cookie| cookie.value()), authorization)
+}
+
+/// The session of the refresh token the request carries, if its secret is the family's
+/// current or previous one. The family id alone is no proof, it is shown to other devices.
+pub async fn refresh_session_id(
+    req: &HttpRequest,
+    refresh_tokens: &dyn RefreshTokenRepository,
+) -> Result<Option<String>, AppError> {
+    let Some(cookie) = req.cookie(REFRESH_TOKEN_COOKIE) else {
+        return Ok(None);
+    };
+    let Some((family_id, secret)) = parse_refresh_token(cookie.value()) else {
+        return Ok(None);
+    };
+    let Some(family) = refresh_tokens.find_family(family_id).await? else {
+        return Ok(None);
+    };
+    let presented_hash = authentication::hash_refresh_secret(secret);
+    let matches = presented_hash == family.current_token_hash
+        || family.previous_token_hash.as_deref() == Some(presented_hash.as_str());
+    Ok(matches.then_some(family.family_id))
+}
+
+/// Splits a refresh token into its family id and secret.
+pub fn parse_refresh_token(token: &str) -> Option<(&str, &str)> {
+    token.split_once('.').filter(|(family_id, secret)| !family_id.is_empty() && !secret.is_empty())
+}
+
+pub fn access_token_cookie(token: String, config: &Config) -> Cookie<'static> {
+    session_cookie(ACCESS_TOKEN_COOKIE, token, config.access_token_ttl)
+}
+
+pub fn refresh_token_cookie(token: String, config: &Config) -> Cookie<'static> {
+    session_cookie(REFRESH_TOKEN_COOKIE, token, config.refresh_token_ttl)
+}
+
+/// An expired cookie that makes the browser drop the one with the same name.
+pub fn removal_cookie(name: &'static str) -> Cookie<'static> {
+    let mut cookie = session_cookie(name, String::new(), 0);
+    cookie.make_removal();
+    cookie
+}
+
+fn session_cookie(name: &'static str, value: String, max_age: i64) -> Cookie<'static> {
+    Cookie::build(name, value)
+        .http_only(true)
+        .secure(false)
+        .same_site(SameSite::Lax)
+        .path("/")
+        .max_age(Duration::seconds(max_age))
+        .finish()
+}
@@ -0,0 +1,50 @@
+use chrono::Utc;
+use crate::config::Config;
+use crate::domain::login_attempt_repository::{LoginAttemptRepository, ThrottlePolicy};
+use crate::errors::AppError;
+
+/// The throttling key of an account's logins.
+pub fn account_key(username: &str) -> String {
+    format!("user:{}", username)
+}
+
+/// The throttling keys a login attempt counts against, each with its policy.
+pub fn login_keys(username: &str, client_ip: &str, config: &Config) -> [(String, ThrottlePolicy); 2] {
+    [
+        (account_key(username), config.account_throttle),
+        (format!("ip:{}", client_ip), config.ip_throttle),
+    ]
+}
+
+/// The throttling keys a password reset request counts against. Apart from the login
+/// ones, so asking for resets cannot lock anybody out.
+pub fn password_reset_keys(username: &str, client_ip: &str, config: &Config) -> [(String, ThrottlePolicy); 2] {
+    [
+        (format!("password-reset:user:{}", username), config.account_throttle),
+        (format!("password-reset:ip:{}", client_ip), config.ip_throttle),
+    ]
+}
+
+/// Refuses the attempt with `AppError::RateLimitError` while any of the keys is blocked.
+pub async fn check(keys: &[(String, ThrottlePolicy)], attempts: &dyn LoginAttemptRepository) -> Result<(), AppError> {
+    let now = Utc::now().timestamp();
+    let mut retry_after = 0;
+    for (key, _) in keys {
+        if let Some(recorded) = attempts.find(key).await? {
+            retry_after = retry_after.max(recorded.blocked_until - now);
+        }
+    }
+
+    if retry_after > 0 {
+        return Err(AppError::RateLimitError(retry_after));
+    }
+    Ok(())
+}
+
+/// Counts a failed attempt against every key.
+pub async fn record_failure(keys: &[(String, ThrottlePolicy)], attempts: &dyn LoginAttemptRepository) -> Result<(), AppError> {
+    for (key, policy) in keys {
+        attempts.record_failure(key, policy).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use actix_web::{post, web, HttpResponse, Responder};
+use chrono::Utc;
+use shared::api::account::VerifyEmailData;
+use crate::config::Config;
+use crate::domain::mailer::{Mail, Mailer};
+use crate::domain::user_repository::{User, UserRepository};
+use crate::errors::AppError;
+use crate::infrastructure::authentication::{decode_purpose_token, generate_purpose_token, TokenPurpose};
+use crate::infrastructure::mailer::send_in_background;
+use std::sync::Arc;
+
+/// Mails the user a link proving they receive mail at `email`.
+pub fn send_verification_mail(
+    user: &User,
+    email: &str,
+    mailer: &Arc<dyn Mailer>,
+    config: &Config,
+) -> Result<(), AppError> {
+    let token = generate_purpose_token(
+        user.id,
+        TokenPurpose::VerifyEmail,
+        email.to_string(),
+        config.email_verification_ttl,
+        &config.keys.signing_key,
+    )?;
+    send_in_background(mailer, Mail {
+        to: email.to_string(),
+        subject: "Confirm your email for Inner Shelter".to_string(),
+        body: format!(
+            "Hi {},\n\nopen this link to confirm your email address:\n\n{}/?verify_email={}\n\n\
+             If you did not create an account, you can ignore this mail.",
+            user.display_name, config.public_url, token,
+        ),
+    });
+    Ok(())
+}
+
+/// Marks the email the token was mailed to as verified. Each token works once, and not
+/// at all if the account's email changed in the meantime.
+#[post("/verify-email")]
+pub async fn verify_email(
+    verify_data: web::Json<VerifyEmailData>,
+    user_repo: web::Data<Arc<dyn UserRepository>>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, AppError> {
+    let claims = decode_purpose_token(&verify_data.token, TokenPurpose::VerifyEmail, &config.keys.verification_keys)?;
+
+    let now = Utc::now().timestamp();
+    let user = user_repo.find_user_by_id(claims.user_id()?).await?
+        .filter(|user| !user.is_deleted(now) && !user.email_verified)
+        .filter(|user| user.email.as_deref() == Some(claims.binding.as_str()))
+        .ok_or_else(AppError::invalid_token)?;
+    user_repo.mark_email_verified(user.id).await?;
+
+    Ok(HttpResponse::Ok().body("Email verified"))
+}
@@ -1,14 +1,193 @@
+use crate::domain::login_attempt_repository::ThrottlePolicy;
+use crate::errors::AppError;
+use crate::infrastructure::authentication::HashingParams;
+use crate::infrastructure::keys::{keyring_from_env, Keyring};
+use shared::policy::{RegistrationPolicy, BCRYPT_MAX_PASSWORD_BYTES};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Where user accounts are stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UserStore {
+    /// The database behind `DATABASE_URL`, Scylla unless the URL says otherwise.
+    Database,
+    Memory,
+}
+
+/// How mail leaves the service.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MailTransport {
+    /// An SMTP relay, see `SmtpMailer::new` for the URL.
+    Smtp(String),
+    /// One file per mail in the directory.
+    File(PathBuf),
+    /// Only the log, links and all. For local development, see `MAIL_LOG_ONLY`.
+    Log,
+}
+
 #[derive(Clone)]
 pub struct Config {
-    pub jwt_secret: String,
+    pub keys: Keyring,
     pub db_url: String,
+    pub user_store: UserStore,
+    /// Lifetime of access tokens in seconds.
+    pub access_token_ttl: i64,
+    /// Lifetime of refresh tokens in seconds, renewed on every refresh.
+    pub refresh_token_ttl: i64,
+    /// Seconds after a refresh during which the token it replaced still gets an access
+    /// token, for tabs that refreshed at the same time. Later uses count as theft.
+    pub refresh_reuse_grace: i64,
+    /// Failed login throttling per username.
+    pub account_throttle: ThrottlePolicy,
+    /// Failed login throttling per client address, looser since addresses can be shared.
+    pub ip_throttle: ThrottlePolicy,
+    /// Rules for new usernames and passwords, the client checks the default one.
+    pub registration_policy: RegistrationPolicy,
+    /// Cost of new password hashes, older hashes are upgraded when their users log in.
+    pub password_hashing: HashingParams,
+    /// How many passwords may be hashed at once.
+    pub hashing_concurrency: usize,
+    /// How long a request waits for a hashing slot before failing as busy.
+    pub hashing_queue_timeout: Duration,
+    /// Seconds between a user asking to delete their account and it being gone.
+    pub account_deletion_grace: i64,
+    /// How often accounts past their deletion grace period are removed for good.
+    pub account_purge_interval: Duration,
+    /// Base URLs of the game servers told about bans as they happen.
+    pub game_server_urls: Vec<String>,
+    /// Shared secret between this service and the game servers for the `/internal`
+    /// endpoints. Without one they are closed and nothing is pushed.
+    pub internal_api_token: Option<String>,
+    pub mail_transport: MailTransport,
+    /// Sender of every mail, e.g. `Inner Shelter <noreply@innershelter.org>`.
+    pub mail_from: String,
+    /// Where players open the client, links in mails point there.
+    pub public_url: String,
+    /// Lifetime of email verification links in seconds.
+    pub email_verification_ttl: i64,
+    /// Lifetime of password reset links in seconds.
+    pub password_reset_ttl: i64,
+    /// How long, in seconds, a login may wait for its second factor after the password.
+    pub mfa_challenge_ttl: i64,
 }
 
 impl Config {
-    pub fn new() -> Self {
-        Self {
-            jwt_secret: std::env::var("JWT_SECRET").unwrap_or("my_secret_key".to_string()),
+    /// Reads the configuration from the environment, failing if it is unsafe to run with.
+    pub fn new() -> Result<Self, AppError> {
+        Ok(Self {
+            keys: keyring_from_env()?,
             db_url: std::env::var("DATABASE_URL").unwrap_or("127.0.0.1:9042".to_string()),
-        }
+            user_store: match std::env::var("USER_STORE").as_deref() {
+                Ok("memory") => UserStore::Memory,
+                _ => UserStore::Database,
+            },
+            access_token_ttl: std::env::var("ACCESS_TOKEN_TTL")
+                .ok()
+                .and_then(|ttl| ttl.parse().ok())
+                .unwrap_or(15 * 60),
+            refresh_token_ttl: std::env::var("REFRESH_TOKEN_TTL")
+                .ok()
+                .and_then(|ttl| ttl.parse().ok())
+                .unwrap_or(30 * 24 * 60 * 60),
+            refresh_reuse_grace: env_or("REFRESH_REUSE_GRACE", 10),
+            account_throttle: ThrottlePolicy {
+                free_attempts: env_or("LOGIN_FREE_ATTEMPTS", 3),
+                base_delay: env_or("LOGIN_BASE_DELAY", 1),
+                lockout_after: env_or("LOGIN_LOCKOUT_AFTER", 10),
+                lockout_duration: env_or("LOGIN_LOCKOUT_DURATION", 15 * 60),
+            },
+            ip_throttle: ThrottlePolicy {
+                free_attempts: env_or("LOGIN_IP_FREE_ATTEMPTS", 10),
+                base_delay: env_or("LOGIN_IP_BASE_DELAY", 1),
+                lockout_after: env_or("LOGIN_IP_LOCKOUT_AFTER", 50),
+                lockout_duration: env_or("LOGIN_IP_LOCKOUT_DURATION", 15 * 60),
+            },
+            registration_policy: registration_policy_from_env()?,
+            // The OWASP recommended minimum for Argon2id
+            password_hashing: HashingParams {
+                memory_cost: env_or("ARGON2_MEMORY_COST", 19 * 1024),
+                iterations: env_or("ARGON2_ITERATIONS", 2),
+                parallelism: env_or("ARGON2_PARALLELISM", 1),
+            },
+            hashing_concurrency: env_or(
+                "HASHING_CONCURRENCY",
+                std::thread::available_parallelism().map_or(1, |cores| cores.get()),
+            ),
+            hashing_queue_timeout: Duration::from_millis(env_or("HASHING_QUEUE_TIMEOUT_MS", 2000)),
+            account_deletion_grace: env_or("ACCOUNT_DELETION_GRACE", 30 * 24 * 60 * 60),
+            account_purge_interval: Duration::from_secs(env_or("ACCOUNT_PURGE_INTERVAL", 60 * 60)),
+            game_server_urls: std::env::var("GAME_SERVER_URLS")
+                .unwrap_or("http://127.0.0.1:8081".to_string())
+                .split(',')
+                .map(|url| url.trim().trim_end_matches('/').to_string())
+                .filter(|url| !url.is_empty())
+                .collect(),
+            internal_api_token: std::env::var("INTERNAL_API_TOKEN").ok().filter(|token| !token.is_empty()),
+            mail_transport: mail_transport_from_env()?,
+            mail_from: std::env::var("MAIL_FROM")
+                .unwrap_or("Inner Shelter <noreply@innershelter.org>".to_string()),
+            public_url: std::env::var("PUBLIC_URL")
+                .unwrap_or("http://innershelter.org:8082".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            email_verification_ttl: env_or("EMAIL_VERIFICATION_TTL", 2 * 24 * 60 * 60),
+            password_reset_ttl: env_or("PASSWORD_RESET_TTL", 60 * 60),
+            mfa_challenge_ttl: env_or("MFA_CHALLENGE_TTL", 5 * 60),
+        })
+    }
+}
+
+/// The default registration policy with any limits set in the environment instead.
+fn registration_policy_from_env() -> Result<RegistrationPolicy, AppError> {
+    let default = RegistrationPolicy::default();
+    let policy = RegistrationPolicy {
+        min_username_length: env_or("USERNAME_MIN_LENGTH", default.min_username_length),
+        max_username_length: env_or("USERNAME_MAX_LENGTH", default.max_username_length),
+        reserved_usernames: match std::env::var("RESERVED_USERNAMES") {
+            Ok(names) => names
+                .split(',')
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .collect(),
+            Err(_) => default.reserved_usernames,
+        },
+        min_password_length: env_or("PASSWORD_MIN_LENGTH", default.min_password_length),
+        max_password_bytes: env_or("PASSWORD_MAX_BYTES", default.max_password_bytes),
+    };
+
+    if policy.min_username_length > policy.max_username_length {
+        return Err(AppError::ConfigError(
+            "USERNAME_MIN_LENGTH is above USERNAME_MAX_LENGTH, nobody could register".into(),
+        ));
     }
+    if policy.min_password_length > policy.max_password_bytes.min(BCRYPT_MAX_PASSWORD_BYTES) {
+        return Err(AppError::ConfigError(
+            "PASSWORD_MIN_LENGTH is above PASSWORD_MAX_BYTES, nobody could register".into(),
+        ));
+    }
+    Ok(policy)
+}
+
+/// SMTP if configured, else files. Mails carry login links, so they only end up in the log
+/// when asked for explicitly, and a service that cannot send them does not start.
+fn mail_transport_from_env() -> Result<MailTransport, AppError> {
+    if let Ok(url) = std::env::var("SMTP_URL") {
+        return Ok(MailTransport::Smtp(url));
+    }
+    if let Ok(dir) = std::env::var("MAIL_DIR") {
+        return Ok(MailTransport::File(PathBuf::from(dir)));
+    }
+    if env_or("MAIL_LOG_ONLY", false) {
+        return Ok(MailTransport::Log);
+    }
+    Err(AppError::ConfigError(
+        "Set SMTP_URL or MAIL_DIR for mail, or MAIL_LOG_ONLY=true to only log it during development".into(),
+    ))
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
 }
@@ -0,0 +1,59 @@
+use crate::errors::AppError;
+
+/// Recent failed logins for one throttling key, an account (`user:<name>`) or a client
+/// address (`ip:<addr>`).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LoginAttempts {
+    pub failures: u32,
+    /// Unix timestamp in seconds before which logins for the key are refused.
+    pub blocked_until: i64,
+    /// Unix timestamp in seconds after which the failures are forgotten.
+    pub expires_at: i64,
+}
+
+/// How hard to throttle one kind of key. The first `free_attempts` failures cost nothing,
+/// every further one doubles the wait starting at `base_delay`, and `lockout_after`
+/// failures lock the key for `lockout_duration`. All durations are in seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThrottlePolicy {
+    pub free_attempts: u32,
+    pub base_delay: i64,
+    pub lockout_after: u32,
+    pub lockout_duration: i64,
+}
+
+impl ThrottlePolicy {
+    /// The attempts after one more failure at `now`.
+    pub fn record_failure(&self, attempts: &LoginAttempts, now: i64) -> LoginAttempts {
+        self.after_failures(attempts.failures.saturating_add(1), now)
+    }
+
+    /// The attempts once the key has failed `failures` times, the last of them at `now`.
+    pub fn after_failures(&self, failures: u32, now: i64) -> LoginAttempts {
+        let delay = if failures >= self.lockout_after {
+            self.lockout_duration
+        } else if failures > self.free_attempts {
+            let doublings = (failures - self.free_attempts - 1).min(32);
+            self.base_delay.saturating_mul(1 << doublings).min(self.lockout_duration)
+        } else {
+            0
+        };
+
+        LoginAttempts {
+            failures,
+            blocked_until: now + delay,
+            // Remember failures for a lockout's length after the last one
+            expires_at: now + delay.max(self.lockout_duration),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+pub trait LoginAttemptRepository: Send + Sync {
+    /// The key's attempts, unless they expired.
+    async fn find(&self, key: &str) -> Result<Option<LoginAttempts>, AppError>;
+    /// Counts one more failure against the key and returns the attempts after it. Atomic, so
+    /// failures sent in parallel are all counted.
+    async fn record_failure(&self, key: &str, policy: &ThrottlePolicy) -> Result<LoginAttempts, AppError>;
+    async fn clear(&self, key: &str) -> Result<(), AppError>;
+}
@@ -0,0 +1,14 @@
+use crate::errors::AppError;
+
+/// A plain text mail to a single recipient.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait::async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: Mail) -> Result<(), AppError>;
+}
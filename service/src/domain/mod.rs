@@ -1,2 +1,6 @@
-pub mod auth;
-pub mod user_repository;
\ No newline at end of file
+pub mod login_attempt_repository;
+pub mod mailer;
+pub mod refresh_token_repository;
+pub mod revoked_token_repository;
+pub mod used_challenge_repository;
+pub mod user_repository;
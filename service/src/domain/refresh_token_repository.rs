@@ -0,0 +1,47 @@
+use crate::errors::AppError;
+use uuid::Uuid;
+
+/// All the refresh tokens descending from one login. Only the most recently issued
+/// token of a family is valid; presenting an older one means it was stolen or replayed,
+/// and the whole family is revoked. A family is what the user sees as a session.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RefreshTokenFamily {
+    pub family_id: String,
+    pub user_id: Uuid,
+    /// SHA-256 of the current token's secret, the secret itself is never stored.
+    pub current_token_hash: String,
+    /// The current hash before the last rotation. Another tab refreshing at the same time
+    /// presents it, see `Config::refresh_reuse_grace`.
+    pub previous_token_hash: Option<String>,
+    pub revoked: bool,
+    /// Unix timestamps in seconds.
+    pub expires_at: i64,
+    pub created_at: i64,
+    /// When the family was last rotated, i.e. the session last renewed its access token.
+    pub last_seen_at: i64,
+    /// The `User-Agent` and address of the login, to let the user recognise the session.
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+#[async_trait::async_trait]
+pub trait RefreshTokenRepository: Send + Sync {
+    async fn create_family(&self, family: &RefreshTokenFamily) -> Result<(), AppError>;
+    async fn find_family(&self, family_id: &str) -> Result<Option<RefreshTokenFamily>, AppError>;
+    /// Every family of the user still on record, revoked and expired ones included.
+    async fn list_user_families(&self, user_id: Uuid) -> Result<Vec<RefreshTokenFamily>, AppError>;
+    /// Atomically replaces the current token hash if it is still `expected_hash` and the
+    /// family is not revoked, keeping `expected_hash` as the previous one and recording
+    /// `seen_at` as its last use. Returns false if another request rotated it first.
+    async fn rotate(
+        &self,
+        family_id: &str,
+        expected_hash: &str,
+        new_hash: &str,
+        expires_at: i64,
+        seen_at: i64,
+    ) -> Result<bool, AppError>;
+    async fn revoke_family(&self, family_id: &str) -> Result<(), AppError>;
+    /// Revokes every family of the user except `keep`, ending their other sessions.
+    async fn revoke_user_families(&self, user_id: Uuid, keep: Option<&str>) -> Result<(), AppError>;
+}
@@ -0,0 +1,10 @@
+use crate::errors::AppError;
+use shared::api::auth::RevokedToken;
+
+#[async_trait::async_trait]
+pub trait RevokedTokenRepository: Send + Sync {
+    async fn revoke(&self, token: &RevokedToken) -> Result<(), AppError>;
+    /// Every revoked token that has not expired yet.
+    async fn list_revoked(&self) -> Result<Vec<RevokedToken>, AppError>;
+    async fn is_revoked(&self, jti: &str) -> Result<bool, AppError>;
+}
@@ -0,0 +1,12 @@
+use crate::errors::AppError;
+
+/// Remembers spent second factor challenges until they expire, apart from the revoked
+/// access tokens, which are published to the game servers.
+#[async_trait::async_trait]
+pub trait UsedChallengeRepository: Send + Sync {
+    /// Marks the challenge as used. `false` if it already was, then the caller lost the
+    /// race for it, even against a concurrent request.
+    async fn use_challenge(&self, challenge_id: &str, expires_at: i64) -> Result<bool, AppError>;
+    /// Makes a challenge usable again, after the code sent with it turned out wrong.
+    async fn release_challenge(&self, challenge_id: &str) -> Result<(), AppError>;
+}
@@ -1,7 +1,135 @@
+use chrono::Utc;
+use shared::api::admin::Ban;
+use uuid::Uuid;
 use crate::errors::AppError;
 
+/// The role every new account starts with.
+pub const DEFAULT_ROLE: &str = "player";
+
+/// An account. The id never changes and is what tokens and sessions refer to, so the
+/// username can be changed without invalidating them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct User {
+    pub id: Uuid,
+    pub username: String,
+    /// Shown to other players, defaults to the username.
+    pub display_name: String,
+    pub password_hash: String,
+    pub roles: Vec<String>,
+    /// Unix timestamp in seconds.
+    pub created_at: i64,
+    /// Unix timestamp in seconds of the last successful login.
+    pub last_login_at: Option<i64>,
+    /// Disabled accounts cannot log in or refresh their sessions. Unlike a ban, this is
+    /// not shown to the player and lasts until staff enable the account again.
+    pub disabled: bool,
+    /// The user has to choose a new password before playing again.
+    pub password_reset_required: bool,
+    /// Unix timestamp in seconds when the user asked to delete the account takes effect.
+    /// Logging in before then cancels it, afterwards the account is treated as gone.
+    pub deletion_scheduled_at: Option<i64>,
+    /// The latest ban, which may have run out already, see `User::active_ban`.
+    pub ban: Option<Ban>,
+    pub email: Option<String>,
+    /// Whether the user followed the link mailed to `email`, only then are password
+    /// resets sent there.
+    pub email_verified: bool,
+    /// Two-factor authentication, once enrolled.
+    pub totp: Option<Totp>,
+}
+
+/// A TOTP enrolment. Only once `enabled` does login ask for a code, until then the user
+/// has yet to prove their authenticator app produces the right ones.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Totp {
+    /// Base32, as shared with the authenticator app.
+    pub secret: String,
+    pub enabled: bool,
+    /// Hashes of the recovery codes not used yet, see `totp::hash_recovery_code`.
+    pub recovery_codes: Vec<String>,
+    /// The time step of the last code accepted. Codes from it or earlier are refused, so
+    /// each code works once.
+    pub last_used_step: Option<i64>,
+}
+
+impl User {
+    /// A fresh account with a new id and the default role.
+    pub fn new(username: &str, password_hash: String) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            username: username.to_string(),
+            display_name: username.to_string(),
+            password_hash,
+            roles: vec![DEFAULT_ROLE.to_string()],
+            created_at: Utc::now().timestamp(),
+            last_login_at: None,
+            disabled: false,
+            password_reset_required: false,
+            deletion_scheduled_at: None,
+            ban: None,
+            email: None,
+            email_verified: false,
+            totp: None,
+        }
+    }
+
+    /// Whether logging in takes a second factor.
+    pub fn has_totp(&self) -> bool {
+        self.totp.as_ref().is_some_and(|totp| totp.enabled)
+    }
+
+    /// Whether the grace period of a requested deletion is over.
+    pub fn is_deleted(&self, now: i64) -> bool {
+        self.deletion_scheduled_at.is_some_and(|at| at <= now)
+    }
+
+    /// Whether existing sessions may go on: not disabled, banned or awaiting deletion.
+    pub fn is_active(&self, now: i64) -> bool {
+        !self.disabled && self.active_ban(now).is_none() && self.deletion_scheduled_at.is_none()
+    }
+
+    /// The ban keeping the user out right now, if any.
+    pub fn active_ban(&self, now: i64) -> Option<&Ban> {
+        self.ban.as_ref().filter(|ban| ban.is_active(now))
+    }
+}
+
 #[async_trait::async_trait]
 pub trait UserRepository: Send + Sync {
-    async fn find_user_by_username(&self, username: &str) -> Result<Option<String>, AppError>;
-    async fn create_user(&self, username: &str, password: String) -> Result<(), AppError>;
+    async fn find_user_by_username(&self, username: &str) -> Result<Option<User>, AppError>;
+    async fn find_user_by_id(&self, id: Uuid) -> Result<Option<User>, AppError>;
+    /// Creates the user only if the username is free, atomically, so concurrent registrations
+    /// cannot overwrite each other. Fails with `AppError::username_taken()` if it is taken.
+    async fn create_user(&self, user: &User) -> Result<(), AppError>;
+    /// Replaces the stored password hash of an existing user, which also satisfies a
+    /// required password reset.
+    async fn update_password(&self, id: Uuid, password_hash: String) -> Result<(), AppError>;
+    /// Remembers when the user last logged in.
+    async fn record_login(&self, id: Uuid, at: i64) -> Result<(), AppError>;
+    async fn update_display_name(&self, id: Uuid, display_name: String) -> Result<(), AppError>;
+    /// Schedules the account's deletion for the given time, or cancels it with `None`.
+    async fn schedule_deletion(&self, id: Uuid, at: Option<i64>) -> Result<(), AppError>;
+    /// Removes the user for good, freeing their username.
+    async fn delete_user(&self, id: Uuid) -> Result<(), AppError>;
+    /// Up to `limit` users in the store's order, starting after the given username.
+    async fn list_users(&self, after: Option<&str>, limit: usize) -> Result<Vec<User>, AppError>;
+    async fn set_roles(&self, id: Uuid, roles: Vec<String>) -> Result<(), AppError>;
+    async fn set_disabled(&self, id: Uuid, disabled: bool) -> Result<(), AppError>;
+    /// Bans the user, replacing any earlier ban, or lifts the ban with `None`.
+    async fn set_ban(&self, id: Uuid, ban: Option<Ban>) -> Result<(), AppError>;
+    /// Every user banned as of `now`, for game servers to refuse.
+    async fn list_active_bans(&self, now: i64) -> Result<Vec<(Uuid, Ban)>, AppError>;
+    /// Makes the user choose a new password at their next login.
+    async fn require_password_reset(&self, id: Uuid) -> Result<(), AppError>;
+    async fn mark_email_verified(&self, id: Uuid) -> Result<(), AppError>;
+    /// Replaces the user's email, which then has to be verified again.
+    async fn update_email(&self, id: Uuid, email: String) -> Result<(), AppError>;
+    /// Replaces the user's TOTP enrolment, or removes it with `None`.
+    async fn set_totp(&self, id: Uuid, totp: Option<Totp>) -> Result<(), AppError>;
+    /// Removes the recovery code with the given hash, atomically, so it works only once.
+    /// Returns whether it was there to remove.
+    async fn use_recovery_code(&self, id: Uuid, code_hash: &str) -> Result<bool, AppError>;
+    /// Records `step` as the time step of the last accepted TOTP code, atomically and
+    /// only if it is later than the recorded one. Returns whether it was.
+    async fn use_totp_step(&self, id: Uuid, step: i64) -> Result<bool, AppError>;
 }
@@ -1,40 +1,173 @@
+use actix_web::http::header;
 use actix_web::{HttpResponse, ResponseError};
+use chrono::DateTime;
+use shared::api::admin::Ban;
+use shared::api::auth::MfaChallenge;
+use shared::api::error::{ApiError, ErrorCode};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum AppError {
     #[error("Database error: {0}")]
     DbError(String),
 
+    /// Missing or unusable session, the detail is only logged.
     #[error("Authentication error: {0}")]
     AuthError(String),
 
+    #[error("Invalid credentials")]
+    CredentialsError,
+
+    /// Authenticated, but not allowed to do this, the detail is only logged.
+    #[error("Forbidden: {0}")]
+    ForbiddenError(String),
+
+    /// The credentials are right but the account is banned, the ban is shown to the user.
+    #[error("Banned: {}", .0.reason)]
+    BannedError(Ban),
+
+    /// The credentials are right but an administrator requires a new password first.
+    #[error("Password reset required")]
+    PasswordResetRequired,
+
+    /// The password is right but the account has two-factor authentication, the login
+    /// goes on with the challenge.
+    #[error("Second factor required")]
+    MfaRequired(MfaChallenge),
+
+    /// What was looked up does not exist, the detail is only logged.
+    #[error("Not found: {0}")]
+    NotFoundError(String),
+
     #[error("Invalid input: {0}")]
-    ValidationError(String),
+    ValidationError(ApiError),
+
+    #[error("Conflict: {0}")]
+    ConflictError(ApiError),
+
+    /// Too many failed attempts, retry after the given number of seconds.
+    #[error("Rate limited for {0} seconds")]
+    RateLimitError(i64),
+
+    /// Too much work queued, e.g. password hashing, the client should retry shortly.
+    #[error("Service busy")]
+    BusyError,
+
+    #[error("Configuration error: {0}")]
+    ConfigError(String),
+
+    #[error("Mail error: {0}")]
+    MailError(String),
 
     #[error("Internal server error")]
     InternalError,
 }
 
+impl AppError {
+    pub fn username_taken() -> Self {
+        AppError::ConflictError(
+            ApiError::new(ErrorCode::UsernameTaken, "Username already taken").with_field("username"),
+        )
+    }
+
+    /// A second factor code that is wrong, or a recovery code already used.
+    pub fn invalid_code() -> Self {
+        AppError::ValidationError(
+            ApiError::new(ErrorCode::InvalidCredentials, "The code is incorrect").with_field("code"),
+        )
+    }
+
+    /// A mailed link that is malformed, expired, already used or meant for something else.
+    pub fn invalid_token() -> Self {
+        AppError::ValidationError(ApiError::invalid("token", "This link is invalid or has expired"))
+    }
+}
+
 impl ResponseError for AppError {
     fn error_response(&self) -> HttpResponse {
         match self {
             AppError::DbError(msg) => {
                 log::error!("Database error: {}", msg);
-                HttpResponse::InternalServerError().body("Database error")
+                HttpResponse::InternalServerError().json(internal_error())
             },
             AppError::AuthError(msg) => {
                 log::warn!("Authentication error: {}", msg);
-                HttpResponse::Unauthorized().body("Authentication failed")
+                HttpResponse::Unauthorized().json(ApiError::new(ErrorCode::Unauthorized, "Authentication failed"))
+            },
+            AppError::CredentialsError => {
+                log::warn!("Authentication error: invalid credentials");
+                HttpResponse::Unauthorized().json(ApiError::new(ErrorCode::InvalidCredentials, "Invalid username or password"))
+            },
+            AppError::ForbiddenError(msg) => {
+                log::warn!("Forbidden: {}", msg);
+                HttpResponse::Forbidden().json(ApiError::new(ErrorCode::Forbidden, "You are not allowed to do that"))
+            },
+            AppError::BannedError(ban) => {
+                log::warn!("Login refused, the account is banned");
+                let message = match ban.until.and_then(|until| DateTime::from_timestamp(until, 0)) {
+                    Some(until) => format!("You are banned until {}: {}", until.format("%Y-%m-%d %H:%M UTC"), ban.reason),
+                    None => format!("You are banned: {}", ban.reason),
+                };
+                HttpResponse::Forbidden().json(ApiError::new(ErrorCode::Banned, message).with_ban(ban.clone()))
+            },
+            AppError::PasswordResetRequired => {
+                log::info!("Login refused until the password is reset");
+                HttpResponse::Forbidden().json(ApiError::new(
+                    ErrorCode::PasswordResetRequired,
+                    "You need to choose a new password",
+                ))
+            },
+            AppError::MfaRequired(challenge) => {
+                log::info!("Login waiting for the second factor");
+                HttpResponse::Unauthorized().json(
+                    ApiError::new(ErrorCode::MfaRequired, "Enter the code from your authenticator app")
+                        .with_mfa_challenge(challenge.clone()),
+                )
+            },
+            AppError::NotFoundError(msg) => {
+                log::warn!("Not found: {}", msg);
+                HttpResponse::NotFound().json(ApiError::new(ErrorCode::NotFound, "Not found"))
             },
-            AppError::ValidationError(msg) => {
-                log::warn!("Validation error: {}", msg);
-                HttpResponse::BadRequest().body(msg.clone())
+            AppError::ValidationError(error) => {
+                log::warn!("Validation error: {}", error);
+                HttpResponse::BadRequest().json(error)
+            },
+            AppError::ConflictError(error) => {
+                log::warn!("Conflict: {}", error);
+                HttpResponse::Conflict().json(error)
+            },
+            AppError::RateLimitError(retry_after) => {
+                log::warn!("Rate limited for {} seconds", retry_after);
+                HttpResponse::TooManyRequests()
+                    .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+                    .json(ApiError::new(
+                        ErrorCode::RateLimited,
+                        format!("Too many attempts, try again in {} seconds", retry_after),
+                    ))
+            },
+            AppError::BusyError => {
+                log::warn!("Service busy, rejecting request");
+                HttpResponse::ServiceUnavailable()
+                    .insert_header((header::RETRY_AFTER, "1"))
+                    .json(ApiError::new(ErrorCode::ServiceBusy, "The service is busy, try again shortly"))
+            },
+            AppError::ConfigError(msg) => {
+                log::error!("Configuration error: {}", msg);
+                HttpResponse::InternalServerError().json(internal_error())
+            },
+            AppError::MailError(msg) => {
+                log::error!("Mail error: {}", msg);
+                HttpResponse::InternalServerError().json(internal_error())
             },
             AppError::InternalError => {
                 log::error!("Internal server error");
-                HttpResponse::InternalServerError().body("Internal server error")
+                HttpResponse::InternalServerError().json(internal_error())
             },
         }
     }
 }
+
+fn internal_error() -> ApiError {
+    ApiError::new(ErrorCode::Internal, "Internal server error")
+}
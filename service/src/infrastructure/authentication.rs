@@ -1,26 +1,180 @@
-use bcrypt::{hash, verify, DEFAULT_COST};
-use jsonwebtoken::{encode, Header, EncodingKey};
-use chrono::{Utc, Duration as ChronoDuration};
-use crate::domain::auth::Claims;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{self, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chrono::Utc;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use shared::token::{self, Claims, SigningKey, VerificationKeys};
+use uuid::Uuid;
+use crate::domain::user_repository::User;
 use crate::errors::AppError;
 
-pub fn hash_password(password: &str) -> Result<String, AppError> {
-    hash(password, DEFAULT_COST)
+/// Argon2id cost parameters for new password hashes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HashingParams {
+    /// Memory in KiB.
+    pub memory_cost: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+fn argon2(params: &HashingParams) -> Result<Argon2<'static>, AppError> {
+    let params = Params::new(params.memory_cost, params.iterations, params.parallelism, None)
+        .map_err(|e| AppError::ConfigError(e.to_string()))?;
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+/// Hashes a new password with Argon2id, as a PHC string carrying its own parameters.
+pub fn hash_password(password: &str, params: &HashingParams) -> Result<String, AppError> {
+    let salt = SaltString::generate(&mut OsRng);
+    argon2(params)?
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
         .map_err(|e| AppError::AuthError(e.to_string()))
 }
 
+/// Checks a password against a stored Argon2 hash, or a bcrypt one from before Argon2.
 pub fn verify_password(password: &str, hashed: &str) -> Result<bool, AppError> {
-    verify(password, hashed)
-        .map_err(|e| AppError::AuthError(e.to_string()))
+    if hashed.starts_with("$2") {
+        return bcrypt::verify(password, hashed)
+            .map_err(|e| AppError::AuthError(e.to_string()));
+    }
+
+    let parsed = PasswordHash::new(hashed)
+        .map_err(|e| AppError::AuthError(e.to_string()))?;
+    match Argon2::default().verify_password(password.as_bytes(), &parsed) {
+        Ok(()) => Ok(true),
+        Err(password_hash::Error::Password) => Ok(false),
+        Err(e) => Err(AppError::AuthError(e.to_string())),
+    }
 }
 
-pub fn generate_jwt(username: &str, secret: &[u8]) -> Result<String, AppError> {
-    let expiration = Utc::now() + ChronoDuration::hours(1);
-    let claims = Claims {
-        sub: username.to_string(),
-        exp: expiration.timestamp() as usize,
+/// Whether a stored hash should be replaced on the next successful login, because it is
+/// not Argon2id or was made with other parameters than the configured ones.
+pub fn needs_rehash(hashed: &str, params: &HashingParams) -> bool {
+    let Ok(parsed) = PasswordHash::new(hashed) else {
+        return true;
     };
-    encode(&Header::default(), &claims, &EncodingKey::from_secret(secret))
+    if parsed.algorithm != argon2::ARGON2ID_IDENT {
+        return true;
+    }
+
+    match Params::try_from(&parsed) {
+        Ok(stored) => {
+            stored.m_cost() != params.memory_cost
+                || stored.t_cost() != params.iterations
+                || stored.p_cost() != params.parallelism
+        }
+        Err(_) => true,
+    }
+}
+
+/// Issues an access token for the user in the session `session_id`, returning it with its claims.
+pub fn generate_jwt(
+    user: &User,
+    session_id: &str,
+    signing_key: &SigningKey,
+    ttl: i64,
+) -> Result<(String, Claims), AppError> {
+    token::issue(&user.id.to_string(), &user.username, user.roles.clone(), Some(session_id), ttl, signing_key)
+        .map_err(|e| AppError::AuthError(e.to_string()))
+}
+
+pub fn decode_jwt(token: &str, keys: &VerificationKeys) -> Result<Claims, AppError> {
+    token::validate(token, keys)
         .map_err(|e| AppError::AuthError(e.to_string()))
 }
 
+/// A random, URL-safe secret for an opaque refresh token.
+pub fn generate_refresh_secret() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Refresh secrets are stored hashed so a leaked table cannot be replayed. They are
+/// random and high-entropy, a fast hash is enough.
+pub fn hash_refresh_secret(secret: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(secret.as_bytes()))
+}
+
+/// What a single purpose token, mailed or handed out mid-login, lets its holder do. Each
+/// purpose is its own audience, so a token for one cannot be used for another, nor as an
+/// access token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenPurpose {
+    VerifyEmail,
+    PasswordReset,
+    /// Finishing a login with the second factor.
+    MfaChallenge,
+}
+
+impl TokenPurpose {
+    fn audience(&self) -> &'static str {
+        match self {
+            TokenPurpose::VerifyEmail => "inner-shelter-verify-email",
+            TokenPurpose::PasswordReset => "inner-shelter-password-reset",
+            TokenPurpose::MfaChallenge => "inner-shelter-mfa-challenge",
+        }
+    }
+}
+
+/// Claims of a purpose token. `binding` ties it to the state it depends on, the email it
+/// verifies or a fingerprint of the password it replaces or was proven with, so it stops
+/// working once that changes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PurposeTokenClaims {
+    pub sub: String,
+    pub iss: String,
+    pub aud: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub jti: String,
+    pub binding: String,
+}
+
+impl PurposeTokenClaims {
+    pub fn user_id(&self) -> Result<Uuid, AppError> {
+        Uuid::parse_str(&self.sub).map_err(|_| AppError::invalid_token())
+    }
+}
+
+pub fn generate_purpose_token(
+    user_id: Uuid,
+    purpose: TokenPurpose,
+    binding: String,
+    ttl: i64,
+    signing_key: &SigningKey,
+) -> Result<String, AppError> {
+    let now = Utc::now().timestamp();
+    let claims = PurposeTokenClaims {
+        sub: user_id.to_string(),
+        iss: token::ISSUER.to_string(),
+        aud: purpose.audience().to_string(),
+        iat: now,
+        exp: now + ttl,
+        jti: Uuid::new_v4().to_string(),
+        binding,
+    };
+    token::sign(&claims, signing_key).map_err(|e| AppError::AuthError(e.to_string()))
+}
+
+/// Fails with `AppError::invalid_token()` for anything but an unexpired token of the purpose.
+pub fn decode_purpose_token(
+    purpose_token: &str,
+    purpose: TokenPurpose,
+    keys: &VerificationKeys,
+) -> Result<PurposeTokenClaims, AppError> {
+    token::verify(purpose_token, keys, purpose.audience()).map_err(|e| {
+        log::warn!("Rejected a {:?} token: {}", purpose, e);
+        AppError::invalid_token()
+    })
+}
+
+/// Identifies the password a token is bound to without putting its hash in the token.
+pub fn password_fingerprint(password_hash: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(password_hash.as_bytes()))
+}
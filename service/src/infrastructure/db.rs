@@ -1,7 +1,173 @@
 use scylla::transport::session::{Session, SessionConfig};
+use std::collections::HashSet;
 use std::sync::Arc;
 use crate::errors::AppError;
 
+/// A versioned set of CQL statements. Applied migrations are recorded in
+/// `inner_shelter.schema_migrations` and never run again, so once released a
+/// migration must not be edited; add a new one instead.
+struct Migration {
+    version: i32,
+    description: &'static str,
+    statements: &'static [&'static str],
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create users table",
+        statements: &[
+            "CREATE TABLE IF NOT EXISTS inner_shelter.users (
+                username text PRIMARY KEY,
+                password text
+            )",
+        ],
+    },
+    Migration {
+        version: 2,
+        description: "create refresh token families table",
+        statements: &[
+            "CREATE TABLE IF NOT EXISTS inner_shelter.refresh_token_families (
+                family_id text PRIMARY KEY,
+                username text,
+                current_token_hash text,
+                revoked boolean,
+                expires_at bigint
+            )",
+        ],
+    },
+    Migration {
+        version: 3,
+        description: "create revoked tokens table",
+        statements: &[
+            "CREATE TABLE IF NOT EXISTS inner_shelter.revoked_tokens (
+                jti text PRIMARY KEY,
+                expires_at bigint
+            )",
+        ],
+    },
+    Migration {
+        version: 4,
+        description: "create login attempts table",
+        statements: &[
+            "CREATE TABLE IF NOT EXISTS inner_shelter.login_attempts (
+                key text PRIMARY KEY,
+                failures int,
+                blocked_until bigint,
+                expires_at bigint
+            )",
+        ],
+    },
+    Migration {
+        version: 5,
+        description: "add user ids and profile fields",
+        statements: &[
+            "ALTER TABLE inner_shelter.users ADD (
+                id uuid,
+                display_name text,
+                roles set<text>,
+                created_at bigint,
+                last_login_at bigint,
+                disabled boolean,
+                password_reset_required boolean
+            )",
+            "CREATE TABLE IF NOT EXISTS inner_shelter.users_by_id (
+                id uuid PRIMARY KEY,
+                username text
+            )",
+            // Families of old sessions have no user id and are no longer accepted
+            "ALTER TABLE inner_shelter.refresh_token_families ADD user_id uuid",
+        ],
+    },
+    Migration {
+        version: 6,
+        description: "add account deletion and refresh token families by user",
+        statements: &[
+            "ALTER TABLE inner_shelter.users ADD deletion_scheduled_at bigint",
+            "CREATE TABLE IF NOT EXISTS inner_shelter.refresh_token_families_by_user (
+                user_id uuid,
+                family_id text,
+                PRIMARY KEY (user_id, family_id)
+            )",
+        ],
+    },
+    Migration {
+        version: 7,
+        description: "add bans",
+        statements: &[
+            "ALTER TABLE inner_shelter.users ADD (
+                ban_reason text,
+                banned_at bigint,
+                banned_until bigint
+            )",
+            // Only active bans, timed ones expire with their row, so listing them stays cheap
+            "CREATE TABLE IF NOT EXISTS inner_shelter.bans (
+                user_id uuid PRIMARY KEY,
+                reason text,
+                banned_at bigint,
+                until bigint
+            )",
+        ],
+    },
+    Migration {
+        version: 8,
+        description: "add user emails",
+        statements: &[
+            "ALTER TABLE inner_shelter.users ADD (
+                email text,
+                email_verified boolean
+            )",
+        ],
+    },
+    Migration {
+        version: 9,
+        description: "add two-factor authentication",
+        statements: &[
+            "ALTER TABLE inner_shelter.users ADD (
+                totp_secret text,
+                totp_enabled boolean,
+                totp_recovery_codes set<text>
+            )",
+        ],
+    },
+    Migration {
+        version: 10,
+        description: "add session details",
+        statements: &[
+            "ALTER TABLE inner_shelter.refresh_token_families ADD (
+                created_at bigint,
+                last_seen_at bigint,
+                user_agent text,
+                ip text
+            )",
+        ],
+    },
+    Migration {
+        version: 11,
+        description: "add the last used TOTP step",
+        statements: &[
+            "ALTER TABLE inner_shelter.users ADD totp_last_step bigint",
+        ],
+    },
+    Migration {
+        version: 12,
+        description: "keep the previous refresh token hash",
+        statements: &[
+            "ALTER TABLE inner_shelter.refresh_token_families ADD previous_token_hash text",
+        ],
+    },
+    Migration {
+        version: 13,
+        description: "create used challenges table",
+        statements: &[
+            "CREATE TABLE IF NOT EXISTS inner_shelter.used_challenges (
+                challenge_id text PRIMARY KEY,
+                expires_at bigint
+            )",
+        ],
+    },
+];
+
 pub async fn get_db_session(cassandra_uri: &str) -> Result<Arc<Session>, AppError> {
     let mut session_config = SessionConfig::new();
     session_config.add_known_node(cassandra_uri);
@@ -11,3 +177,63 @@ pub async fn get_db_session(cassandra_uri: &str) -> Result<Arc<Session>, AppErro
     
     Ok(Arc::new(session))
 }
+
+/// Creates the keyspace and applies every migration that has not been applied yet, in order.
+pub async fn run_migrations(session: &Session) -> Result<(), AppError> {
+    run_statement(
+        session,
+        "CREATE KEYSPACE IF NOT EXISTS inner_shelter
+            WITH replication = {'class': 'SimpleStrategy', 'replication_factor': 1}",
+    )
+    .await?;
+    run_statement(
+        session,
+        "CREATE TABLE IF NOT EXISTS inner_shelter.schema_migrations (
+            version int PRIMARY KEY,
+            description text,
+            applied_at timestamp
+        )",
+    )
+    .await?;
+
+    let applied: HashSet<i32> = session
+        .query_unpaged("SELECT version FROM inner_shelter.schema_migrations", &[])
+        .await
+        .map_err(|e| AppError::DbError(e.to_string()))?
+        .rows_typed_or_empty::<(i32,)>()
+        .map(|row| row.map(|(version,)| version))
+        .collect::<Result<_, _>>()
+        .map_err(|e| AppError::DbError(e.to_string()))?;
+
+    for migration in MIGRATIONS.iter().filter(|m| !applied.contains(&m.version)) {
+        log::info!("Applying migration {}: {}", migration.version, migration.description);
+
+        for statement in migration.statements {
+            run_statement(session, statement).await?;
+        }
+
+        session
+            .query_unpaged(
+                "INSERT INTO inner_shelter.schema_migrations (version, description, applied_at)
+                    VALUES (?, ?, toTimestamp(now()))",
+                (migration.version, migration.description),
+            )
+            .await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+    }
+
+    Ok(())
+}
+
+/// Runs a schema statement and waits until every node has seen the change.
+async fn run_statement(session: &Session, statement: &str) -> Result<(), AppError> {
+    session
+        .query_unpaged(statement, &[])
+        .await
+        .map_err(|e| AppError::DbError(e.to_string()))?;
+    session
+        .await_schema_agreement()
+        .await
+        .map_err(|e| AppError::DbError(e.to_string()))?;
+    Ok(())
+}
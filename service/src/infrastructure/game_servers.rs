@@ -0,0 +1,56 @@
+use crate::config::Config;
+use serde::Serialize;
+use shared::api::admin::{BanUpdate, INTERNAL_TOKEN_HEADER};
+use shared::api::auth::RevokedToken;
+
+/// Tells the game servers about changes they must act on at once, rather than at their
+/// next poll. Best effort: failures are only logged, the servers catch up when they poll.
+#[derive(Clone)]
+pub struct GameServers {
+    client: reqwest::Client,
+    urls: Vec<String>,
+    token: Option<String>,
+}
+
+impl GameServers {
+    pub fn from_config(config: &Config) -> Self {
+        if config.internal_api_token.is_none() && !config.game_server_urls.is_empty() {
+            log::warn!("INTERNAL_API_TOKEN is not set, game servers will only learn about bans and revocations late");
+        }
+        Self {
+            client: reqwest::Client::new(),
+            urls: config.game_server_urls.clone(),
+            token: config.internal_api_token.clone(),
+        }
+    }
+
+    /// Sends the ban change to every game server in the background.
+    pub fn push_ban(&self, update: BanUpdate) {
+        self.push("/internal/bans", "ban update", &update);
+    }
+
+    /// Sends a revoked token or session to every game server in the background, so they
+    /// close its connections without waiting for the next revocation poll.
+    pub fn push_revocation(&self, revocation: RevokedToken) {
+        self.push("/internal/revocations", "revocation", &revocation);
+    }
+
+    fn push<T: Serialize>(&self, path: &str, what: &'static str, body: &T) {
+        let Some(token) = self.token.clone() else {
+            return;
+        };
+
+        for url in &self.urls {
+            let request = self.client
+                .post(format!("{}{}", url, path))
+                .header(INTERNAL_TOKEN_HEADER, &token)
+                .json(body);
+            let url = url.clone();
+            actix_rt::spawn(async move {
+                if let Err(e) = request.send().await.and_then(|resp| resp.error_for_status()) {
+                    log::error!("Failed to push a {} to {}: {}", what, url, e);
+                }
+            });
+        }
+    }
+}
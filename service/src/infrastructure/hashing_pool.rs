@@ -0,0 +1,79 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use crate::config::Config;
+use crate::errors::AppError;
+use crate::infrastructure::authentication::{self, HashingParams};
+
+/// Runs password hashing on the blocking thread pool, at most `concurrency` at a time, so
+/// a burst of logins cannot stall the executor or exhaust memory. Requests that cannot
+/// start within `queue_timeout` fail with `AppError::BusyError` instead of piling up.
+#[derive(Clone)]
+pub struct HashingPool {
+    params: HashingParams,
+    permits: Arc<Semaphore>,
+    queue_timeout: Duration,
+    /// Hash of a random password with the current parameters, verified against for
+    /// unknown users so they take as long as known ones.
+    dummy_hash: Arc<str>,
+}
+
+impl HashingPool {
+    pub fn new(params: HashingParams, concurrency: usize, queue_timeout: Duration) -> Result<Self, AppError> {
+        let dummy_password = authentication::generate_refresh_secret();
+        Ok(Self {
+            params,
+            permits: Arc::new(Semaphore::new(concurrency.max(1))),
+            queue_timeout,
+            dummy_hash: authentication::hash_password(&dummy_password, &params)?.into(),
+        })
+    }
+
+    pub fn from_config(config: &Config) -> Result<Self, AppError> {
+        Self::new(config.password_hashing, config.hashing_concurrency, config.hashing_queue_timeout)
+    }
+
+    pub async fn hash(&self, password: &str) -> Result<String, AppError> {
+        let password = password.to_string();
+        let params = self.params;
+        self.run(move || authentication::hash_password(&password, &params)).await
+    }
+
+    pub async fn verify(&self, password: &str, hashed: &str) -> Result<bool, AppError> {
+        let password = password.to_string();
+        let hashed = hashed.to_string();
+        self.run(move || authentication::verify_password(&password, &hashed)).await
+    }
+
+    /// Verifies a login's password against the user's stored hash. Unknown users are
+    /// checked against a dummy hash instead, so the response takes as long either way and
+    /// does not reveal which usernames exist.
+    pub async fn verify_login(&self, password: &str, stored: Option<&str>) -> Result<bool, AppError> {
+        let matches = self.verify(password, stored.unwrap_or(&self.dummy_hash)).await?;
+        Ok(matches && stored.is_some())
+    }
+
+    /// Whether a stored hash was made with other than the current algorithm or parameters.
+    pub fn needs_rehash(&self, hashed: &str) -> bool {
+        authentication::needs_rehash(hashed, &self.params)
+    }
+
+    async fn run<T, F>(&self, f: F) -> Result<T, AppError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T, AppError> + Send + 'static,
+    {
+        let permit = tokio::time::timeout(self.queue_timeout, self.permits.clone().acquire_owned())
+            .await
+            .map_err(|_| AppError::BusyError)?
+            .map_err(|_| AppError::InternalError)?;
+
+        tokio::task::spawn_blocking(move || {
+            // Held until the hash is done, even if the request gave up waiting for it
+            let _permit = permit;
+            f()
+        })
+        .await
+        .map_err(|_| AppError::InternalError)?
+    }
+}
@@ -0,0 +1,119 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ed25519_dalek::pkcs8::{DecodePrivateKey, EncodePrivateKey};
+use ed25519_dalek::SigningKey as Ed25519Key;
+use shared::token::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, KeyAlgorithm, OctetKeyPairParameters,
+    OctetKeyPairType, PublicKeyUse,
+};
+use shared::token::{JwkSet, SigningKey, VerificationKeys};
+use std::path::Path;
+use crate::errors::AppError;
+
+/// The service's token keys: the one new tokens are signed with, and the public half of
+/// every key that is still trusted. Rotating means adding a key, switching signing to it
+/// once verifiers have picked it up, and removing the old one after its tokens expired.
+#[derive(Clone)]
+pub struct Keyring {
+    pub signing_key: SigningKey,
+    /// Published at `/.well-known/jwks.json` for the game server.
+    pub jwks: JwkSet,
+    pub verification_keys: VerificationKeys,
+}
+
+impl Keyring {
+    /// Loads every `<key id>.pem` Ed25519 private key (PKCS#8) in `dir`. Signs with
+    /// `signing_kid`, or if unset with the last key id in sort order, so date-named keys
+    /// like `2026-10.pem` pick the newest.
+    pub fn load(dir: &Path, signing_kid: Option<&str>) -> Result<Self, AppError> {
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| AppError::ConfigError(format!("Cannot read {}: {}", dir.display(), e)))?;
+
+        let mut keys = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|e| AppError::ConfigError(e.to_string()))?.path();
+            if path.extension().is_none_or(|extension| extension != "pem") {
+                continue;
+            }
+
+            let kid = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+            let pem = std::fs::read_to_string(&path)
+                .map_err(|e| AppError::ConfigError(format!("Cannot read {}: {}", path.display(), e)))?;
+            let key = Ed25519Key::from_pkcs8_pem(&pem)
+                .map_err(|e| AppError::ConfigError(format!("Invalid key {}: {}", path.display(), e)))?;
+            keys.push((kid, key));
+        }
+
+        keys.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let signing_kid = match signing_kid {
+            Some(kid) => kid.to_string(),
+            None => keys
+                .last()
+                .map(|(kid, _)| kid.clone())
+                .ok_or_else(|| AppError::ConfigError(format!("No keys in {}", dir.display())))?,
+        };
+
+        Self::from_keys(&keys, &signing_kid)
+    }
+
+    /// A single throwaway key, for local development and tests. Tokens signed with it
+    /// stop validating when the service restarts.
+    pub fn generate(kid: &str) -> Self {
+        let key = Ed25519Key::generate(&mut rand::rngs::OsRng);
+        Self::from_keys(&[(kid.to_string(), key)], kid).expect("a generated key is valid")
+    }
+
+    fn from_keys(keys: &[(String, Ed25519Key)], signing_kid: &str) -> Result<Self, AppError> {
+        let (_, signing) = keys
+            .iter()
+            .find(|(kid, _)| kid == signing_kid)
+            .ok_or_else(|| AppError::ConfigError(format!("No key with id {}", signing_kid)))?;
+        let der = signing.to_pkcs8_der().map_err(|e| AppError::ConfigError(e.to_string()))?;
+
+        let jwks = JwkSet {
+            keys: keys.iter().map(|(kid, key)| public_jwk(kid, key)).collect(),
+        };
+        let verification_keys = VerificationKeys::from_jwks(&jwks)
+            .map_err(|e| AppError::ConfigError(e.to_string()))?;
+
+        Ok(Self {
+            signing_key: SigningKey::from_ed_der(signing_kid, der.as_bytes()),
+            jwks,
+            verification_keys,
+        })
+    }
+}
+
+/// Loads the keyring from `JWT_KEYS_DIR`. Without it the service refuses to start unless
+/// `ALLOW_DEV_KEYS=true`, which signs with a throwaway key instead.
+pub fn keyring_from_env() -> Result<Keyring, AppError> {
+    if let Ok(dir) = std::env::var("JWT_KEYS_DIR") {
+        let signing_kid = std::env::var("JWT_SIGNING_KEY_ID").ok();
+        return Keyring::load(Path::new(&dir), signing_kid.as_deref());
+    }
+
+    if std::env::var("ALLOW_DEV_KEYS").is_ok_and(|flag| flag == "true") {
+        log::warn!("JWT_KEYS_DIR is unset, signing tokens with a throwaway development key");
+        return Ok(Keyring::generate("dev"));
+    }
+
+    Err(AppError::ConfigError(
+        "JWT_KEYS_DIR is unset, point it at the signing keys or set ALLOW_DEV_KEYS=true".into(),
+    ))
+}
+
+fn public_jwk(kid: &str, key: &Ed25519Key) -> Jwk {
+    Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(KeyAlgorithm::EdDSA),
+            key_id: Some(kid.to_string()),
+            ..Default::default()
+        },
+        algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: OctetKeyPairType::OctetKeyPair,
+            curve: EllipticCurve::Ed25519,
+            x: URL_SAFE_NO_PAD.encode(key.verifying_key().to_bytes()),
+        }),
+    }
+}
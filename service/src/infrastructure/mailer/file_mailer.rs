@@ -0,0 +1,35 @@
+use crate::domain::mailer::{Mail, Mailer};
+use crate::errors::AppError;
+use chrono::Utc;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// Writes every mail to its own file in `dir`, or only logs it without one. For local
+/// development and tests, where links are picked out of the mail by hand.
+pub struct FileMailer {
+    dir: Option<PathBuf>,
+}
+
+impl FileMailer {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self { dir }
+    }
+}
+
+#[async_trait::async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, mail: Mail) -> Result<(), AppError> {
+        let text = format!("To: {}\nSubject: {}\n\n{}\n", mail.to, mail.subject, mail.body);
+        let Some(dir) = &self.dir else {
+            log::info!("Mail not sent:\n{}", text);
+            return Ok(());
+        };
+
+        // Named so that they sort in the order they were sent
+        let path = dir.join(format!("{}-{}.eml", Utc::now().timestamp_millis(), Uuid::new_v4()));
+        tokio::fs::create_dir_all(dir).await
+            .map_err(|e| AppError::MailError(format!("Cannot create {}: {}", dir.display(), e)))?;
+        tokio::fs::write(&path, text).await
+            .map_err(|e| AppError::MailError(format!("Cannot write {}: {}", path.display(), e)))
+    }
+}
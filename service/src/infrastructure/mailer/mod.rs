@@ -0,0 +1,31 @@
+pub mod file_mailer;
+pub mod smtp_mailer;
+
+use crate::config::{Config, MailTransport};
+use crate::domain::mailer::{Mail, Mailer};
+use crate::errors::AppError;
+use std::sync::Arc;
+
+/// The mailer the configuration asks for.
+pub fn create_mailer(config: &Config) -> Result<Arc<dyn Mailer>, AppError> {
+    match &config.mail_transport {
+        MailTransport::Smtp(url) => Ok(Arc::new(smtp_mailer::SmtpMailer::new(url, &config.mail_from)?)),
+        MailTransport::File(dir) => Ok(Arc::new(file_mailer::FileMailer::new(Some(dir.clone())))),
+        MailTransport::Log => {
+            log::warn!("MAIL_LOG_ONLY is set, mail is only logged");
+            Ok(Arc::new(file_mailer::FileMailer::new(None)))
+        }
+    }
+}
+
+/// Sends the mail without making the request wait for it, failures are only logged.
+/// Also keeps how long a request takes from telling whether a mail went out.
+pub fn send_in_background(mailer: &Arc<dyn Mailer>, mail: Mail) {
+    let mailer = mailer.clone();
+    actix_rt::spawn(async move {
+        let to = mail.to.clone();
+        if let Err(e) = mailer.send(mail).await {
+            log::error!("Failed to send mail to {}: {}", to, e);
+        }
+    });
+}
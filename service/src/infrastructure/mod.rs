@@ -1,3 +1,10 @@
 pub mod db;
 pub mod authentication;
+pub mod game_servers;
+pub mod hashing_pool;
+pub mod keys;
+pub mod mailer;
 pub mod repository;
+pub mod totp;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
@@ -0,0 +1,43 @@
+use crate::domain::login_attempt_repository::{LoginAttemptRepository, LoginAttempts, ThrottlePolicy};
+use crate::errors::AppError;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Keeps login attempts in process memory, only enough for a single service instance.
+#[derive(Default)]
+pub struct InMemoryLoginAttemptRepository {
+    attempts: RwLock<HashMap<String, LoginAttempts>>,
+}
+
+impl InMemoryLoginAttemptRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl LoginAttemptRepository for InMemoryLoginAttemptRepository {
+    async fn find(&self, key: &str) -> Result<Option<LoginAttempts>, AppError> {
+        let attempts = self.attempts.read().map_err(|e| AppError::DbError(e.to_string()))?;
+        let now = Utc::now().timestamp();
+        Ok(attempts.get(key).filter(|attempts| attempts.expires_at > now).cloned())
+    }
+
+    async fn record_failure(&self, key: &str, policy: &ThrottlePolicy) -> Result<LoginAttempts, AppError> {
+        let mut all = self.attempts.write().map_err(|e| AppError::DbError(e.to_string()))?;
+        // Forget expired keys so guessing random usernames cannot grow the map forever
+        let now = Utc::now().timestamp();
+        all.retain(|_, attempts| attempts.expires_at > now);
+        let recorded = all.get(key).cloned().unwrap_or_default();
+        let attempts = policy.record_failure(&recorded, now);
+        all.insert(key.to_string(), attempts.clone());
+        Ok(attempts)
+    }
+
+    async fn clear(&self, key: &str) -> Result<(), AppError> {
+        let mut attempts = self.attempts.write().map_err(|e| AppError::DbError(e.to_string()))?;
+        attempts.remove(key);
+        Ok(())
+    }
+}
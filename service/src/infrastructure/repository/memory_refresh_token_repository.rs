@@ -0,0 +1,74 @@
+use crate::domain::refresh_token_repository::{RefreshTokenFamily, RefreshTokenRepository};
+use crate::errors::AppError;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use uuid::Uuid;
+
+/// Keeps refresh token families in process memory, see `InMemoryUserRepository`.
+#[derive(Default)]
+pub struct InMemoryRefreshTokenRepository {
+    families: RwLock<HashMap<String, RefreshTokenFamily>>,
+}
+
+impl InMemoryRefreshTokenRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenRepository for InMemoryRefreshTokenRepository {
+    async fn create_family(&self, family: &RefreshTokenFamily) -> Result<(), AppError> {
+        let mut families = self.families.write().map_err(|e| AppError::DbError(e.to_string()))?;
+        families.insert(family.family_id.clone(), family.clone());
+        Ok(())
+    }
+
+    async fn find_family(&self, family_id: &str) -> Result<Option<RefreshTokenFamily>, AppError> {
+        let families = self.families.read().map_err(|e| AppError::DbError(e.to_string()))?;
+        Ok(families.get(family_id).cloned())
+    }
+
+    async fn list_user_families(&self, user_id: Uuid) -> Result<Vec<RefreshTokenFamily>, AppError> {
+        let families = self.families.read().map_err(|e| AppError::DbError(e.to_string()))?;
+        Ok(families.values().filter(|family| family.user_id == user_id).cloned().collect())
+    }
+
+    async fn rotate(
+        &self,
+        family_id: &str,
+        expected_hash: &str,
+        new_hash: &str,
+        expires_at: i64,
+        seen_at: i64,
+    ) -> Result<bool, AppError> {
+        let mut families = self.families.write().map_err(|e| AppError::DbError(e.to_string()))?;
+        match families.get_mut(family_id) {
+            Some(family) if !family.revoked && family.current_token_hash == expected_hash => {
+                family.previous_token_hash = Some(expected_hash.to_string());
+                family.current_token_hash = new_hash.to_string();
+                family.expires_at = expires_at;
+                family.last_seen_at = seen_at;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn revoke_family(&self, family_id: &str) -> Result<(), AppError> {
+        let mut families = self.families.write().map_err(|e| AppError::DbError(e.to_string()))?;
+        if let Some(family) = families.get_mut(family_id) {
+            family.revoked = true;
+        }
+        Ok(())
+    }
+
+    async fn revoke_user_families(&self, user_id: Uuid, keep: Option<&str>) -> Result<(), AppError> {
+        let mut families = self.families.write().map_err(|e| AppError::DbError(e.to_string()))?;
+        families
+            .values_mut()
+            .filter(|family| family.user_id == user_id && Some(family.family_id.as_str()) != keep)
+            .for_each(|family| family.revoked = true);
+        Ok(())
+    }
+}
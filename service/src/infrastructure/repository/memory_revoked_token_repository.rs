@@ -0,0 +1,45 @@
+use crate::domain::revoked_token_repository::RevokedTokenRepository;
+use crate::errors::AppError;
+use chrono::Utc;
+use shared::api::auth::RevokedToken;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Keeps revoked tokens in process memory, see `InMemoryUserRepository`.
+#[derive(Default)]
+pub struct InMemoryRevokedTokenRepository {
+    tokens: RwLock<HashMap<String, i64>>,
+}
+
+impl InMemoryRevokedTokenRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl RevokedTokenRepository for InMemoryRevokedTokenRepository {
+    async fn revoke(&self, token: &RevokedToken) -> Result<(), AppError> {
+        let mut tokens = self.tokens.write().map_err(|e| AppError::DbError(e.to_string()))?;
+        // Forget tokens that expired in the meantime, nobody needs to know about them any more
+        let now = Utc::now().timestamp();
+        tokens.retain(|_, expires_at| *expires_at > now);
+        tokens.insert(token.jti.clone(), token.expires_at);
+        Ok(())
+    }
+
+    async fn list_revoked(&self) -> Result<Vec<RevokedToken>, AppError> {
+        let tokens = self.tokens.read().map_err(|e| AppError::DbError(e.to_string()))?;
+        let now = Utc::now().timestamp();
+        Ok(tokens
+            .iter()
+            .filter(|(_, expires_at)| **expires_at > now)
+            .map(|(jti, expires_at)| RevokedToken { jti: jti.clone(), expires_at: *expires_at })
+            .collect())
+    }
+
+    async fn is_revoked(&self, jti: &str) -> Result<bool, AppError> {
+        let tokens = self.tokens.read().map_err(|e| AppError::DbError(e.to_string()))?;
+        Ok(tokens.contains_key(jti))
+    }
+}
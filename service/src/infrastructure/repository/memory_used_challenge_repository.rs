@@ -0,0 +1,38 @@
+use crate::domain::used_challenge_repository::UsedChallengeRepository;
+use crate::errors::AppError;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Keeps used challenges in process memory, see `InMemoryUserRepository`.
+#[derive(Default)]
+pub struct InMemoryUsedChallengeRepository {
+    challenges: Mutex<HashMap<String, i64>>,
+}
+
+impl InMemoryUsedChallengeRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl UsedChallengeRepository for InMemoryUsedChallengeRepository {
+    async fn use_challenge(&self, challenge_id: &str, expires_at: i64) -> Result<bool, AppError> {
+        let mut challenges = self.challenges.lock().map_err(|e| AppError::DbError(e.to_string()))?;
+        // Expired challenges are refused before they get here
+        let now = Utc::now().timestamp();
+        challenges.retain(|_, expires_at| *expires_at > now);
+        if challenges.contains_key(challenge_id) {
+            return Ok(false);
+        }
+        challenges.insert(challenge_id.to_string(), expires_at);
+        Ok(true)
+    }
+
+    async fn release_challenge(&self, challenge_id: &str) -> Result<(), AppError> {
+        let mut challenges = self.challenges.lock().map_err(|e| AppError::DbError(e.to_string()))?;
+        challenges.remove(challenge_id);
+        Ok(())
+    }
+}
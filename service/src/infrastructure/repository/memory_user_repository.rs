@@ -0,0 +1,152 @@
+use crate::domain::user_repository::{Totp, User, UserRepository};
+use crate::errors::AppError;
+use shared::api::admin::Ban;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use uuid::Uuid;
+
+/// Keeps users in process memory, for tests and local development without a database.
+/// Everything is lost when the service stops.
+#[derive(Default)]
+pub struct InMemoryUserRepository {
+    /// Keyed by username, the lookup that happens on every login.
+    users: RwLock<HashMap<String, User>>,
+}
+
+impl InMemoryUserRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn update(&self, id: Uuid, f: impl FnOnce(&mut User)) -> Result<(), AppError> {
+        let mut users = self.users.write().map_err(|e| AppError::DbError(e.to_string()))?;
+        if let Some(user) = users.values_mut().find(|user| user.id == id) {
+            f(user);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl UserRepository for InMemoryUserRepository {
+    async fn find_user_by_username(&self, username: &str) -> Result<Option<User>, AppError> {
+        let users = self.users.read().map_err(|e| AppError::DbError(e.to_string()))?;
+        Ok(users.get(username).cloned())
+    }
+
+    async fn find_user_by_id(&self, id: Uuid) -> Result<Option<User>, AppError> {
+        let users = self.users.read().map_err(|e| AppError::DbError(e.to_string()))?;
+        Ok(users.values().find(|user| user.id == id).cloned())
+    }
+
+    async fn create_user(&self, user: &User) -> Result<(), AppError> {
+        let mut users = self.users.write().map_err(|e| AppError::DbError(e.to_string()))?;
+        match users.entry(user.username.clone()) {
+            Entry::Occupied(_) => Err(AppError::username_taken()),
+            Entry::Vacant(entry) => {
+                entry.insert(user.clone());
+                Ok(())
+            }
+        }
+    }
+
+    async fn update_password(&self, id: Uuid, password_hash: String) -> Result<(), AppError> {
+        self.update(id, |user| {
+            user.password_hash = password_hash;
+            user.password_reset_required = false;
+        })
+    }
+
+    async fn record_login(&self, id: Uuid, at: i64) -> Result<(), AppError> {
+        self.update(id, |user| user.last_login_at = Some(at))
+    }
+
+    async fn update_display_name(&self, id: Uuid, display_name: String) -> Result<(), AppError> {
+        self.update(id, |user| user.display_name = display_name)
+    }
+
+    async fn schedule_deletion(&self, id: Uuid, at: Option<i64>) -> Result<(), AppError> {
+        self.update(id, |user| user.deletion_scheduled_at = at)
+    }
+
+    async fn delete_user(&self, id: Uuid) -> Result<(), AppError> {
+        let mut users = self.users.write().map_err(|e| AppError::DbError(e.to_string()))?;
+        users.retain(|_, user| user.id != id);
+        Ok(())
+    }
+
+    async fn list_users(&self, after: Option<&str>, limit: usize) -> Result<Vec<User>, AppError> {
+        let users = self.users.read().map_err(|e| AppError::DbError(e.to_string()))?;
+        let mut page: Vec<User> = users
+            .values()
+            .filter(|user| after.is_none_or(|after| user.username.as_str() > after))
+            .cloned()
+            .collect();
+        page.sort_by(|a, b| a.username.cmp(&b.username));
+        page.truncate(limit);
+        Ok(page)
+    }
+
+    async fn set_roles(&self, id: Uuid, roles: Vec<String>) -> Result<(), AppError> {
+        self.update(id, |user| user.roles = roles)
+    }
+
+    async fn set_disabled(&self, id: Uuid, disabled: bool) -> Result<(), AppError> {
+        self.update(id, |user| user.disabled = disabled)
+    }
+
+    async fn set_ban(&self, id: Uuid, ban: Option<Ban>) -> Result<(), AppError> {
+        self.update(id, |user| user.ban = ban)
+    }
+
+    async fn list_active_bans(&self, now: i64) -> Result<Vec<(Uuid, Ban)>, AppError> {
+        let users = self.users.read().map_err(|e| AppError::DbError(e.to_string()))?;
+        Ok(users
+            .values()
+            .filter_map(|user| user.active_ban(now).map(|ban| (user.id, ban.clone())))
+            .collect())
+    }
+
+    async fn require_password_reset(&self, id: Uuid) -> Result<(), AppError> {
+        self.update(id, |user| user.password_reset_required = true)
+    }
+
+    async fn mark_email_verified(&self, id: Uuid) -> Result<(), AppError> {
+        self.update(id, |user| user.email_verified = true)
+    }
+
+    async fn update_email(&self, id: Uuid, email: String) -> Result<(), AppError> {
+        self.update(id, |user| {
+            user.email = Some(email);
+            user.email_verified = false;
+        })
+    }
+
+    async fn set_totp(&self, id: Uuid, totp: Option<Totp>) -> Result<(), AppError> {
+        self.update(id, |user| user.totp = totp)
+    }
+
+    async fn use_recovery_code(&self, id: Uuid, code_hash: &str) -> Result<bool, AppError> {
+        let mut used = false;
+        self.update(id, |user| {
+            if let Some(totp) = user.totp.as_mut() {
+                let before = totp.recovery_codes.len();
+                totp.recovery_codes.retain(|hash| hash != code_hash);
+                used = totp.recovery_codes.len() < before;
+            }
+        })?;
+        Ok(used)
+    }
+
+    async fn use_totp_step(&self, id: Uuid, step: i64) -> Result<bool, AppError> {
+        let mut used = false;
+        self.update(id, |user| {
+            if let Some(totp) = user.totp.as_mut().filter(|totp| totp.last_used_step < Some(step)) {
+                totp.last_used_step = Some(step);
+                used = true;
+            }
+        })?;
+        Ok(used)
+    }
+}
@@ -1,9 +1,92 @@
+pub mod memory_login_attempt_repository;
+pub mod memory_refresh_token_repository;
+pub mod memory_revoked_token_repository;
+pub mod memory_used_challenge_repository;
+pub mod memory_user_repository;
+pub mod scylla_login_attempt_repository;
+pub mod scylla_refresh_token_repository;
+pub mod scylla_revoked_token_repository;
+pub mod scylla_used_challenge_repository;
 pub mod scylla_user_repository;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_login_attempt_repository;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_refresh_token_repository;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_revoked_token_repository;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_used_challenge_repository;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_user_repository;
 
+use crate::config::{Config, UserStore};
+use crate::domain::login_attempt_repository::LoginAttemptRepository;
+use crate::domain::refresh_token_repository::RefreshTokenRepository;
+use crate::domain::revoked_token_repository::RevokedTokenRepository;
+use crate::domain::used_challenge_repository::UsedChallengeRepository;
 use crate::domain::user_repository::UserRepository;
-use scylla::Session;
+use crate::errors::AppError;
+use crate::infrastructure::db::{get_db_session, run_migrations};
 use std::sync::Arc;
 
-pub fn create_user_repository(session: Arc<Session>) -> Arc<dyn UserRepository> {
-    Arc::new(scylla_user_repository::ScyllaUserRepository::new(session))
+/// Every repository the service needs, all backed by the same store.
+#[derive(Clone)]
+pub struct Repositories {
+    pub users: Arc<dyn UserRepository>,
+    pub refresh_tokens: Arc<dyn RefreshTokenRepository>,
+    pub revoked_tokens: Arc<dyn RevokedTokenRepository>,
+    pub login_attempts: Arc<dyn LoginAttemptRepository>,
+    pub used_challenges: Arc<dyn UsedChallengeRepository>,
+}
+
+impl Repositories {
+    pub fn in_memory() -> Self {
+        Self {
+            users: Arc::new(memory_user_repository::InMemoryUserRepository::new()),
+            refresh_tokens: Arc::new(memory_refresh_token_repository::InMemoryRefreshTokenRepository::new()),
+            revoked_tokens: Arc::new(memory_revoked_token_repository::InMemoryRevokedTokenRepository::new()),
+            login_attempts: Arc::new(memory_login_attempt_repository::InMemoryLoginAttemptRepository::new()),
+            used_challenges: Arc::new(memory_used_challenge_repository::InMemoryUsedChallengeRepository::new()),
+        }
+    }
+}
+
+/// Picks the backend from the configured store and, for a database, the URL scheme:
+/// `sqlite:` URLs open SQLite, anything else is a Scylla node address.
+pub async fn create_repositories(config: &Config) -> Result<Repositories, AppError> {
+    match config.user_store {
+        UserStore::Database if config.db_url.starts_with("sqlite:") => {
+            #[cfg(feature = "sqlite")]
+            {
+                let connection = crate::infrastructure::sqlite::open_sqlite(&config.db_url)?;
+                Ok(Repositories {
+                    users: Arc::new(sqlite_user_repository::SqliteUserRepository::new(connection.clone())),
+                    refresh_tokens: Arc::new(sqlite_refresh_token_repository::SqliteRefreshTokenRepository::new(connection.clone())),
+                    revoked_tokens: Arc::new(sqlite_revoked_token_repository::SqliteRevokedTokenRepository::new(connection.clone())),
+                    login_attempts: Arc::new(sqlite_login_attempt_repository::SqliteLoginAttemptRepository::new(connection.clone())),
+                    used_challenges: Arc::new(sqlite_used_challenge_repository::SqliteUsedChallengeRepository::new(connection)),
+                })
+            }
+            #[cfg(not(feature = "sqlite"))]
+            {
+                Err(AppError::DbError("sqlite support requires the `sqlite` feature".into()))
+            }
+        }
+        UserStore::Database => {
+            // Attempt to get the database session
+            let session = get_db_session(&config.db_url).await?;
+            run_migrations(&session).await?;
+            Ok(Repositories {
+                users: Arc::new(scylla_user_repository::ScyllaUserRepository::new(session.clone())),
+                refresh_tokens: Arc::new(scylla_refresh_token_repository::ScyllaRefreshTokenRepository::new(session.clone())),
+                revoked_tokens: Arc::new(scylla_revoked_token_repository::ScyllaRevokedTokenRepository::new(session.clone())),
+                login_attempts: Arc::new(scylla_login_attempt_repository::ScyllaLoginAttemptRepository::new(session.clone())),
+                used_challenges: Arc::new(scylla_used_challenge_repository::ScyllaUsedChallengeRepository::new(session)),
+            })
+        }
+        UserStore::Memory => {
+            log::warn!("Using the in-memory user store, accounts will not survive a restart");
+            Ok(Repositories::in_memory())
+        }
+    }
 }
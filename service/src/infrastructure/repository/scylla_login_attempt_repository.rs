@@ -0,0 +1,87 @@
+use super::scylla_user_repository::applied;
+use crate::domain::login_attempt_repository::{LoginAttemptRepository, LoginAttempts, ThrottlePolicy};
+use crate::errors::AppError;
+use chrono::Utc;
+use scylla::Session;
+use std::sync::Arc;
+
+/// How often counting a failure is retried when other failures for the key keep winning.
+const MAX_RETRIES: usize = 10;
+
+pub struct ScyllaLoginAttemptRepository {
+    session: Arc<Session>,
+}
+
+impl ScyllaLoginAttemptRepository {
+    pub fn new(session: Arc<Session>) -> Self {
+        Self { session }
+    }
+}
+
+#[async_trait::async_trait]
+impl LoginAttemptRepository for ScyllaLoginAttemptRepository {
+    async fn find(&self, key: &str) -> Result<Option<LoginAttempts>, AppError> {
+        let query = "SELECT failures, blocked_until, expires_at FROM inner_shelter.login_attempts WHERE key = ?";
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        let result = self.session.execute_unpaged(&prepared, (key,)).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+
+        let row = result
+            .maybe_first_row_typed::<(i32, i64, i64)>()
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+
+        Ok(row.map(|(failures, blocked_until, expires_at)| LoginAttempts {
+            failures: failures as u32,
+            blocked_until,
+            expires_at,
+        }))
+    }
+
+    async fn record_failure(&self, key: &str, policy: &ThrottlePolicy) -> Result<LoginAttempts, AppError> {
+        let insert = self.session
+            .prepare("INSERT INTO inner_shelter.login_attempts (key, failures, blocked_until, expires_at)
+                VALUES (?, ?, ?, ?) IF NOT EXISTS USING TTL ?")
+            .await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        let update = self.session
+            .prepare("UPDATE inner_shelter.login_attempts USING TTL ? SET failures = ?, blocked_until = ?, expires_at = ?
+                WHERE key = ? IF failures = ?")
+            .await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+
+        // Each write is conditional on the count it was computed from, so parallel failures
+        // retry with the new count instead of overwriting each other
+        for _ in 0..MAX_RETRIES {
+            let now = Utc::now().timestamp();
+            let recorded = self.find(key).await?;
+            let attempts = policy.record_failure(recorded.as_ref().unwrap_or(&LoginAttempts::default()), now);
+            // Rows expire with the attempts they record, so old failures need no cleanup
+            let ttl = (attempts.expires_at - now).max(1) as i32;
+            let result = match recorded {
+                None => self.session
+                    .execute_unpaged(&insert, (key, attempts.failures as i32, attempts.blocked_until, attempts.expires_at, ttl))
+                    .await,
+                Some(recorded) => self.session
+                    .execute_unpaged(
+                        &update,
+                        (ttl, attempts.failures as i32, attempts.blocked_until, attempts.expires_at, key, recorded.failures as i32),
+                    )
+                    .await,
+            };
+            if applied(result.map_err(|e| AppError::DbError(e.to_string()))?)? {
+                return Ok(attempts);
+            }
+        }
+        Err(AppError::DbError(format!("too much contention counting failures for {}", key)))
+    }
+
+    async fn clear(&self, key: &str) -> Result<(), AppError> {
+        let query = "DELETE FROM inner_shelter.login_attempts WHERE key = ?";
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        self.session.execute_unpaged(&prepared, (key,)).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        Ok(())
+    }
+}
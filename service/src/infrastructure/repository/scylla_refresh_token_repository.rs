@@ -0,0 +1,164 @@
+use super::scylla_user_repository::applied;
+use crate::domain::refresh_token_repository::{RefreshTokenFamily, RefreshTokenRepository};
+use crate::errors::AppError;
+use futures_util::stream::TryStreamExt;
+use scylla::Session;
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub struct ScyllaRefreshTokenRepository {
+    session: Arc<Session>,
+}
+
+#[derive(scylla::FromRow)]
+struct FamilyRow {
+    user_id: Option<Uuid>,
+    current_token_hash: String,
+    revoked: bool,
+    expires_at: i64,
+    created_at: Option<i64>,
+    last_seen_at: Option<i64>,
+    user_agent: Option<String>,
+    ip: Option<String>,
+    previous_token_hash: Option<String>,
+}
+
+impl ScyllaRefreshTokenRepository {
+    pub fn new(session: Arc<Session>) -> Self {
+        Self { session }
+    }
+
+    async fn user_family_ids(&self, user_id: Uuid) -> Result<Vec<String>, AppError> {
+        let query = "SELECT family_id FROM inner_shelter.refresh_token_families_by_user WHERE user_id = ?";
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        let rows = self.session.execute_iter(prepared, (user_id,)).await
+            .map_err(|e| AppError::DbError(e.to_string()))?
+            .into_typed::<(String,)>()
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        Ok(rows.into_iter().map(|(family_id,)| family_id).collect())
+    }
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenRepository for ScyllaRefreshTokenRepository {
+    async fn create_family(&self, family: &RefreshTokenFamily) -> Result<(), AppError> {
+        let query = "INSERT INTO inner_shelter.refresh_token_families
+            (family_id, user_id, current_token_hash, revoked, expires_at, created_at, last_seen_at, user_agent, ip,
+            previous_token_hash)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        self.session.execute_unpaged(&prepared, (
+            &family.family_id,
+            family.user_id,
+            &family.current_token_hash,
+            family.revoked,
+            family.expires_at,
+            family.created_at,
+            family.last_seen_at,
+            &family.user_agent,
+            &family.ip,
+            &family.previous_token_hash,
+        )).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+
+        let query = "INSERT INTO inner_shelter.refresh_token_families_by_user (user_id, family_id) VALUES (?, ?)";
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        self.session.execute_unpaged(&prepared, (family.user_id, &family.family_id)).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn find_family(&self, family_id: &str) -> Result<Option<RefreshTokenFamily>, AppError> {
+        let query = "SELECT user_id, current_token_hash, revoked, expires_at, created_at, last_seen_at, user_agent, ip,
+            previous_token_hash
+            FROM inner_shelter.refresh_token_families WHERE family_id = ?";
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        let row = self.session.execute_unpaged(&prepared, (family_id,)).await
+            .map_err(|e| AppError::DbError(e.to_string()))?
+            .maybe_first_row_typed::<FamilyRow>()
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+
+        // Families from before user ids have none and cannot be refreshed any more
+        Ok(row.and_then(|row| Some(RefreshTokenFamily {
+            family_id: family_id.to_string(),
+            user_id: row.user_id?,
+            current_token_hash: row.current_token_hash,
+            revoked: row.revoked,
+            expires_at: row.expires_at,
+            // Families from before session details show as started at the epoch
+            created_at: row.created_at.unwrap_or_default(),
+            last_seen_at: row.last_seen_at.unwrap_or_default(),
+            user_agent: row.user_agent,
+            ip: row.ip,
+            previous_token_hash: row.previous_token_hash,
+        })))
+    }
+
+    async fn list_user_families(&self, user_id: Uuid) -> Result<Vec<RefreshTokenFamily>, AppError> {
+        let family_ids = self.user_family_ids(user_id).await?;
+        let mut families = Vec::with_capacity(family_ids.len());
+        for family_id in family_ids {
+            if let Some(family) = self.find_family(&family_id).await? {
+                families.push(family);
+            }
+        }
+        Ok(families)
+    }
+
+    async fn rotate(
+        &self,
+        family_id: &str,
+        expected_hash: &str,
+        new_hash: &str,
+        expires_at: i64,
+        seen_at: i64,
+    ) -> Result<bool, AppError> {
+        // A lightweight transaction, so two requests presenting the same token cannot both rotate it
+        let query = "UPDATE inner_shelter.refresh_token_families
+            SET current_token_hash = ?, previous_token_hash = ?, expires_at = ?, last_seen_at = ?
+            WHERE family_id = ?
+            IF current_token_hash = ? AND revoked = false";
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        let values = (new_hash, expected_hash, expires_at, seen_at, family_id, expected_hash);
+        let result = self.session.execute_unpaged(&prepared, values).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+
+        applied(result)
+    }
+
+    async fn revoke_family(&self, family_id: &str) -> Result<(), AppError> {
+        let query = "UPDATE inner_shelter.refresh_token_families SET revoked = true
+            WHERE family_id = ? IF EXISTS";
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        self.session.execute_unpaged(&prepared, (family_id,)).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn revoke_user_families(&self, user_id: Uuid, keep: Option<&str>) -> Result<(), AppError> {
+        let family_ids = self.user_family_ids(user_id).await?;
+
+        // Revoked families can never be used again, so they leave the index
+        let forget = self.session
+            .prepare("DELETE FROM inner_shelter.refresh_token_families_by_user WHERE user_id = ? AND family_id = ?")
+            .await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        for family_id in family_ids {
+            if Some(family_id.as_str()) == keep {
+                continue;
+            }
+            self.revoke_family(&family_id).await?;
+            self.session.execute_unpaged(&forget, (user_id, &family_id)).await
+                .map_err(|e| AppError::DbError(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use crate::domain::revoked_token_repository::RevokedTokenRepository;
+use crate::errors::AppError;
+use chrono::Utc;
+use futures_util::stream::TryStreamExt;
+use scylla::Session;
+use shared::api::auth::RevokedToken;
+use std::sync::Arc;
+
+pub struct ScyllaRevokedTokenRepository {
+    session: Arc<Session>,
+}
+
+impl ScyllaRevokedTokenRepository {
+    pub fn new(session: Arc<Session>) -> Self {
+        Self { session }
+    }
+}
+
+#[async_trait::async_trait]
+impl RevokedTokenRepository for ScyllaRevokedTokenRepository {
+    async fn revoke(&self, token: &RevokedToken) -> Result<(), AppError> {
+        // Rows expire together with the token they revoke, keeping the table small
+        let ttl = (token.expires_at - Utc::now().timestamp()).max(1) as i32;
+        let query = "INSERT INTO inner_shelter.revoked_tokens (jti, expires_at) VALUES (?, ?) USING TTL ?";
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        self.session.execute_unpaged(&prepared, (&token.jti, token.expires_at, ttl)).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn list_revoked(&self) -> Result<Vec<RevokedToken>, AppError> {
+        let query = "SELECT jti, expires_at FROM inner_shelter.revoked_tokens";
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        let rows = self.session.execute_iter(prepared, &[]).await
+            .map_err(|e| AppError::DbError(e.to_string()))?
+            .into_typed::<(String, i64)>()
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(jti, expires_at)| RevokedToken { jti, expires_at })
+            .collect())
+    }
+
+    async fn is_revoked(&self, jti: &str) -> Result<bool, AppError> {
+        let query = "SELECT jti FROM inner_shelter.revoked_tokens WHERE jti = ?";
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        let row = self.session.execute_unpaged(&prepared, (jti,)).await
+            .map_err(|e| AppError::DbError(e.to_string()))?
+            .maybe_first_row_typed::<(String,)>()
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        Ok(row.is_some())
+    }
+}
@@ -0,0 +1,40 @@
+use super::scylla_user_repository::applied;
+use crate::domain::used_challenge_repository::UsedChallengeRepository;
+use crate::errors::AppError;
+use chrono::Utc;
+use scylla::Session;
+use std::sync::Arc;
+
+pub struct ScyllaUsedChallengeRepository {
+    session: Arc<Session>,
+}
+
+impl ScyllaUsedChallengeRepository {
+    pub fn new(session: Arc<Session>) -> Self {
+        Self { session }
+    }
+}
+
+#[async_trait::async_trait]
+impl UsedChallengeRepository for ScyllaUsedChallengeRepository {
+    async fn use_challenge(&self, challenge_id: &str, expires_at: i64) -> Result<bool, AppError> {
+        // Rows expire together with the challenge, a lightweight transaction lets one use win
+        let ttl = (expires_at - Utc::now().timestamp()).max(1) as i32;
+        let query = "INSERT INTO inner_shelter.used_challenges (challenge_id, expires_at) VALUES (?, ?)
+            IF NOT EXISTS USING TTL ?";
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        let result = self.session.execute_unpaged(&prepared, (challenge_id, expires_at, ttl)).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        applied(result)
+    }
+
+    async fn release_challenge(&self, challenge_id: &str) -> Result<(), AppError> {
+        let query = "DELETE FROM inner_shelter.used_challenges WHERE challenge_id = ?";
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        self.session.execute_unpaged(&prepared, (challenge_id,)).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        Ok(())
+    }
+}
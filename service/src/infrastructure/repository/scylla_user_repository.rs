@@ -1,9 +1,45 @@
-use crate::domain::user_repository::UserRepository;
+use crate::domain::user_repository::{Totp, User, UserRepository, DEFAULT_ROLE};
 use crate::errors::AppError;
+use shared::api::admin::Ban;
+use chrono::Utc;
+use futures_util::stream::TryStreamExt;
+use scylla::batch::Batch;
 use scylla::Session;
 use std::sync::Arc;
-use futures_util::stream::TryStreamExt;
+use uuid::Uuid;
+
+const INSERTED_COLUMNS: &str = "id, username, display_name, password, roles, created_at, last_login_at, disabled, password_reset_required, deletion_scheduled_at, ban_reason, banned_at, banned_until, email, email_verified";
+const USER_COLUMNS: &str = "id, username, display_name, password, roles, created_at, last_login_at, disabled, password_reset_required, deletion_scheduled_at, ban_reason, banned_at, banned_until, email, email_verified, totp_secret, totp_enabled, totp_recovery_codes, totp_last_step";
+
+/// Longest TTL Scylla accepts, 20 years in seconds.
+const MAX_TTL: i64 = 20 * 365 * 24 * 60 * 60;
 
+/// `USER_COLUMNS` in order. Columns added after the first release are null for older users.
+#[derive(scylla::FromRow)]
+struct UserRow {
+    id: Option<Uuid>,
+    username: String,
+    display_name: Option<String>,
+    password_hash: String,
+    roles: Option<Vec<String>>,
+    created_at: Option<i64>,
+    last_login_at: Option<i64>,
+    disabled: Option<bool>,
+    password_reset_required: Option<bool>,
+    deletion_scheduled_at: Option<i64>,
+    ban_reason: Option<String>,
+    banned_at: Option<i64>,
+    banned_until: Option<i64>,
+    email: Option<String>,
+    email_verified: Option<bool>,
+    totp_secret: Option<String>,
+    totp_enabled: Option<bool>,
+    totp_recovery_codes: Option<Vec<String>>,
+    totp_last_step: Option<i64>,
+}
+
+/// Users are keyed by username, the lookup every login does, with `users_by_id` mapping
+/// ids back to usernames.
 pub struct ScyllaUserRepository {
     session: Arc<Session>,
 }
@@ -12,32 +48,401 @@ impl ScyllaUserRepository {
     pub fn new(session: Arc<Session>) -> Self {
         Self { session }
     }
+
+    async fn select_user(&self, username: &str) -> Result<Option<UserRow>, AppError> {
+        let query = format!("SELECT {} FROM inner_shelter.users WHERE username = ?", USER_COLUMNS);
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        self.session.execute_unpaged(&prepared, (username,)).await
+            .map_err(|e| AppError::DbError(e.to_string()))?
+            .maybe_first_row_typed::<UserRow>()
+            .map_err(|e| AppError::DbError(e.to_string()))
+    }
+
+    /// Gives a user from before ids existed one. Conditional, so concurrent logins agree
+    /// on the same id.
+    async fn assign_id(&self, username: &str) -> Result<(), AppError> {
+        let id = Uuid::new_v4();
+        let query = "UPDATE inner_shelter.users
+            SET id = ?, display_name = ?, roles = ?, created_at = ?
+            WHERE username = ? IF id = null";
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        let result = self.session
+            .execute_unpaged(&prepared, (id, username, vec![DEFAULT_ROLE], Utc::now().timestamp(), username))
+            .await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+
+        if applied(result)? {
+            self.index_id(id, username).await?;
+        }
+        Ok(())
+    }
+
+    async fn index_id(&self, id: Uuid, username: &str) -> Result<(), AppError> {
+        let query = "INSERT INTO inner_shelter.users_by_id (id, username) VALUES (?, ?)";
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        self.session.execute_unpaged(&prepared, (id, username)).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn username_for(&self, id: Uuid) -> Result<Option<String>, AppError> {
+        let query = "SELECT username FROM inner_shelter.users_by_id WHERE id = ?";
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        let row = self.session.execute_unpaged(&prepared, (id,)).await
+            .map_err(|e| AppError::DbError(e.to_string()))?
+            .maybe_first_row_typed::<(String,)>()
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        Ok(row.map(|(username,)| username))
+    }
+
+    /// Sets one column of the user with the given id, if it still exists.
+    async fn update_column<V>(&self, id: Uuid, column: &str, value: V) -> Result<(), AppError>
+    where
+        V: scylla::serialize::value::SerializeValue + Send + Sync,
+    {
+        let Some(username) = self.username_for(id).await? else {
+            return Ok(());
+        };
+
+        // IF EXISTS so a user deleted in the meantime is not brought back
+        let query = format!("UPDATE inner_shelter.users SET {} = ? WHERE username = ? IF EXISTS", column);
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        self.session.execute_unpaged(&prepared, (value, username)).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        Ok(())
+    }
+}
+
+fn user_from_row(row: UserRow) -> Result<User, AppError> {
+    let id = row.id.ok_or_else(|| AppError::DbError(format!("User {} has no id", row.username)))?;
+    Ok(User {
+        id,
+        display_name: row.display_name.unwrap_or_else(|| row.username.clone()),
+        username: row.username,
+        password_hash: row.password_hash,
+        roles: row.roles.unwrap_or_default(),
+        created_at: row.created_at.unwrap_or_default(),
+        last_login_at: row.last_login_at,
+        disabled: row.disabled.unwrap_or(false),
+        password_reset_required: row.password_reset_required.unwrap_or(false),
+        deletion_scheduled_at: row.deletion_scheduled_at,
+        ban: row.banned_at.map(|banned_at| Ban {
+            reason: row.ban_reason.unwrap_or_default(),
+            banned_at,
+            until: row.banned_until,
+        }),
+        email: row.email,
+        email_verified: row.email_verified.unwrap_or(false),
+        totp: row.totp_secret.map(|secret| Totp {
+            secret,
+            enabled: row.totp_enabled.unwrap_or(false),
+            recovery_codes: row.totp_recovery_codes.unwrap_or_default(),
+            last_used_step: row.totp_last_step,
+        }),
+    })
+}
+
+/// The first column of a lightweight transaction's result is `[applied]`, followed by
+/// the existing row when it was not.
+pub(crate) fn applied(result: scylla::QueryResult) -> Result<bool, AppError> {
+    Ok(result.first_row()
+        .map_err(|e| AppError::DbError(e.to_string()))?
+        .columns
+        .first()
+        .and_then(|column| column.as_ref())
+        .and_then(|value| value.as_boolean())
+        .unwrap_or(false))
 }
 
 #[async_trait::async_trait]
 impl UserRepository for ScyllaUserRepository {
-    async fn find_user_by_username(&self, username: &str) -> Result<Option<String>, AppError> {
-        let query = "SELECT password FROM inner_shelter.users WHERE username = ?";
+    async fn find_user_by_username(&self, username: &str) -> Result<Option<User>, AppError> {
+        let Some(mut row) = self.select_user(username).await? else {
+            return Ok(None);
+        };
+        if row.id.is_none() {
+            self.assign_id(username).await?;
+            row = match self.select_user(username).await? {
+                Some(row) => row,
+                None => return Ok(None),
+            };
+        }
+
+        user_from_row(row).map(Some)
+    }
+
+    async fn find_user_by_id(&self, id: Uuid) -> Result<Option<User>, AppError> {
+        let Some(username) = self.username_for(id).await? else {
+            return Ok(None);
+        };
+        // The index can outlive the user it points at
+        Ok(self.find_user_by_username(&username).await?.filter(|user| user.id == id))
+    }
+
+    async fn create_user(&self, user: &User) -> Result<(), AppError> {
+        // A lightweight transaction, the insert only happens if no row exists for the username.
+        // New users have not enrolled in TOTP, so its columns are left out.
+        let query = format!(
+            "INSERT INTO inner_shelter.users ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) IF NOT EXISTS",
+            INSERTED_COLUMNS
+        );
         let prepared = self.session.prepare(query).await
             .map_err(|e| AppError::DbError(e.to_string()))?;
-        let result = self.session.execute_iter(prepared, (username,)).await
+        let result = self.session.execute_unpaged(&prepared, (
+            user.id,
+            &user.username,
+            &user.display_name,
+            &user.password_hash,
+            &user.roles,
+            user.created_at,
+            user.last_login_at,
+            user.disabled,
+            user.password_reset_required,
+            user.deletion_scheduled_at,
+            user.ban.as_ref().map(|ban| &ban.reason),
+            user.ban.as_ref().map(|ban| ban.banned_at),
+            user.ban.as_ref().and_then(|ban| ban.until),
+            &user.email,
+            user.email_verified,
+        )).await
             .map_err(|e| AppError::DbError(e.to_string()))?;
-        let mut rows = result.into_typed::<(String,)>();
 
-        if let Some(row) = rows.try_next().await
-            .map_err(|e| AppError::DbError(e.to_string()))? {
-            Ok(Some(row.0))
+        if applied(result)? {
+            self.index_id(user.id, &user.username).await
         } else {
-            Ok(None)
+            Err(AppError::username_taken())
         }
     }
 
-    async fn create_user(&self, username: &str, password: String) -> Result<(), AppError> {
-        let insert_query = "INSERT INTO inner_shelter.users (username, password) VALUES (?, ?)";
-        let prepared_insert = self.session.prepare(insert_query).await
+    async fn update_password(&self, id: Uuid, password_hash: String) -> Result<(), AppError> {
+        self.update_column(id, "password", password_hash).await?;
+        self.update_column(id, "password_reset_required", false).await
+    }
+
+    async fn record_login(&self, id: Uuid, at: i64) -> Result<(), AppError> {
+        self.update_column(id, "last_login_at", at).await
+    }
+
+    async fn update_display_name(&self, id: Uuid, display_name: String) -> Result<(), AppError> {
+        self.update_column(id, "display_name", display_name).await
+    }
+
+    async fn schedule_deletion(&self, id: Uuid, at: Option<i64>) -> Result<(), AppError> {
+        self.update_column(id, "deletion_scheduled_at", at).await
+    }
+
+    async fn delete_user(&self, id: Uuid) -> Result<(), AppError> {
+        let Some(username) = self.username_for(id).await? else {
+            return Ok(());
+        };
+
+        // A logged batch, so no ban or id lookup outlives the user row
+        let mut batch = Batch::default();
+        for query in [
+            "DELETE FROM inner_shelter.users WHERE username = ?",
+            "DELETE FROM inner_shelter.users_by_id WHERE id = ?",
+            "DELETE FROM inner_shelter.bans WHERE user_id = ?",
+        ] {
+            batch.append_statement(self.session.prepare(query).await
+                .map_err(|e| AppError::DbError(e.to_string()))?);
+        }
+        self.session.batch(&batch, ((&username,), (id,), (id,))).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn list_users(&self, after: Option<&str>, limit: usize) -> Result<Vec<User>, AppError> {
+        // Partitions come back in token order, so that is the order pages follow too
+        let rows = match after {
+            Some(after) => {
+                let query = format!(
+                    "SELECT {} FROM inner_shelter.users WHERE token(username) > token(?) LIMIT ?",
+                    USER_COLUMNS
+                );
+                let prepared = self.session.prepare(query).await
+                    .map_err(|e| AppError::DbError(e.to_string()))?;
+                self.session.execute_iter(prepared, (after, limit as i32)).await
+            }
+            None => {
+                let query = format!("SELECT {} FROM inner_shelter.users LIMIT ?", USER_COLUMNS);
+                let prepared = self.session.prepare(query).await
+                    .map_err(|e| AppError::DbError(e.to_string()))?;
+                self.session.execute_iter(prepared, (limit as i32,)).await
+            }
+        }
+            .map_err(|e| AppError::DbError(e.to_string()))?
+            .into_typed::<UserRow>()
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+
+        let mut users = Vec::with_capacity(rows.len());
+        for row in rows {
+            if row.id.is_some() {
+                users.push(user_from_row(row)?);
+            } else if let Some(user) = self.find_user_by_username(&row.username).await? {
+                // Looking a user from before ids up gives them one
+                users.push(user);
+            }
+        }
+        Ok(users)
+    }
+
+    async fn set_roles(&self, id: Uuid, roles: Vec<String>) -> Result<(), AppError> {
+        self.update_column(id, "roles", roles).await
+    }
+
+    async fn set_disabled(&self, id: Uuid, disabled: bool) -> Result<(), AppError> {
+        self.update_column(id, "disabled", disabled).await
+    }
+
+    async fn set_ban(&self, id: Uuid, ban: Option<Ban>) -> Result<(), AppError> {
+        let Some(username) = self.username_for(id).await? else {
+            return Ok(());
+        };
+
+        // A logged batch, so the user row and the ban list never disagree. Conditions cannot
+        // span both tables, the row was just looked up instead
+        let user_query = "UPDATE inner_shelter.users SET ban_reason = ?, banned_at = ?, banned_until = ?
+            WHERE username = ?";
+        let user_values = (
+            ban.as_ref().map(|ban| &ban.reason),
+            ban.as_ref().map(|ban| ban.banned_at),
+            ban.as_ref().and_then(|ban| ban.until),
+            &username,
+        );
+        let mut batch = Batch::default();
+        batch.append_statement(self.session.prepare(user_query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?);
+
+        match &ban {
+            Some(ban) => {
+                // A TTL of 0 keeps the row forever, Scylla refuses any longer than its maximum
+                let ttl = ban.until.map_or(0, |until| (until - Utc::now().timestamp()).clamp(1, MAX_TTL)) as i32;
+                let query = "INSERT INTO inner_shelter.bans (user_id, reason, banned_at, until)
+                    VALUES (?, ?, ?, ?) USING TTL ?";
+                batch.append_statement(self.session.prepare(query).await
+                    .map_err(|e| AppError::DbError(e.to_string()))?);
+                self.session.batch(&batch, (user_values, (id, &ban.reason, ban.banned_at, ban.until, ttl))).await
+                    .map_err(|e| AppError::DbError(e.to_string()))?;
+            }
+            None => {
+                let query = "DELETE FROM inner_shelter.bans WHERE user_id = ?";
+                batch.append_statement(self.session.prepare(query).await
+                    .map_err(|e| AppError::DbError(e.to_string()))?);
+                self.session.batch(&batch, (user_values, (id,))).await
+                    .map_err(|e| AppError::DbError(e.to_string()))?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn list_active_bans(&self, now: i64) -> Result<Vec<(Uuid, Ban)>, AppError> {
+        let query = "SELECT user_id, reason, banned_at, until FROM inner_shelter.bans";
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        let rows = self.session.execute_iter(prepared, &[]).await
+            .map_err(|e| AppError::DbError(e.to_string()))?
+            .into_typed::<(Uuid, String, i64, Option<i64>)>()
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(user_id, reason, banned_at, until)| (user_id, Ban { reason, banned_at, until }))
+            .filter(|(_, ban)| ban.is_active(now))
+            .collect())
+    }
+
+    async fn require_password_reset(&self, id: Uuid) -> Result<(), AppError> {
+        self.update_column(id, "password_reset_required", true).await
+    }
+
+    async fn mark_email_verified(&self, id: Uuid) -> Result<(), AppError> {
+        self.update_column(id, "email_verified", true).await
+    }
+
+    async fn update_email(&self, id: Uuid, email: String) -> Result<(), AppError> {
+        let Some(username) = self.username_for(id).await? else {
+            return Ok(());
+        };
+
+        // One statement, so the new address is never taken for verified
+        let query = "UPDATE inner_shelter.users SET email = ?, email_verified = false WHERE username = ? IF EXISTS";
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        self.session.execute_unpaged(&prepared, (email, username)).await
             .map_err(|e| AppError::DbError(e.to_string()))?;
-        self.session.execute_iter(prepared_insert, (username, password)).await
+        Ok(())
+    }
+
+    async fn set_totp(&self, id: Uuid, totp: Option<Totp>) -> Result<(), AppError> {
+        let Some(username) = self.username_for(id).await? else {
+            return Ok(());
+        };
+
+        let query = "UPDATE inner_shelter.users
+            SET totp_secret = ?, totp_enabled = ?, totp_recovery_codes = ?, totp_last_step = ?
+            WHERE username = ? IF EXISTS";
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        self.session.execute_unpaged(&prepared, (
+            totp.as_ref().map(|totp| &totp.secret),
+            totp.as_ref().map(|totp| totp.enabled),
+            totp.as_ref().map(|totp| &totp.recovery_codes),
+            totp.as_ref().and_then(|totp| totp.last_used_step),
+            username,
+        )).await
             .map_err(|e| AppError::DbError(e.to_string()))?;
         Ok(())
     }
+
+    async fn use_recovery_code(&self, id: Uuid, code_hash: &str) -> Result<bool, AppError> {
+        let Some(user) = self.find_user_by_id(id).await? else {
+            return Ok(false);
+        };
+        let Some(codes) = user.totp.map(|totp| totp.recovery_codes) else {
+            return Ok(false);
+        };
+        if !codes.iter().any(|hash| hash == code_hash) {
+            return Ok(false);
+        }
+
+        // Conditional on the codes read, so of two concurrent uses only one applies
+        let remaining: Vec<&String> = codes.iter().filter(|hash| *hash != code_hash).collect();
+        let query = "UPDATE inner_shelter.users SET totp_recovery_codes = ?
+            WHERE username = ? IF totp_recovery_codes = ?";
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        let result = self.session.execute_unpaged(&prepared, (remaining, &user.username, &codes)).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        applied(result)
+    }
+
+    async fn use_totp_step(&self, id: Uuid, step: i64) -> Result<bool, AppError> {
+        let Some(user) = self.find_user_by_id(id).await? else {
+            return Ok(false);
+        };
+        let Some(last_used_step) = user.totp.map(|totp| totp.last_used_step) else {
+            return Ok(false);
+        };
+        if last_used_step >= Some(step) {
+            return Ok(false);
+        }
+
+        // Conditional on the step read, so a code sent twice at once is only accepted once
+        let query = "UPDATE inner_shelter.users SET totp_last_step = ?
+            WHERE username = ? IF totp_last_step = ?";
+        let prepared = self.session.prepare(query).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        let result = self.session.execute_unpaged(&prepared, (step, &user.username, last_used_step)).await
+            .map_err(|e| AppError::DbError(e.to_string()))?;
+        applied(result)
+    }
 }
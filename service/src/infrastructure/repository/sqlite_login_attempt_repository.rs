@@ -0,0 +1,82 @@
+use crate::domain::login_attempt_repository::{LoginAttemptRepository, LoginAttempts, ThrottlePolicy};
+use crate::errors::AppError;
+use crate::infrastructure::sqlite::{with_connection, SqliteConnection};
+use chrono::Utc;
+use rusqlite::OptionalExtension;
+
+pub struct SqliteLoginAttemptRepository {
+    connection: SqliteConnection,
+}
+
+impl SqliteLoginAttemptRepository {
+    pub fn new(connection: SqliteConnection) -> Self {
+        Self { connection }
+    }
+}
+
+#[async_trait::async_trait]
+impl LoginAttemptRepository for SqliteLoginAttemptRepository {
+    async fn find(&self, key: &str) -> Result<Option<LoginAttempts>, AppError> {
+        let key = key.to_string();
+        let now = Utc::now().timestamp();
+        with_connection(&self.connection, move |connection| {
+            connection
+                .query_row(
+                    "SELECT failures, blocked_until, expires_at FROM login_attempts WHERE key = ?1 AND expires_at > ?2",
+                    (key, now),
+                    |row| {
+                        Ok(LoginAttempts {
+                            failures: row.get(0)?,
+                            blocked_until: row.get(1)?,
+                            expires_at: row.get(2)?,
+                        })
+                    },
+                )
+                .optional()
+        })
+        .await
+    }
+
+    async fn record_failure(&self, key: &str, policy: &ThrottlePolicy) -> Result<LoginAttempts, AppError> {
+        let key = key.to_string();
+        let policy = *policy;
+        let now = Utc::now().timestamp();
+        with_connection(&self.connection, move |connection| {
+            // Forget expired keys so guessing random usernames cannot grow the table forever
+            connection.execute("DELETE FROM login_attempts WHERE expires_at <= ?1", [now])?;
+            // Count in the statement rather than writing back a count read earlier, so no
+            // failure is lost to a parallel one. The times only ever move forward
+            let failures: u32 = connection.query_row(
+                "INSERT INTO login_attempts (key, failures, blocked_until, expires_at) VALUES (?1, 1, ?2, ?2)
+                ON CONFLICT (key) DO UPDATE SET failures = failures + 1
+                RETURNING failures",
+                (&key, now),
+                |row| row.get(0),
+            )?;
+            let attempts = policy.after_failures(failures, now);
+            connection.query_row(
+                "UPDATE login_attempts SET blocked_until = MAX(blocked_until, ?2), expires_at = MAX(expires_at, ?3)
+                WHERE key = ?1
+                RETURNING failures, blocked_until, expires_at",
+                (&key, attempts.blocked_until, attempts.expires_at),
+                |row| {
+                    Ok(LoginAttempts {
+                        failures: row.get(0)?,
+                        blocked_until: row.get(1)?,
+                        expires_at: row.get(2)?,
+                    })
+                },
+            )
+        })
+        .await
+    }
+
+    async fn clear(&self, key: &str) -> Result<(), AppError> {
+        let key = key.to_string();
+        with_connection(&self.connection, move |connection| {
+            connection.execute("DELETE FROM login_attempts WHERE key = ?1", [key])?;
+            Ok(())
+        })
+        .await
+    }
+}
@@ -0,0 +1,138 @@
+use crate::domain::refresh_token_repository::{RefreshTokenFamily, RefreshTokenRepository};
+use crate::errors::AppError;
+use crate::infrastructure::sqlite::{with_connection, SqliteConnection};
+use rusqlite::OptionalExtension;
+use uuid::Uuid;
+
+/// The columns `family_from_row` reads, in order.
+const COLUMNS: &str = "family_id, user_id, current_token_hash, revoked, expires_at, created_at, last_seen_at, \
+    user_agent, ip, previous_token_hash";
+
+fn family_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<RefreshTokenFamily> {
+    let user_id: String = row.get(1)?;
+    Ok(RefreshTokenFamily {
+        family_id: row.get(0)?,
+        user_id: Uuid::parse_str(&user_id).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(1, rusqlite::types::Type::Text, Box::new(e))
+        })?,
+        current_token_hash: row.get(2)?,
+        revoked: row.get(3)?,
+        expires_at: row.get(4)?,
+        created_at: row.get(5)?,
+        last_seen_at: row.get(6)?,
+        user_agent: row.get(7)?,
+        ip: row.get(8)?,
+        previous_token_hash: row.get(9)?,
+    })
+}
+
+pub struct SqliteRefreshTokenRepository {
+    connection: SqliteConnection,
+}
+
+impl SqliteRefreshTokenRepository {
+    pub fn new(connection: SqliteConnection) -> Self {
+        Self { connection }
+    }
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenRepository for SqliteRefreshTokenRepository {
+    async fn create_family(&self, family: &RefreshTokenFamily) -> Result<(), AppError> {
+        let family = family.clone();
+        with_connection(&self.connection, move |connection| {
+            connection.execute(
+                "INSERT INTO refresh_token_families
+                    (family_id, user_id, current_token_hash, revoked, expires_at,
+                    created_at, last_seen_at, user_agent, ip, previous_token_hash)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                (
+                    family.family_id,
+                    family.user_id.to_string(),
+                    family.current_token_hash,
+                    family.revoked,
+                    family.expires_at,
+                    family.created_at,
+                    family.last_seen_at,
+                    family.user_agent,
+                    family.ip,
+                    family.previous_token_hash,
+                ),
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn find_family(&self, family_id: &str) -> Result<Option<RefreshTokenFamily>, AppError> {
+        let family_id = family_id.to_string();
+        with_connection(&self.connection, move |connection| {
+            connection
+                .query_row(
+                    &format!("SELECT {} FROM refresh_token_families WHERE family_id = ?1", COLUMNS),
+                    [&family_id],
+                    family_from_row,
+                )
+                .optional()
+        })
+        .await
+    }
+
+    async fn list_user_families(&self, user_id: Uuid) -> Result<Vec<RefreshTokenFamily>, AppError> {
+        with_connection(&self.connection, move |connection| {
+            let mut statement = connection.prepare(
+                &format!("SELECT {} FROM refresh_token_families WHERE user_id = ?1", COLUMNS),
+            )?;
+            let families = statement.query_map([user_id.to_string()], family_from_row)?;
+            families.collect()
+        })
+        .await
+    }
+
+    async fn rotate(
+        &self,
+        family_id: &str,
+        expected_hash: &str,
+        new_hash: &str,
+        expires_at: i64,
+        seen_at: i64,
+    ) -> Result<bool, AppError> {
+        let (family_id, expected_hash, new_hash) =
+            (family_id.to_string(), expected_hash.to_string(), new_hash.to_string());
+        with_connection(&self.connection, move |connection| {
+            // The conditions make this a compare-and-swap, a single statement is atomic
+            let updated = connection.execute(
+                "UPDATE refresh_token_families
+                    SET current_token_hash = ?1, previous_token_hash = ?5, expires_at = ?2, last_seen_at = ?3
+                    WHERE family_id = ?4 AND current_token_hash = ?5 AND revoked = 0",
+                (new_hash, expires_at, seen_at, family_id, expected_hash),
+            )?;
+            Ok(updated == 1)
+        })
+        .await
+    }
+
+    async fn revoke_family(&self, family_id: &str) -> Result<(), AppError> {
+        let family_id = family_id.to_string();
+        with_connection(&self.connection, move |connection| {
+            connection.execute(
+                "UPDATE refresh_token_families SET revoked = 1 WHERE family_id = ?1",
+                [family_id],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn revoke_user_families(&self, user_id: Uuid, keep: Option<&str>) -> Result<(), AppError> {
+        let keep = keep.map(str::to_string);
+        with_connection(&self.connection, move |connection| {
+            connection.execute(
+                "UPDATE refresh_token_families SET revoked = 1 WHERE user_id = ?1 AND family_id IS NOT ?2",
+                (user_id.to_string(), keep),
+            )?;
+            Ok(())
+        })
+        .await
+    }
+}
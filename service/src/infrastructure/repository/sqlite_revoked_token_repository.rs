@@ -0,0 +1,57 @@
+use crate::domain::revoked_token_repository::RevokedTokenRepository;
+use crate::errors::AppError;
+use crate::infrastructure::sqlite::{with_connection, SqliteConnection};
+use chrono::Utc;
+use rusqlite::OptionalExtension;
+use shared::api::auth::RevokedToken;
+
+pub struct SqliteRevokedTokenRepository {
+    connection: SqliteConnection,
+}
+
+impl SqliteRevokedTokenRepository {
+    pub fn new(connection: SqliteConnection) -> Self {
+        Self { connection }
+    }
+}
+
+#[async_trait::async_trait]
+impl RevokedTokenRepository for SqliteRevokedTokenRepository {
+    async fn revoke(&self, token: &RevokedToken) -> Result<(), AppError> {
+        let token = token.clone();
+        let now = Utc::now().timestamp();
+        with_connection(&self.connection, move |connection| {
+            // Forget tokens that expired in the meantime, nobody needs to know about them any more
+            connection.execute("DELETE FROM revoked_tokens WHERE expires_at <= ?1", [now])?;
+            connection.execute(
+                "INSERT OR REPLACE INTO revoked_tokens (jti, expires_at) VALUES (?1, ?2)",
+                (token.jti, token.expires_at),
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list_revoked(&self) -> Result<Vec<RevokedToken>, AppError> {
+        let now = Utc::now().timestamp();
+        with_connection(&self.connection, move |connection| {
+            let mut statement = connection.prepare("SELECT jti, expires_at FROM revoked_tokens WHERE expires_at > ?1")?;
+            let rows = statement.query_map([now], |row| {
+                Ok(RevokedToken { jti: row.get(0)?, expires_at: row.get(1)? })
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    async fn is_revoked(&self, jti: &str) -> Result<bool, AppError> {
+        let jti = jti.to_string();
+        with_connection(&self.connection, move |connection| {
+            connection
+                .query_row("SELECT 1 FROM revoked_tokens WHERE jti = ?1", [jti], |_| Ok(()))
+                .optional()
+                .map(|row| row.is_some())
+        })
+        .await
+    }
+}
@@ -0,0 +1,41 @@
+use crate::domain::used_challenge_repository::UsedChallengeRepository;
+use crate::errors::AppError;
+use crate::infrastructure::sqlite::{with_connection, SqliteConnection};
+use chrono::Utc;
+
+pub struct SqliteUsedChallengeRepository {
+    connection: SqliteConnection,
+}
+
+impl SqliteUsedChallengeRepository {
+    pub fn new(connection: SqliteConnection) -> Self {
+        Self { connection }
+    }
+}
+
+#[async_trait::async_trait]
+impl UsedChallengeRepository for SqliteUsedChallengeRepository {
+    async fn use_challenge(&self, challenge_id: &str, expires_at: i64) -> Result<bool, AppError> {
+        let challenge_id = challenge_id.to_string();
+        let now = Utc::now().timestamp();
+        with_connection(&self.connection, move |connection| {
+            // Expired challenges are refused before they get here
+            connection.execute("DELETE FROM used_challenges WHERE expires_at <= ?1", [now])?;
+            let inserted = connection.execute(
+                "INSERT OR IGNORE INTO used_challenges (challenge_id, expires_at) VALUES (?1, ?2)",
+                (challenge_id, expires_at),
+            )?;
+            Ok(inserted == 1)
+        })
+        .await
+    }
+
+    async fn release_challenge(&self, challenge_id: &str) -> Result<(), AppError> {
+        let challenge_id = challenge_id.to_string();
+        with_connection(&self.connection, move |connection| {
+            connection.execute("DELETE FROM used_challenges WHERE challenge_id = ?1", [challenge_id])?;
+            Ok(())
+        })
+        .await
+    }
+}
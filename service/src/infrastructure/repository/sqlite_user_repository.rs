@@ -0,0 +1,317 @@
+use crate::domain::user_repository::{Totp, User, UserRepository};
+use crate::errors::AppError;
+use shared::api::admin::Ban;
+use crate::infrastructure::sqlite::{with_connection, SqliteConnection};
+use rusqlite::{ErrorCode, OptionalExtension, Row};
+use uuid::Uuid;
+
+const USER_COLUMNS: &str = "id, username, display_name, password, roles, created_at, last_login_at, disabled, password_reset_required, deletion_scheduled_at, ban_reason, banned_at, banned_until, email, email_verified, totp_secret, totp_enabled, totp_recovery_codes, totp_last_step";
+
+/// Stores users in an embedded SQLite database, for small deployments and CI.
+pub struct SqliteUserRepository {
+    connection: SqliteConnection,
+}
+
+impl SqliteUserRepository {
+    pub fn new(connection: SqliteConnection) -> Self {
+        Self { connection }
+    }
+
+    async fn find_user_where(&self, condition: &'static str, value: String) -> Result<Option<User>, AppError> {
+        with_connection(&self.connection, move |connection| {
+            connection
+                .query_row(
+                    &format!("SELECT {} FROM users WHERE {} = ?1", USER_COLUMNS, condition),
+                    [value],
+                    user_from_row,
+                )
+                .optional()
+        })
+        .await
+    }
+}
+
+/// Roles and recovery code hashes are stored comma separated, neither can contain one.
+fn user_from_row(row: &Row<'_>) -> rusqlite::Result<User> {
+    let id: String = row.get(0)?;
+    let roles: String = row.get(4)?;
+    let ban = match row.get::<_, Option<i64>>(11)? {
+        Some(banned_at) => Some(Ban {
+            reason: row.get::<_, Option<String>>(10)?.unwrap_or_default(),
+            banned_at,
+            until: row.get(12)?,
+        }),
+        None => None,
+    };
+    let totp = match row.get::<_, Option<String>>(15)? {
+        Some(secret) => Some(Totp {
+            secret,
+            enabled: row.get(16)?,
+            recovery_codes: split_list(&row.get::<_, Option<String>>(17)?.unwrap_or_default()),
+            last_used_step: row.get(18)?,
+        }),
+        None => None,
+    };
+    Ok(User {
+        id: Uuid::parse_str(&id)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e)))?,
+        username: row.get(1)?,
+        display_name: row.get(2)?,
+        password_hash: row.get(3)?,
+        roles: split_list(&roles),
+        created_at: row.get(5)?,
+        last_login_at: row.get(6)?,
+        disabled: row.get(7)?,
+        password_reset_required: row.get(8)?,
+        deletion_scheduled_at: row.get(9)?,
+        ban,
+        email: row.get(13)?,
+        email_verified: row.get(14)?,
+        totp,
+    })
+}
+
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',').filter(|item| !item.is_empty()).map(String::from).collect()
+}
+
+#[async_trait::async_trait]
+impl UserRepository for SqliteUserRepository {
+    async fn find_user_by_username(&self, username: &str) -> Result<Option<User>, AppError> {
+        self.find_user_where("username", username.to_string()).await
+    }
+
+    async fn find_user_by_id(&self, id: Uuid) -> Result<Option<User>, AppError> {
+        self.find_user_where("id", id.to_string()).await
+    }
+
+    async fn create_user(&self, user: &User) -> Result<(), AppError> {
+        let user = user.clone();
+        let inserted = with_connection(&self.connection, move |connection| {
+            // The primary key makes the insert fail rather than replace an existing user
+            match connection.execute(
+                &format!("INSERT INTO users ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)", USER_COLUMNS),
+                rusqlite::params![
+                    user.id.to_string(),
+                    user.username,
+                    user.display_name,
+                    user.password_hash,
+                    user.roles.join(","),
+                    user.created_at,
+                    user.last_login_at,
+                    user.disabled,
+                    user.password_reset_required,
+                    user.deletion_scheduled_at,
+                    user.ban.as_ref().map(|ban| ban.reason.clone()),
+                    user.ban.as_ref().map(|ban| ban.banned_at),
+                    user.ban.as_ref().and_then(|ban| ban.until),
+                    user.email,
+                    user.email_verified,
+                    user.totp.as_ref().map(|totp| totp.secret.clone()),
+                    user.totp.as_ref().is_some_and(|totp| totp.enabled),
+                    user.totp.as_ref().map(|totp| totp.recovery_codes.join(",")),
+                    user.totp.as_ref().and_then(|totp| totp.last_used_step),
+                ],
+            ) {
+                Ok(_) => Ok(true),
+                Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == ErrorCode::ConstraintViolation => Ok(false),
+                Err(e) => Err(e),
+            }
+        })
+        .await?;
+
+        if inserted {
+            Ok(())
+        } else {
+            Err(AppError::username_taken())
+        }
+    }
+
+    async fn update_password(&self, id: Uuid, password_hash: String) -> Result<(), AppError> {
+        with_connection(&self.connection, move |connection| {
+            connection.execute(
+                "UPDATE users SET password = ?1, password_reset_required = 0 WHERE id = ?2",
+                (password_hash, id.to_string()),
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn record_login(&self, id: Uuid, at: i64) -> Result<(), AppError> {
+        with_connection(&self.connection, move |connection| {
+            connection.execute("UPDATE users SET last_login_at = ?1 WHERE id = ?2", (at, id.to_string()))?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update_display_name(&self, id: Uuid, display_name: String) -> Result<(), AppError> {
+        with_connection(&self.connection, move |connection| {
+            connection.execute("UPDATE users SET display_name = ?1 WHERE id = ?2", (display_name, id.to_string()))?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn schedule_deletion(&self, id: Uuid, at: Option<i64>) -> Result<(), AppError> {
+        with_connection(&self.connection, move |connection| {
+            connection.execute("UPDATE users SET deletion_scheduled_at = ?1 WHERE id = ?2", (at, id.to_string()))?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_user(&self, id: Uuid) -> Result<(), AppError> {
+        with_connection(&self.connection, move |connection| {
+            connection.execute("DELETE FROM users WHERE id = ?1", [id.to_string()])?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list_users(&self, after: Option<&str>, limit: usize) -> Result<Vec<User>, AppError> {
+        let after = after.unwrap_or_default().to_string();
+        with_connection(&self.connection, move |connection| {
+            let mut statement = connection.prepare(&format!(
+                "SELECT {} FROM users WHERE username > ?1 ORDER BY username LIMIT ?2",
+                USER_COLUMNS
+            ))?;
+            let rows = statement.query_map((after, limit as i64), user_from_row)?;
+            rows.collect()
+        })
+        .await
+    }
+
+    async fn set_roles(&self, id: Uuid, roles: Vec<String>) -> Result<(), AppError> {
+        with_connection(&self.connection, move |connection| {
+            connection.execute("UPDATE users SET roles = ?1 WHERE id = ?2", (roles.join(","), id.to_string()))?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn set_disabled(&self, id: Uuid, disabled: bool) -> Result<(), AppError> {
+        with_connection(&self.connection, move |connection| {
+            connection.execute("UPDATE users SET disabled = ?1 WHERE id = ?2", (disabled, id.to_string()))?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn set_ban(&self, id: Uuid, ban: Option<Ban>) -> Result<(), AppError> {
+        with_connection(&self.connection, move |connection| {
+            connection.execute(
+                "UPDATE users SET ban_reason = ?1, banned_at = ?2, banned_until = ?3 WHERE id = ?4",
+                (
+                    ban.as_ref().map(|ban| ban.reason.clone()),
+                    ban.as_ref().map(|ban| ban.banned_at),
+                    ban.as_ref().and_then(|ban| ban.until),
+                    id.to_string(),
+                ),
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list_active_bans(&self, now: i64) -> Result<Vec<(Uuid, Ban)>, AppError> {
+        with_connection(&self.connection, move |connection| {
+            let mut statement = connection.prepare(&format!(
+                "SELECT {} FROM users
+                    WHERE banned_at IS NOT NULL AND (banned_until IS NULL OR banned_until > ?1)",
+                USER_COLUMNS
+            ))?;
+            let rows = statement.query_map([now], user_from_row)?;
+            let mut bans = Vec::new();
+            for user in rows {
+                let user = user?;
+                if let Some(ban) = user.ban {
+                    bans.push((user.id, ban));
+                }
+            }
+            Ok(bans)
+        })
+        .await
+    }
+
+    async fn require_password_reset(&self, id: Uuid) -> Result<(), AppError> {
+        with_connection(&self.connection, move |connection| {
+            connection.execute("UPDATE users SET password_reset_required = 1 WHERE id = ?1", [id.to_string()])?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn mark_email_verified(&self, id: Uuid) -> Result<(), AppError> {
+        with_connection(&self.connection, move |connection| {
+            connection.execute("UPDATE users SET email_verified = 1 WHERE id = ?1", [id.to_string()])?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update_email(&self, id: Uuid, email: String) -> Result<(), AppError> {
+        with_connection(&self.connection, move |connection| {
+            connection.execute(
+                "UPDATE users SET email = ?1, email_verified = 0 WHERE id = ?2",
+                (email, id.to_string()),
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn set_totp(&self, id: Uuid, totp: Option<Totp>) -> Result<(), AppError> {
+        with_connection(&self.connection, move |connection| {
+            connection.execute(
+                "UPDATE users SET totp_secret = ?1, totp_enabled = ?2, totp_recovery_codes = ?3, totp_last_step = ?4
+                    WHERE id = ?5",
+                (
+                    totp.as_ref().map(|totp| totp.secret.clone()),
+                    totp.as_ref().is_some_and(|totp| totp.enabled),
+                    totp.as_ref().map(|totp| totp.recovery_codes.join(",")),
+                    totp.as_ref().and_then(|totp| totp.last_used_step),
+                    id.to_string(),
+                ),
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn use_recovery_code(&self, id: Uuid, code_hash: &str) -> Result<bool, AppError> {
+        let code_hash = code_hash.to_string();
+        with_connection(&self.connection, move |connection| {
+            let codes: Option<String> = connection
+                .query_row("SELECT totp_recovery_codes FROM users WHERE id = ?1", [id.to_string()], |row| row.get(0))
+                .optional()?
+                .flatten();
+            let codes = split_list(&codes.unwrap_or_default());
+            if !codes.contains(&code_hash) {
+                return Ok(false);
+            }
+
+            // Conditional on the codes read, so of two concurrent uses only one succeeds
+            let remaining: Vec<String> = codes.iter().filter(|hash| **hash != code_hash).cloned().collect();
+            let updated = connection.execute(
+                "UPDATE users SET totp_recovery_codes = ?1 WHERE id = ?2 AND totp_recovery_codes = ?3",
+                (remaining.join(","), id.to_string(), codes.join(",")),
+            )?;
+            Ok(updated == 1)
+        })
+        .await
+    }
+
+    async fn use_totp_step(&self, id: Uuid, step: i64) -> Result<bool, AppError> {
+        with_connection(&self.connection, move |connection| {
+            let updated = connection.execute(
+                "UPDATE users SET totp_last_step = ?1
+                    WHERE id = ?2 AND totp_secret IS NOT NULL AND (totp_last_step IS NULL OR totp_last_step < ?1)",
+                (step, id.to_string()),
+            )?;
+            Ok(updated == 1)
+        })
+        .await
+    }
+}
@@ -0,0 +1,144 @@
+use rusqlite::Connection;
+use std::sync::{Arc, Mutex};
+use crate::errors::AppError;
+
+pub type SqliteConnection = Arc<Mutex<Connection>>;
+
+/// Schema versions in order, the applied count is kept in `PRAGMA user_version`. Like the
+/// Scylla migrations, a released entry must not be edited; add a new one instead.
+const MIGRATIONS: &[&str] = &[
+    "
+        CREATE TABLE IF NOT EXISTS users (
+            username TEXT PRIMARY KEY,
+            password TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS refresh_token_families (
+            family_id TEXT PRIMARY KEY,
+            username TEXT NOT NULL,
+            current_token_hash TEXT NOT NULL,
+            revoked INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS revoked_tokens (
+            jti TEXT PRIMARY KEY,
+            expires_at INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS login_attempts (
+            key TEXT PRIMARY KEY,
+            failures INTEGER NOT NULL,
+            blocked_until INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL
+        );
+    ",
+    // Rich user records: a stable id and profile fields. Existing users get a random v4
+    // id, and refresh token families now point at user ids, so everyone logs in again.
+    "
+        ALTER TABLE users ADD COLUMN id TEXT;
+        ALTER TABLE users ADD COLUMN display_name TEXT NOT NULL DEFAULT '';
+        ALTER TABLE users ADD COLUMN roles TEXT NOT NULL DEFAULT 'player';
+        ALTER TABLE users ADD COLUMN created_at INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE users ADD COLUMN last_login_at INTEGER;
+        ALTER TABLE users ADD COLUMN disabled INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE users ADD COLUMN password_reset_required INTEGER NOT NULL DEFAULT 0;
+        UPDATE users SET display_name = username, created_at = CAST(strftime('%s', 'now') AS INTEGER);
+        UPDATE users SET id = lower(
+            hex(randomblob(4)) || '-' || hex(randomblob(2)) || '-4' || substr(hex(randomblob(2)), 2) || '-'
+            || substr('89ab', 1 + abs(random()) % 4, 1) || substr(hex(randomblob(2)), 2) || '-' || hex(randomblob(6))
+        );
+        CREATE UNIQUE INDEX users_by_id ON users (id);
+        DROP TABLE refresh_token_families;
+        CREATE TABLE refresh_token_families (
+            family_id TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            current_token_hash TEXT NOT NULL,
+            revoked INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL
+        );
+    ",
+    "
+        ALTER TABLE users ADD COLUMN deletion_scheduled_at INTEGER;
+        CREATE INDEX refresh_token_families_by_user ON refresh_token_families (user_id);
+    ",
+    "
+        ALTER TABLE users ADD COLUMN ban_reason TEXT;
+        ALTER TABLE users ADD COLUMN banned_at INTEGER;
+        ALTER TABLE users ADD COLUMN banned_until INTEGER;
+    ",
+    "
+        ALTER TABLE users ADD COLUMN email TEXT;
+        ALTER TABLE users ADD COLUMN email_verified INTEGER NOT NULL DEFAULT 0;
+    ",
+    "
+        ALTER TABLE users ADD COLUMN totp_secret TEXT;
+        ALTER TABLE users ADD COLUMN totp_enabled INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE users ADD COLUMN totp_recovery_codes TEXT;
+    ",
+    "
+        ALTER TABLE refresh_token_families ADD COLUMN created_at INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE refresh_token_families ADD COLUMN last_seen_at INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE refresh_token_families ADD COLUMN user_agent TEXT;
+        ALTER TABLE refresh_token_families ADD COLUMN ip TEXT;
+    ",
+    "
+        ALTER TABLE users ADD COLUMN totp_last_step INTEGER;
+    ",
+    "
+        ALTER TABLE refresh_token_families ADD COLUMN previous_token_hash TEXT;
+    ",
+    "
+        CREATE TABLE IF NOT EXISTS used_challenges (
+            challenge_id TEXT PRIMARY KEY,
+            expires_at INTEGER NOT NULL
+        );
+    ",
+];
+
+/// Opens the database behind a `sqlite:` URL, e.g. `sqlite://inner_shelter.db` or
+/// `sqlite::memory:`, and brings its schema up to date.
+pub fn open_sqlite(url: &str) -> Result<SqliteConnection, AppError> {
+    let path = url
+        .strip_prefix("sqlite://")
+        .or_else(|| url.strip_prefix("sqlite:"))
+        .ok_or_else(|| AppError::DbError(format!("Not a sqlite URL: {}", url)))?;
+
+    let connection = if path == ":memory:" {
+        Connection::open_in_memory()
+    } else {
+        Connection::open(path)
+    }
+    .map_err(|e| AppError::DbError(e.to_string()))?;
+
+    migrate(&connection).map_err(|e| AppError::DbError(e.to_string()))?;
+
+    Ok(Arc::new(Mutex::new(connection)))
+}
+
+fn migrate(connection: &Connection) -> rusqlite::Result<()> {
+    let applied: usize = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        log::info!("Applying sqlite migration {}", index + 1);
+        // Each migration and its version bump commit together or not at all
+        connection.execute_batch(&format!(
+            "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+            migration,
+            index + 1
+        ))?;
+    }
+    Ok(())
+}
+
+/// Runs `f` against the connection on the blocking pool, SQLite calls are synchronous.
+pub async fn with_connection<T, F>(connection: &SqliteConnection, f: F) -> Result<T, AppError>
+where
+    T: Send + 'static,
+    F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+{
+    let connection = connection.clone();
+    tokio::task::spawn_blocking(move || {
+        let connection = connection.lock().map_err(|e| AppError::DbError(e.to_string()))?;
+        f(&connection).map_err(|e| AppError::DbError(e.to_string()))
+    })
+    .await
+    .map_err(|e| AppError::DbError(e.to_string()))?
+}
@@ -0,0 +1,80 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand::{Rng, RngCore};
+use sha2::{Digest, Sha256};
+use totp_rs::{Algorithm, Secret, TOTP};
+use crate::errors::AppError;
+
+/// Shown as the account's issuer in authenticator apps.
+const ISSUER: &str = "Inner Shelter";
+/// How many recovery codes an enrolment hands out.
+pub const RECOVERY_CODE_COUNT: usize = 10;
+/// Recovery codes avoid characters that are easily mixed up when typed from paper.
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+/// Seconds per code.
+const STEP: i64 = 30;
+/// Steps of clock drift allowed either way.
+const SKEW: i64 = 1;
+
+/// The parameters every authenticator app understands: SHA-1, six digits every 30
+/// seconds. Drift is allowed for by `matching_step` rather than the library, which
+/// would not tell which step matched.
+fn totp(secret: &str, username: &str) -> Result<TOTP, AppError> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|e| AppError::DbError(format!("Unreadable TOTP secret: {}", e)))?;
+    TOTP::new(Algorithm::SHA1, 6, 0, STEP as u64, secret, Some(ISSUER.to_string()), username.to_string())
+        .map_err(|e| AppError::AuthError(e.to_string()))
+}
+
+/// A new random 160 bit secret, base32 encoded as authenticator apps expect it.
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    Secret::Raw(bytes.to_vec()).to_encoded().to_string()
+}
+
+/// The `otpauth://` URI that authenticator apps scan, usually shown as a QR code.
+pub fn otpauth_uri(secret: &str, username: &str) -> Result<String, AppError> {
+    Ok(totp(secret, username)?.get_url())
+}
+
+/// The time step of the six digit code if it is the current one, or the one just before
+/// or after it. Callers record the step to refuse the same code a second time.
+pub fn matching_step(secret: &str, username: &str, code: &str, now: i64) -> Result<Option<i64>, AppError> {
+    let totp = totp(secret, username)?;
+    let current = now.max(0) / STEP;
+    Ok(((current - SKEW).max(0)..=current + SKEW).find(|step| totp.check(code, (step * STEP) as u64)))
+}
+
+/// Whether the input looks like a TOTP code rather than a recovery code.
+pub fn is_totp_code(code: &str) -> bool {
+    code.len() == 6 && code.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+/// Fresh recovery codes, formatted `xxxxx-xxxxx` for reading off paper.
+pub fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code: String = (0..RECOVERY_CODE_LENGTH)
+                .map(|_| RECOVERY_CODE_ALPHABET[rng.gen_range(0..RECOVERY_CODE_ALPHABET.len())] as char)
+                .collect();
+            format!("{}-{}", &code[..RECOVERY_CODE_LENGTH / 2], &code[RECOVERY_CODE_LENGTH / 2..])
+        })
+        .collect()
+}
+
+/// Recovery codes are stored hashed like refresh secrets. Being random and tried only
+/// under login throttling, they do not need a slow hash. Case, spaces and dashes the
+/// user typed do not matter.
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    URL_SAFE_NO_PAD.encode(Sha256::digest(normalized.as_bytes()))
+}
@@ -0,0 +1,6 @@
+pub mod config;
+pub mod domain;
+pub mod infrastructure;
+pub mod application;
+pub mod presentation;
+pub mod errors;
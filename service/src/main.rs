@@ -1,10 +1,8 @@
-mod config;
-mod domain;
-mod infrastructure;
-mod application;
-mod presentation;
-mod errors;
-
+use service::config::Config;
+use service::errors::AppError;
+use service::infrastructure::repository::create_repositories;
+use service::presentation;
+use shared::api::auth::Role;
 use std::env;
 
 #[tokio::main]
@@ -13,12 +11,66 @@ async fn main() -> std::io::Result<()> {
     env::set_var("RUST_LOG", "info"); // Set default log level
     env_logger::init();
 
+    // Apply the database schema and exit, so deploys can migrate before starting new instances
+    if env::args().any(|arg| arg == "--migrate-only") {
+        return match migrate().await {
+            Ok(_) => {
+                log::info!("Migrations applied");
+                Ok(())
+            }
+            Err(e) => {
+                log::error!("Migrations failed: {}", e);
+                Err(std::io::Error::other("Migrations failed"))
+            }
+        };
+    }
+
+    // Give an existing account a role and exit, the way to appoint the first admin
+    let args: Vec<String> = env::args().collect();
+    if let Some(position) = args.iter().position(|arg| arg == "--grant-role") {
+        let (Some(username), Some(role)) = (args.get(position + 1), args.get(position + 2)) else {
+            log::error!("Usage: --grant-role <username> <player|moderator|admin>");
+            return Err(std::io::Error::other("Missing arguments"));
+        };
+        return match grant_role(username, role).await {
+            Ok(_) => {
+                log::info!("Granted {} the {} role", username, role);
+                Ok(())
+            }
+            Err(e) => {
+                log::error!("Granting the role failed: {}", e);
+                Err(std::io::Error::other("Granting the role failed"))
+            }
+        };
+    }
+
     // Start the server and handle potential AppError
     match presentation::routes::start_server().await {
         Ok(_) => Ok(()),
         Err(e) => {
             log::error!("Server failed to start: {}", e);
-            Err(std::io::Error::new(std::io::ErrorKind::Other, "Server failed to start"))
+            Err(std::io::Error::other("Server failed to start"))
         }
     }
 }
+
+/// Connecting to the store applies its pending migrations.
+async fn migrate() -> Result<(), AppError> {
+    let config = Config::new()?;
+    create_repositories(&config).await?;
+    Ok(())
+}
+
+async fn grant_role(username: &str, role: &str) -> Result<(), AppError> {
+    let role: Role = role.parse().map_err(AppError::ValidationError)?;
+    let config = Config::new()?;
+    let repositories = create_repositories(&config).await?;
+
+    let user = repositories.users.find_user_by_username(username).await?
+        .ok_or_else(|| AppError::NotFoundError(format!("User {}", username)))?;
+    let mut roles = user.roles;
+    if !roles.iter().any(|existing| existing == role.as_str()) {
+        roles.push(role.as_str().to_string());
+    }
+    repositories.users.set_roles(user.id, roles).await
+}
@@ -1,34 +1,83 @@
+use actix_web::http::header;
 use actix_web::{web, App, HttpServer};
 use actix_cors::Cors;
-use crate::infrastructure::db::get_db_session;
-use crate::infrastructure::repository::create_user_repository;
-use crate::application::{login, register};
+use crate::infrastructure::game_servers::GameServers;
+use crate::infrastructure::hashing_pool::HashingPool;
+use crate::infrastructure::mailer::create_mailer;
+use crate::infrastructure::repository::create_repositories;
+use crate::application::account_purge::spawn_account_purge;
+use crate::application::{
+    admin, internal, jwks, login, logout, me, password_reset, refresh, register, revocations, verify_email,
+};
 use crate::config::Config;
 use crate::errors::AppError;
+use shared::api::error::{ApiError, ErrorCode};
 
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
-    cfg.service(login::login)
-        .service(register::register);
+    // Malformed bodies, paths and queries get the same JSON error shape as everything else
+    cfg.app_data(web::JsonConfig::default().error_handler(|err, _| {
+        AppError::ValidationError(ApiError::new(ErrorCode::InvalidInput, err.to_string())).into()
+    }))
+    .app_data(web::PathConfig::default().error_handler(|err, _| {
+        AppError::ValidationError(ApiError::new(ErrorCode::InvalidInput, err.to_string())).into()
+    }))
+    .app_data(web::QueryConfig::default().error_handler(|err, _| {
+        AppError::ValidationError(ApiError::new(ErrorCode::InvalidInput, err.to_string())).into()
+    }));
+
+    cfg.service(jwks::jwks)
+        .service(login::login)
+        .service(login::login_mfa)
+        .service(logout::logout)
+        .service(me::profile)
+        .service(me::update_profile)
+        .service(me::change_password)
+        .service(me::delete_account)
+        .service(me::enrol_totp)
+        .service(me::confirm_totp)
+        .service(me::disable_totp)
+        .service(me::sessions)
+        .service(me::revoke_session)
+        .service(password_reset::request_password_reset)
+        .service(password_reset::confirm_password_reset)
+        .service(refresh::refresh)
+        .service(register::register)
+        .service(revocations::revocations)
+        .service(verify_email::verify_email)
+        .service(admin::scope())
+        .service(internal::scope());
 }
 
 pub async fn start_server() -> Result<(), AppError> {
-    let config = Config::new();
+    let config = Config::new()?;
+
+    let repositories = create_repositories(&config).await?;
+    let hashing = HashingPool::from_config(&config)?;
+    let game_servers = GameServers::from_config(&config);
+    let mailer = create_mailer(&config)?;
 
-    // Attempt to get the database session
-    let db_session = get_db_session(&config.db_url).await?;
-    let user_repository = create_user_repository(db_session.clone());
+    // Accounts past their deletion grace period are removed, freeing their usernames
+    spawn_account_purge(repositories.users.clone(), config.account_purge_interval);
 
     HttpServer::new(move || {
         let cors = Cors::default()
             .allowed_origin("http://innershelter.org:8082")
             .allow_any_method()
             .allow_any_header()
+            .expose_headers([header::RETRY_AFTER])
             .supports_credentials();
 
         App::new()
             .wrap(cors)
             .app_data(web::Data::new(config.clone()))
-            .app_data(web::Data::new(user_repository.clone()))
+            .app_data(web::Data::new(repositories.users.clone()))
+            .app_data(web::Data::new(repositories.refresh_tokens.clone()))
+            .app_data(web::Data::new(repositories.revoked_tokens.clone()))
+            .app_data(web::Data::new(repositories.login_attempts.clone()))
+            .app_data(web::Data::new(repositories.used_challenges.clone()))
+            .app_data(web::Data::new(hashing.clone()))
+            .app_data(web::Data::new(game_servers.clone()))
+            .app_data(web::Data::new(mailer.clone()))
             .configure(init_routes)
     })
     .bind("127.0.0.1:8080")
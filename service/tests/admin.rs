@@ -0,0 +1,324 @@
+mod common;
+
+use actix_web::cookie::Cookie;
+use actix_web::http::StatusCode;
+use actix_web::test;
+use common::{login_request, refresh_request, INTERNAL_API_TOKEN};
+use service::infrastructure::repository::Repositories;
+use shared::api::admin::{Ban, BanData, BanUpdate, SetRolesData, UserPage, UserSummary, INTERNAL_TOKEN_HEADER};
+use shared::api::auth::{LoginData, Role};
+use shared::api::error::{ApiError, ErrorCode};
+
+/// Gives an already registered user extra roles straight in the store, the way
+/// `--grant-role` does.
+async fn grant(repositories: &Repositories, username: &str, roles: &[&str]) {
+    let user = repositories.users.find_user_by_username(username).await.unwrap().unwrap();
+    repositories.users.set_roles(user.id, roles.iter().map(|role| role.to_string()).collect()).await.unwrap();
+}
+
+async fn user_id(repositories: &Repositories, username: &str) -> String {
+    repositories.users.find_user_by_username(username).await.unwrap().unwrap().id.to_string()
+}
+
+fn admin_request(method: &str, path: &str, access_token: &Cookie<'_>) -> test::TestRequest {
+    let req = match method {
+        "GET" => test::TestRequest::get(),
+        "PUT" => test::TestRequest::put(),
+        "DELETE" => test::TestRequest::delete(),
+        _ => test::TestRequest::post(),
+    };
+    req.uri(&format!("/admin{}", path)).cookie(access_token.clone().into_owned())
+}
+
+fn ban_data(reason: &str, duration: Option<i64>) -> BanData {
+    BanData { reason: reason.to_string(), duration }
+}
+
+#[actix_web::test]
+async fn players_cannot_use_the_admin_api() {
+    let app = init_app!();
+    let (access_token, _) = session!(&app, "alice", "correct horse");
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/admin/users").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let resp = test::call_service(&app, admin_request("GET", "/users", &access_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let error: ApiError = test::read_body_json(resp).await;
+    assert_eq!(error.code, ErrorCode::Forbidden);
+}
+
+#[actix_web::test]
+async fn moderators_page_through_users() {
+    let repositories = Repositories::in_memory();
+    let app = init_app!(repositories.clone());
+    for username in ["alice", "bob", "carol"] {
+        session!(&app, username, "correct horse");
+    }
+    grant(&repositories, "alice", &["player", "moderator"]).await;
+    let (access_token, _) = log_in!(&app, "alice", "correct horse");
+
+    let req = admin_request("GET", "/users?limit=2", &access_token);
+    let page: UserPage = test::call_and_read_body_json(&app, req.to_request()).await;
+    assert_eq!(page.users.len(), 2);
+    let next = page.next.expect("a second page");
+
+    let req = admin_request("GET", &format!("/users?limit=2&after={}", next), &access_token);
+    let last: UserPage = test::call_and_read_body_json(&app, req.to_request()).await;
+    assert_eq!(last.users.len(), 1);
+    assert_eq!(last.next, None);
+
+    let mut usernames: Vec<String> = page.users.iter().chain(&last.users).map(|user| user.username.clone()).collect();
+    usernames.sort();
+    assert_eq!(usernames, ["alice", "bob", "carol"]);
+
+    let req = admin_request("GET", &format!("/users/{}", user_id(&repositories, "bob").await), &access_token);
+    let bob: UserSummary = test::call_and_read_body_json(&app, req.to_request()).await;
+    assert_eq!(bob.username, "bob");
+}
+
+#[actix_web::test]
+async fn unknown_and_malformed_user_ids() {
+    let repositories = Repositories::in_memory();
+    let app = init_app!(repositories.clone());
+    session!(&app, "alice", "correct horse");
+    grant(&repositories, "alice", &["moderator"]).await;
+    let (access_token, _) = log_in!(&app, "alice", "correct horse");
+
+    let path = format!("/users/{}", uuid::Uuid::new_v4());
+    let resp = test::call_service(&app, admin_request("GET", &path, &access_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let resp = test::call_service(&app, admin_request("GET", "/users/bob", &access_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn ban_ends_sessions_until_unbanned() {
+    let repositories = Repositories::in_memory();
+    let app = init_app!(repositories.clone());
+    session!(&app, "alice", "correct horse");
+    let (_, bobs_refresh_token) = session!(&app, "bob", "correct horse");
+    grant(&repositories, "alice", &["moderator"]).await;
+    let (access_token, _) = log_in!(&app, "alice", "correct horse");
+    let bob = user_id(&repositories, "bob").await;
+
+    let req = admin_request("POST", &format!("/users/{}/ban", bob), &access_token).set_json(ban_data("cheating", None));
+    let summary: UserSummary = test::call_and_read_body_json(&app, req.to_request()).await;
+    assert_eq!(summary.ban.as_ref().map(|ban| ban.until), Some(None));
+
+    let resp = test::call_service(&app, login_request("bob", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let error: ApiError = test::read_body_json(resp).await;
+    assert_eq!(error.code, ErrorCode::Banned);
+    assert_eq!(error.ban.map(|ban| ban.reason), Some("cheating".to_string()));
+    let resp = test::call_service(&app, refresh_request(&bobs_refresh_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let req = admin_request("POST", &format!("/users/{}/unban", bob), &access_token);
+    let summary: UserSummary = test::call_and_read_body_json(&app, req.to_request()).await;
+    assert_eq!(summary.ban, None);
+
+    let resp = test::call_service(&app, login_request("bob", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn timed_bans_run_out() {
+    let repositories = Repositories::in_memory();
+    let app = init_app!(repositories.clone());
+    session!(&app, "alice", "correct horse");
+    session!(&app, "bob", "correct horse");
+    grant(&repositories, "alice", &["moderator"]).await;
+    let (access_token, _) = log_in!(&app, "alice", "correct horse");
+    let path = format!("/users/{}/ban", user_id(&repositories, "bob").await);
+
+    for invalid in [ban_data("  ", None), ban_data("spam", Some(0)), ban_data("spam", Some(i64::MAX))] {
+        let req = admin_request("POST", &path, &access_token).set_json(invalid);
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    let req = admin_request("POST", &path, &access_token).set_json(ban_data("spam", Some(3600)));
+    let summary: UserSummary = test::call_and_read_body_json(&app, req.to_request()).await;
+    let until = summary.ban.and_then(|ban| ban.until).expect("a timed ban");
+
+    let resp = test::call_service(&app, login_request("bob", "correct horse").to_request()).await;
+    let error: ApiError = test::read_body_json(resp).await;
+    assert_eq!(error.ban.and_then(|ban| ban.until), Some(until));
+
+    // Once it has run out, the ban is no longer in the way
+    let bob = repositories.users.find_user_by_username("bob").await.unwrap().unwrap();
+    let expired = Ban { until: Some(chrono::Utc::now().timestamp() - 1), ..bob.ban.unwrap() };
+    repositories.users.set_ban(bob.id, Some(expired)).await.unwrap();
+    let resp = test::call_service(&app, login_request("bob", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn game_servers_list_bans_with_the_internal_token() {
+    let repositories = Repositories::in_memory();
+    let app = init_app!(repositories.clone());
+    session!(&app, "alice", "correct horse");
+    session!(&app, "bob", "correct horse");
+    grant(&repositories, "alice", &["moderator"]).await;
+    let (access_token, _) = log_in!(&app, "alice", "correct horse");
+    let bob = user_id(&repositories, "bob").await;
+    let req = admin_request("POST", &format!("/users/{}/ban", bob), &access_token).set_json(ban_data("cheating", None));
+    test::call_service(&app, req.to_request()).await;
+
+    for token in [None, Some("wrong")] {
+        let mut req = test::TestRequest::get().uri("/internal/bans");
+        if let Some(token) = token {
+            req = req.insert_header((INTERNAL_TOKEN_HEADER, token));
+        }
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/internal/bans")
+        .insert_header((INTERNAL_TOKEN_HEADER, INTERNAL_API_TOKEN));
+    let bans: Vec<BanUpdate> = test::call_and_read_body_json(&app, req.to_request()).await;
+    assert_eq!(bans.len(), 1);
+    assert_eq!(bans[0].user_id, bob);
+}
+
+#[actix_web::test]
+async fn staff_cannot_act_on_their_peers_or_themselves() {
+    let repositories = Repositories::in_memory();
+    let app = init_app!(repositories.clone());
+    for username in ["alice", "bob", "carol"] {
+        session!(&app, username, "correct horse");
+    }
+    grant(&repositories, "alice", &["moderator"]).await;
+    grant(&repositories, "bob", &["moderator"]).await;
+    grant(&repositories, "carol", &["admin"]).await;
+    let (moderator_token, _) = log_in!(&app, "alice", "correct horse");
+    let (admin_token, _) = log_in!(&app, "carol", "correct horse");
+
+    for (target, token) in [("bob", &moderator_token), ("carol", &moderator_token), ("carol", &admin_token)] {
+        let path = format!("/users/{}/ban", user_id(&repositories, target).await);
+        let req = admin_request("POST", &path, token).set_json(ban_data("spam", None));
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN, "banned {}", target);
+    }
+
+    let path = format!("/users/{}/ban", user_id(&repositories, "bob").await);
+    let req = admin_request("POST", &path, &admin_token).set_json(ban_data("spam", None));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn forced_password_reset_happens_at_login() {
+    let repositories = Repositories::in_memory();
+    let app = init_app!(repositories.clone());
+    session!(&app, "alice", "correct horse");
+    let (_, bobs_refresh_token) = session!(&app, "bob", "correct horse");
+    grant(&repositories, "alice", &["moderator"]).await;
+    let (access_token, _) = log_in!(&app, "alice", "correct horse");
+
+    let path = format!("/users/{}/password-reset", user_id(&repositories, "bob").await);
+    let summary: UserSummary = test::call_and_read_body_json(&app, admin_request("POST", &path, &access_token).to_request()).await;
+    assert!(summary.password_reset_required);
+
+    let resp = test::call_service(&app, refresh_request(&bobs_refresh_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let resp = test::call_service(&app, login_request("bob", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let error: ApiError = test::read_body_json(resp).await;
+    assert_eq!(error.code, ErrorCode::PasswordResetRequired);
+
+    let login = |new_password: &str| test::TestRequest::post().uri("/login").set_json(LoginData {
+        username: "bob".to_string(),
+        password: "correct horse".to_string(),
+        include_token: false,
+        new_password: Some(new_password.to_string()),
+    });
+    let resp = test::call_service(&app, login("correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = test::call_service(&app, login("staple battery horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = test::call_service(&app, login_request("bob", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app, login_request("bob", "staple battery horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn only_admins_set_roles() {
+    let repositories = Repositories::in_memory();
+    let app = init_app!(repositories.clone());
+    for username in ["alice", "bob", "carol"] {
+        session!(&app, username, "correct horse");
+    }
+    grant(&repositories, "alice", &["moderator"]).await;
+    grant(&repositories, "carol", &["admin"]).await;
+    let (moderator_token, _) = log_in!(&app, "alice", "correct horse");
+    let (admin_token, _) = log_in!(&app, "carol", "correct horse");
+    let path = format!("/users/{}/roles", user_id(&repositories, "bob").await);
+    let roles = SetRolesData { roles: vec![Role::Moderator, Role::Player, Role::Moderator] };
+
+    let req = admin_request("PUT", &path, &moderator_token).set_json(&roles);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let req = admin_request("PUT", &path, &admin_token).set_json(&roles);
+    let summary: UserSummary = test::call_and_read_body_json(&app, req.to_request()).await;
+    assert_eq!(summary.roles, ["player", "moderator"]);
+
+    // The next token carries them
+    let (access_token, _) = log_in!(&app, "bob", "correct horse");
+    let resp = test::call_service(&app, admin_request("GET", "/users", &access_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let req = admin_request("PUT", &path, &admin_token)
+        .set_payload(r#"{"roles": ["overlord"]}"#)
+        .insert_header(("Content-Type", "application/json"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn demoted_staff_lose_access_at_once() {
+    let repositories = Repositories::in_memory();
+    let app = init_app!(repositories.clone());
+    session!(&app, "alice", "correct horse");
+    grant(&repositories, "alice", &["admin"]).await;
+    let (access_token, _) = log_in!(&app, "alice", "correct horse");
+    let resp = test::call_service(&app, admin_request("GET", "/users", &access_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // The token still says admin
+    grant(&repositories, "alice", &["player"]).await;
+    let resp = test::call_service(&app, admin_request("GET", "/users", &access_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn disabled_accounts_stay_out_until_enabled() {
+    let repositories = Repositories::in_memory();
+    let app = init_app!(repositories.clone());
+    session!(&app, "alice", "correct horse");
+    let (bobs_access_token, _) = session!(&app, "bob", "correct horse");
+    grant(&repositories, "alice", &["moderator"]).await;
+    let (access_token, _) = log_in!(&app, "alice", "correct horse");
+    let bob = user_id(&repositories, "bob").await;
+
+    let req = admin_request("POST", &format!("/users/{}/disable", bob), &access_token);
+    let summary: UserSummary = test::call_and_read_body_json(&app, req.to_request()).await;
+    assert!(summary.disabled);
+    let resp = test::call_service(&app, login_request("bob", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let req = test::TestRequest::get().uri("/me").cookie(bobs_access_token).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let req = admin_request("POST", &format!("/users/{}/enable", bob), &access_token);
+    let summary: UserSummary = test::call_and_read_body_json(&app, req.to_request()).await;
+    assert!(!summary.disabled);
+    log_in!(&app, "bob", "correct horse");
+}
@@ -0,0 +1,214 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test;
+use common::{login_request, register_request, response_cookie, test_config};
+use service::config::Config;
+use service::infrastructure::authentication::HashingParams;
+use service::infrastructure::repository::Repositories;
+use shared::api::auth::{LoginData, LoginResponse};
+use shared::api::error::{ApiError, ErrorCode};
+use std::time::Duration;
+
+#[actix_web::test]
+async fn register_creates_user() {
+    let app = init_app!();
+
+    let resp = test::call_service(&app, register_request("alice", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn register_rejects_taken_username() {
+    let app = init_app!();
+
+    let resp = test::call_service(&app, register_request("alice", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = test::call_service(&app, register_request("alice", "another one").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let error: ApiError = test::read_body_json(resp).await;
+    assert_eq!(error.code, ErrorCode::UsernameTaken);
+
+    // The original password still works
+    let resp = test::call_service(&app, login_request("alice", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn concurrent_registrations_create_one_user() {
+    let app = init_app!();
+
+    let (first, second) = futures::join!(
+        test::call_service(&app, register_request("alice", "correct horse").to_request()),
+        test::call_service(&app, register_request("alice", "another one").to_request()),
+    );
+
+    let mut statuses = [first.status(), second.status()];
+    statuses.sort();
+    assert_eq!(statuses, [StatusCode::OK, StatusCode::CONFLICT]);
+}
+
+#[actix_web::test]
+async fn register_rejects_short_username() {
+    let app = init_app!();
+
+    let resp = test::call_service(&app, register_request("al", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let error: ApiError = test::read_body_json(resp).await;
+    assert_eq!(error.code, ErrorCode::InvalidInput);
+    assert_eq!(error.field.as_deref(), Some("username"));
+}
+
+#[actix_web::test]
+async fn register_enforces_password_policy() {
+    let app = init_app!();
+
+    for password in ["", "short", "password123", "alice-rocks", &"x".repeat(73)] {
+        let resp = test::call_service(&app, register_request("alice", password).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{:?} was accepted", password);
+        let error: ApiError = test::read_body_json(resp).await;
+        assert_eq!(error.code, ErrorCode::WeakPassword);
+        assert_eq!(error.field.as_deref(), Some("password"));
+    }
+}
+
+#[actix_web::test]
+async fn register_rejects_reserved_and_odd_usernames() {
+    let app = init_app!();
+
+    for username in ["Admin", "al ice", "al\u{0456}ce", &"a".repeat(33)] {
+        let resp = test::call_service(&app, register_request(username, "correct horse").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{:?} was accepted", username);
+        let error: ApiError = test::read_body_json(resp).await;
+        assert_eq!(error.field.as_deref(), Some("username"));
+    }
+}
+
+#[actix_web::test]
+async fn malformed_body_is_a_json_error() {
+    let app = init_app!();
+
+    let req = test::TestRequest::post()
+        .uri("/register")
+        .insert_header(("Content-Type", "application/json"))
+        .set_payload("{\"username\": ")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let error: ApiError = test::read_body_json(resp).await;
+    assert_eq!(error.code, ErrorCode::InvalidInput);
+}
+
+#[actix_web::test]
+async fn login_sets_access_token_cookie() {
+    let app = init_app!();
+    test::call_service(&app, register_request("alice", "correct horse").to_request()).await;
+
+    let resp = test::call_service(&app, login_request("alice", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let cookie = response_cookie(&resp, "access_token").expect("access_token cookie");
+    assert!(cookie.http_only().unwrap_or(false));
+
+    // Verifiable with nothing but the published public keys
+    let req = test::TestRequest::get().uri("/.well-known/jwks.json").to_request();
+    let jwks: shared::token::JwkSet = test::call_and_read_body_json(&app, req).await;
+    let keys = shared::token::VerificationKeys::from_jwks(&jwks).unwrap();
+
+    let claims = shared::token::validate(cookie.value(), &keys).unwrap();
+    assert!(uuid::Uuid::parse_str(&claims.sub).is_ok());
+    assert_eq!(claims.username, "alice");
+    assert_eq!(claims.roles, vec!["player".to_string()]);
+    assert_eq!(claims.iss, shared::token::ISSUER);
+    assert_eq!(claims.aud, shared::token::AUDIENCE);
+    assert!(!claims.jti.is_empty());
+}
+
+#[actix_web::test]
+async fn login_returns_session_details() {
+    let app = init_app!();
+    test::call_service(&app, register_request("alice", "correct horse").to_request()).await;
+
+    let resp = test::call_service(&app, login_request("alice", "correct horse").to_request()).await;
+    let cookie = response_cookie(&resp, "access_token").unwrap();
+    let body: LoginResponse = test::read_body_json(resp).await;
+    assert_eq!(body.username, "alice");
+    assert_eq!(body.display_name, "alice");
+    assert!(body.expires_at > chrono::Utc::now().timestamp());
+    // Browsers get the token in a cookie only
+    assert_eq!(body.token, None);
+
+    let req = test::TestRequest::post().uri("/login").set_json(LoginData {
+        username: "alice".to_string(),
+        password: "correct horse".to_string(),
+        include_token: true,
+        new_password: None,
+    });
+    let body: LoginResponse = test::call_and_read_body_json(&app, req.to_request()).await;
+    assert!(body.token.is_some_and(|token| token != cookie.value()));
+}
+
+#[actix_web::test]
+async fn login_rejects_wrong_password() {
+    let app = init_app!();
+    test::call_service(&app, register_request("alice", "correct horse").to_request()).await;
+
+    let resp = test::call_service(&app, login_request("alice", "battery staple").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    assert!(resp.response().cookies().next().is_none());
+    let error: ApiError = test::read_body_json(resp).await;
+    assert_eq!(error.code, ErrorCode::InvalidCredentials);
+}
+
+#[actix_web::test]
+async fn login_rejects_unknown_user() {
+    let app = init_app!();
+
+    let resp = test::call_service(&app, login_request("nobody", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn unknown_user_is_indistinguishable_from_wrong_password() {
+    let app = init_app!();
+    test::call_service(&app, register_request("alice", "correct horse").to_request()).await;
+
+    let unknown = test::call_service(&app, login_request("nobody", "correct horse").to_request()).await;
+    let wrong = test::call_service(&app, login_request("alice", "battery staple").to_request()).await;
+    assert_eq!(unknown.status(), wrong.status());
+
+    let unknown: ApiError = test::read_body_json(unknown).await;
+    let wrong: ApiError = test::read_body_json(wrong).await;
+    assert_eq!(unknown, wrong);
+}
+
+#[actix_web::test]
+async fn unknown_user_login_still_verifies_a_hash() {
+    // A single hashing slot that nobody waits for: whenever two logins hash at the same
+    // time, one of them is turned away as busy
+    let config = Config {
+        password_hashing: HashingParams { memory_cost: 8 * 1024, iterations: 2, parallelism: 1 },
+        hashing_concurrency: 1,
+        hashing_queue_timeout: Duration::from_millis(1),
+        ..test_config()
+    };
+    let app = init_app!(Repositories::in_memory(), config);
+
+    let (first, second) = futures::join!(
+        test::call_service(&app, login_request("nobody", "correct horse").to_request()),
+        test::call_service(&app, login_request("somebody", "correct horse").to_request()),
+    );
+
+    let mut statuses = [first.status(), second.status()];
+    statuses.sort();
+    assert_eq!(statuses, [StatusCode::UNAUTHORIZED, StatusCode::SERVICE_UNAVAILABLE]);
+}
+
+#[actix_web::test]
+async fn login_rejects_empty_password() {
+    let app = init_app!();
+
+    let resp = test::call_service(&app, login_request("alice", "").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,175 @@
+#![allow(dead_code)]
+
+use actix_web::cookie::Cookie;
+use actix_web::dev::ServiceResponse;
+use actix_web::test;
+use service::config::{Config, MailTransport, UserStore};
+use service::domain::login_attempt_repository::ThrottlePolicy;
+use service::infrastructure::authentication::HashingParams;
+use service::infrastructure::keys::Keyring;
+use shared::api::auth::{LoginData, RegisterData};
+use shared::policy::RegistrationPolicy;
+use std::time::Duration;
+
+pub const INTERNAL_API_TOKEN: &str = "test-internal-token";
+
+pub fn test_config() -> Config {
+    Config {
+        keys: Keyring::generate("test"),
+        db_url: String::new(),
+        user_store: UserStore::Memory,
+        access_token_ttl: 15 * 60,
+        refresh_token_ttl: 24 * 60 * 60,
+        // Any replay is theft, tests of concurrent refreshes set their own
+        refresh_reuse_grace: 0,
+        account_throttle: ThrottlePolicy {
+            free_attempts: 3,
+            base_delay: 60,
+            lockout_after: 10,
+            lockout_duration: 15 * 60,
+        },
+        ip_throttle: ThrottlePolicy {
+            free_attempts: 10,
+            base_delay: 60,
+            lockout_after: 50,
+            lockout_duration: 15 * 60,
+        },
+        registration_policy: RegistrationPolicy::default(),
+        // Cheap enough to keep the tests fast
+        password_hashing: HashingParams {
+            memory_cost: 1024,
+            iterations: 1,
+            parallelism: 1,
+        },
+        hashing_concurrency: 4,
+        hashing_queue_timeout: Duration::from_secs(5),
+        account_deletion_grace: 7 * 24 * 60 * 60,
+        account_purge_interval: Duration::from_secs(60 * 60),
+        // Nothing listens, pushes would only log failures
+        game_server_urls: Vec::new(),
+        internal_api_token: Some(INTERNAL_API_TOKEN.to_string()),
+        // A directory of its own per test, read back with `sent_mail`
+        mail_transport: MailTransport::File(std::env::temp_dir().join(format!("inner-shelter-mail-{}", uuid::Uuid::new_v4()))),
+        mail_from: "Inner Shelter <noreply@innershelter.org>".to_string(),
+        public_url: "http://innershelter.test".to_string(),
+        email_verification_ttl: 60 * 60,
+        password_reset_ttl: 60 * 60,
+        mfa_challenge_ttl: 5 * 60,
+    }
+}
+
+/// Builds the service's routes on top of fresh in-memory repositories and the test
+/// configuration, or the given ones.
+#[macro_export]
+macro_rules! init_app {
+    () => {
+        init_app!(service::infrastructure::repository::Repositories::in_memory())
+    };
+    ($repositories:expr) => {
+        init_app!($repositories, $crate::common::test_config())
+    };
+    ($repositories:expr, $config:expr) => {{
+        let repositories: service::infrastructure::repository::Repositories = $repositories;
+        let config: service::config::Config = $config;
+        let hashing = service::infrastructure::hashing_pool::HashingPool::from_config(&config).unwrap();
+        let game_servers = service::infrastructure::game_servers::GameServers::from_config(&config);
+        let mailer = service::infrastructure::mailer::create_mailer(&config).unwrap();
+        actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(actix_web::web::Data::new(config))
+                .app_data(actix_web::web::Data::new(repositories.users.clone()))
+                .app_data(actix_web::web::Data::new(repositories.refresh_tokens.clone()))
+                .app_data(actix_web::web::Data::new(repositories.revoked_tokens.clone()))
+                .app_data(actix_web::web::Data::new(repositories.login_attempts.clone()))
+                .app_data(actix_web::web::Data::new(repositories.used_challenges.clone()))
+                .app_data(actix_web::web::Data::new(hashing))
+                .app_data(actix_web::web::Data::new(game_servers))
+                .app_data(actix_web::web::Data::new(mailer))
+                .configure(service::presentation::routes::init_routes),
+        )
+        .await
+    }};
+}
+
+/// Logs the user in, returning the access and refresh token cookies.
+#[macro_export]
+macro_rules! log_in {
+    ($app:expr, $username:expr, $password:expr) => {{
+        let req = $crate::common::login_request($username, $password).to_request();
+        let resp = actix_web::test::call_service($app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+        (
+            $crate::common::response_cookie(&resp, "access_token").unwrap(),
+            $crate::common::response_cookie(&resp, "refresh_token").unwrap(),
+        )
+    }};
+}
+
+/// Registers the user and logs them in.
+#[macro_export]
+macro_rules! session {
+    ($app:expr, $username:expr, $password:expr) => {{
+        let req = $crate::common::register_request($username, $password).to_request();
+        actix_web::test::call_service($app, req).await;
+        log_in!($app, $username, $password)
+    }};
+}
+
+pub fn register_request(username: &str, password: &str) -> test::TestRequest {
+    test::TestRequest::post().uri("/register").set_json(RegisterData {
+        username: username.to_string(),
+        password: password.to_string(),
+        email: None,
+    })
+}
+
+pub fn login_request(username: &str, password: &str) -> test::TestRequest {
+    test::TestRequest::post().uri("/login").set_json(LoginData {
+        username: username.to_string(),
+        password: password.to_string(),
+        include_token: false,
+        new_password: None,
+    })
+}
+
+pub fn refresh_request(refresh_token: &Cookie<'_>) -> test::TestRequest {
+    test::TestRequest::post().uri("/refresh").cookie(refresh_token.clone().into_owned())
+}
+
+pub fn response_cookie(resp: &ServiceResponse, name: &str) -> Option<Cookie<'static>> {
+    resp.response()
+        .cookies()
+        .find(|cookie| cookie.name() == name)
+        .map(|cookie| cookie.into_owned())
+}
+
+/// Every mail sent to `to` so far, oldest first. Mail goes out in the background, so this
+/// waits a little for there to be at least `count`.
+pub async fn sent_mail(config: &Config, to: &str, count: usize) -> Vec<String> {
+    let MailTransport::File(dir) = &config.mail_transport else {
+        panic!("the test config writes mail to files");
+    };
+    let mut mails = Vec::new();
+    for _ in 0..50 {
+        let mut paths: Vec<_> = std::fs::read_dir(dir)
+            .map(|entries| entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect())
+            .unwrap_or_default();
+        paths.sort();
+        mails = paths
+            .iter()
+            .filter_map(|path| std::fs::read_to_string(path).ok())
+            .filter(|mail| mail.starts_with(&format!("To: {}\n", to)))
+            .collect();
+        if mails.len() >= count {
+            break;
+        }
+        actix_web::rt::time::sleep(Duration::from_millis(20)).await;
+    }
+    mails
+}
+
+/// The token a mailed link passes in the given query parameter.
+pub fn link_token(mail: &str, parameter: &str) -> String {
+    let start = mail.find(&format!("{}=", parameter)).expect("a link with a token") + parameter.len() + 1;
+    mail[start..].split_whitespace().next().unwrap().to_string()
+}
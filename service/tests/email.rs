@@ -0,0 +1,222 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test;
+use common::{link_token, login_request, refresh_request, sent_mail, test_config};
+use service::infrastructure::repository::Repositories;
+use shared::api::account::{PasswordResetData, PasswordResetRequestData, Profile, UpdateProfileData, VerifyEmailData};
+use shared::api::auth::RegisterData;
+use shared::api::error::ApiError;
+
+fn register_with_email(username: &str, email: &str) -> test::TestRequest {
+    test::TestRequest::post().uri("/register").set_json(RegisterData {
+        username: username.to_string(),
+        password: "correct horse".to_string(),
+        email: Some(email.to_string()),
+    })
+}
+
+fn verify_request(token: &str) -> test::TestRequest {
+    test::TestRequest::post().uri("/verify-email").set_json(VerifyEmailData { token: token.to_string() })
+}
+
+fn reset_request(username: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/password-reset/request")
+        .set_json(PasswordResetRequestData { username: username.to_string() })
+}
+
+fn confirm_request(token: &str, new_password: &str) -> test::TestRequest {
+    test::TestRequest::post().uri("/password-reset/confirm").set_json(PasswordResetData {
+        token: token.to_string(),
+        new_password: new_password.to_string(),
+    })
+}
+
+#[actix_web::test]
+async fn registration_mails_a_verification_link() {
+    let config = test_config();
+    let app = init_app!(Repositories::in_memory(), config.clone());
+
+    let resp = test::call_service(&app, register_with_email("alice", "alice@example.com").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let mail = sent_mail(&config, "alice@example.com", 1).await.pop().expect("a verification mail");
+    let token = link_token(&mail, "verify_email");
+
+    let (access_token, _) = log_in!(&app, "alice", "correct horse");
+    let req = test::TestRequest::get().uri("/me").cookie(access_token.clone()).to_request();
+    let profile: Profile = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(profile.email.as_deref(), Some("alice@example.com"));
+    assert!(!profile.email_verified);
+
+    let resp = test::call_service(&app, verify_request(&token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let req = test::TestRequest::get().uri("/me").cookie(access_token).to_request();
+    let profile: Profile = test::call_and_read_body_json(&app, req).await;
+    assert!(profile.email_verified);
+
+    // Each link works once
+    let resp = test::call_service(&app, verify_request(&token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let error: ApiError = test::read_body_json(resp).await;
+    assert_eq!(error.field.as_deref(), Some("token"));
+}
+
+#[actix_web::test]
+async fn register_rejects_invalid_emails() {
+    let app = init_app!();
+
+    for email in ["", "alice", "alice@", "@example.com", "alice@example", "al ice@example.com"] {
+        let resp = test::call_service(&app, register_with_email("alice", email).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{:?} was accepted", email);
+        let error: ApiError = test::read_body_json(resp).await;
+        assert_eq!(error.field.as_deref(), Some("email"));
+    }
+}
+
+#[actix_web::test]
+async fn password_reset_goes_to_verified_emails_only() {
+    let config = test_config();
+    let app = init_app!(Repositories::in_memory(), config.clone());
+    test::call_service(&app, register_with_email("alice", "alice@example.com").to_request()).await;
+
+    // Unverified and unknown accounts get the same answer, and no mail
+    for username in ["alice", "nobody"] {
+        let resp = test::call_service(&app, reset_request(username).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    }
+    let mails = sent_mail(&config, "alice@example.com", 2).await;
+    assert_eq!(mails.len(), 1, "only the verification mail");
+    let resp = test::call_service(&app, verify_request(&link_token(&mails[0], "verify_email")).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = test::call_service(&app, reset_request("alice").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let mails = sent_mail(&config, "alice@example.com", 2).await;
+    assert!(mails[1].contains("reset_password="));
+}
+
+#[actix_web::test]
+async fn password_reset_replaces_the_password_once() {
+    let config = test_config();
+    let app = init_app!(Repositories::in_memory(), config.clone());
+    test::call_service(&app, register_with_email("alice", "alice@example.com").to_request()).await;
+    let verification = sent_mail(&config, "alice@example.com", 1).await.pop().unwrap();
+    let verification_token = link_token(&verification, "verify_email");
+    test::call_service(&app, verify_request(&verification_token).to_request()).await;
+    let (_, refresh_token) = log_in!(&app, "alice", "correct horse");
+
+    test::call_service(&app, reset_request("alice").to_request()).await;
+    let mails = sent_mail(&config, "alice@example.com", 2).await;
+    let token = link_token(&mails[1], "reset_password");
+
+    // A verification token is no reset token
+    let resp = test::call_service(&app, confirm_request(&verification_token, "staple battery horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = test::call_service(&app, confirm_request(&token, "password123").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let error: ApiError = test::read_body_json(resp).await;
+    assert_eq!(error.field.as_deref(), Some("new_password"));
+
+    let resp = test::call_service(&app, confirm_request(&token, "staple battery horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = test::call_service(&app, confirm_request(&token, "another staple horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = test::call_service(&app, refresh_request(&refresh_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app, login_request("alice", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app, login_request("alice", "staple battery horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn password_reset_requests_are_throttled() {
+    let app = init_app!();
+
+    // The test policy allows three free attempts per account
+    for _ in 0..4 {
+        let resp = test::call_service(&app, reset_request("alice").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    }
+    let resp = test::call_service(&app, reset_request("alice").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // Logging in is throttled separately
+    test::call_service(&app, common::register_request("alice", "correct horse").to_request()).await;
+    let resp = test::call_service(&app, login_request("alice", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn password_reset_lets_a_locked_out_owner_back_in() {
+    let config = test_config();
+    let repositories = Repositories::in_memory();
+    let app = init_app!(repositories.clone(), config.clone());
+    test::call_service(&app, register_with_email("alice", "alice@example.com").to_request()).await;
+    let verification = sent_mail(&config, "alice@example.com", 1).await.pop().unwrap();
+    test::call_service(&app, verify_request(&link_token(&verification, "verify_email")).to_request()).await;
+
+    // Staff asked for a new password and somebody guessed until the account was throttled
+    let alice = repositories.users.find_user_by_username("alice").await.unwrap().unwrap();
+    repositories.users.require_password_reset(alice.id).await.unwrap();
+    for _ in 0..4 {
+        test::call_service(&app, login_request("alice", "wrong").to_request()).await;
+    }
+    let resp = test::call_service(&app, login_request("alice", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    test::call_service(&app, reset_request("alice").to_request()).await;
+    let mails = sent_mail(&config, "alice@example.com", 2).await;
+    let token = link_token(&mails[1], "reset_password");
+    let resp = test::call_service(&app, confirm_request(&token, "staple battery horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = test::call_service(&app, login_request("alice", "staple battery horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn changing_the_email_takes_the_password_and_a_new_verification() {
+    let config = test_config();
+    let app = init_app!(Repositories::in_memory(), config.clone());
+    test::call_service(&app, register_with_email("alice", "alice@example.com").to_request()).await;
+    let verification = sent_mail(&config, "alice@example.com", 1).await.pop().unwrap();
+    let old_token = link_token(&verification, "verify_email");
+    let (access_token, _) = log_in!(&app, "alice", "correct horse");
+    let update = |data: UpdateProfileData| {
+        test::TestRequest::patch().uri("/me").cookie(access_token.clone()).set_json(data).to_request()
+    };
+
+    for password in [None, Some("wrong")] {
+        let resp = test::call_service(&app, update(UpdateProfileData {
+            email: Some("mallory@example.com".to_string()),
+            password: password.map(str::to_string),
+            ..Default::default()
+        })).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let error: ApiError = test::read_body_json(resp).await;
+        assert_eq!(error.field.as_deref(), Some("password"));
+    }
+
+    let profile: Profile = test::call_and_read_body_json(&app, update(UpdateProfileData {
+        email: Some("alice@example.org".to_string()),
+        password: Some("correct horse".to_string()),
+        ..Default::default()
+    })).await;
+    assert_eq!(profile.email.as_deref(), Some("alice@example.org"));
+    assert!(!profile.email_verified);
+
+    // The link mailed to the old address no longer verifies anything
+    let resp = test::call_service(&app, verify_request(&old_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let mail = sent_mail(&config, "alice@example.org", 1).await.pop().expect("a verification mail");
+    let resp = test::call_service(&app, verify_request(&link_token(&mail, "verify_email")).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let req = test::TestRequest::get().uri("/me").cookie(access_token.clone()).to_request();
+    let profile: Profile = test::call_and_read_body_json(&app, req).await;
+    assert!(profile.email_verified);
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test;
+use common::{login_request, test_config};
+use service::infrastructure::authentication::{hash_password, needs_rehash, verify_password, HashingParams};
+use service::domain::user_repository::User;
+use service::errors::AppError;
+use service::infrastructure::hashing_pool::HashingPool;
+use service::infrastructure::repository::Repositories;
+use std::time::Duration;
+
+#[actix_web::test]
+async fn new_hashes_are_argon2id_with_configured_params() {
+    let params = test_config().password_hashing;
+    let hash = hash_password("correct horse", &params).unwrap();
+
+    assert!(hash.starts_with("$argon2id$"));
+    assert!(verify_password("correct horse", &hash).unwrap());
+    assert!(!verify_password("battery staple", &hash).unwrap());
+    assert!(!needs_rehash(&hash, &params));
+    assert!(needs_rehash(&hash, &HashingParams { iterations: 2, ..params }));
+}
+
+#[actix_web::test]
+async fn bcrypt_hash_is_upgraded_on_login() {
+    let repositories = Repositories::in_memory();
+    let bcrypt_hash = bcrypt::hash("correct horse", 4).unwrap();
+    repositories.users.create_user(&User::new("alice", bcrypt_hash)).await.unwrap();
+
+    let app = init_app!(repositories.clone());
+
+    let resp = test::call_service(&app, login_request("alice", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let stored = repositories.users.find_user_by_username("alice").await.unwrap().unwrap().password_hash;
+    assert!(stored.starts_with("$argon2id$"));
+    assert!(verify_password("correct horse", &stored).unwrap());
+
+    // And the upgraded hash keeps working
+    let resp = test::call_service(&app, login_request("alice", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn hashing_fails_fast_when_the_pool_is_saturated() {
+    let params = HashingParams { memory_cost: 8 * 1024, iterations: 2, parallelism: 1 };
+    let pool = HashingPool::new(params, 1, Duration::from_millis(1)).unwrap();
+
+    // The first hash holds the only slot for longer than the second may wait
+    let (first, second) = futures::join!(pool.hash("correct horse"), pool.hash("battery staple"));
+    assert!(first.is_ok());
+    assert!(matches!(second, Err(AppError::BusyError)));
+
+    // Once it is free again hashing works
+    assert!(pool.hash("battery staple").await.is_ok());
+}
@@ -0,0 +1,30 @@
+use ed25519_dalek::pkcs8::{spki::der::pem::LineEnding, EncodePrivateKey};
+use ed25519_dalek::SigningKey;
+use service::infrastructure::keys::Keyring;
+use shared::token;
+
+fn write_key(dir: &std::path::Path, kid: &str) {
+    let key = SigningKey::generate(&mut rand::rngs::OsRng);
+    let pem = key.to_pkcs8_pem(LineEnding::LF).unwrap();
+    std::fs::write(dir.join(format!("{}.pem", kid)), pem.as_bytes()).unwrap();
+}
+
+#[test]
+fn keyring_signs_with_newest_key_and_publishes_all() {
+    let dir = std::env::temp_dir().join(format!("inner-shelter-keys-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    write_key(&dir, "2026-09");
+    write_key(&dir, "2026-10");
+
+    let keyring = Keyring::load(&dir, None).unwrap();
+    assert_eq!(keyring.signing_key.kid, "2026-10");
+    assert_eq!(keyring.jwks.keys.len(), 2);
+
+    // Tokens signed with the retiring key keep validating until it is removed
+    let old = Keyring::load(&dir, Some("2026-09")).unwrap();
+    let (jwt, _) = token::issue("user-id", "alice", Vec::new(), None, 60, &old.signing_key).unwrap();
+    let claims = token::validate(&jwt, &keyring.verification_keys).unwrap();
+    assert_eq!(claims.username, "alice");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
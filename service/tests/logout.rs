@@ -0,0 +1,102 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test;
+use common::{login_request, refresh_request, register_request, response_cookie};
+use shared::api::auth::RevokedToken;
+
+#[actix_web::test]
+async fn logout_revokes_tokens_and_clears_cookies() {
+    let app = init_app!();
+    test::call_service(&app, register_request("alice", "correct horse").to_request()).await;
+    let resp = test::call_service(&app, login_request("alice", "correct horse").to_request()).await;
+    let access_token = response_cookie(&resp, "access_token").unwrap();
+    let refresh_token = response_cookie(&resp, "refresh_token").unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/logout")
+        .cookie(access_token)
+        .cookie(refresh_token.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(response_cookie(&resp, "access_token").unwrap().value(), "");
+    assert_eq!(response_cookie(&resp, "refresh_token").unwrap().value(), "");
+
+    // The access token and its session show up in the revocation list...
+    let req = test::TestRequest::get().uri("/revocations").to_request();
+    let revoked: Vec<RevokedToken> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(revoked.len(), 2);
+
+    // ...and the refresh token is dead
+    let resp = test::call_service(&app, refresh_request(&refresh_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn logout_without_session_succeeds() {
+    let app = init_app!();
+
+    let resp = test::call_service(&app, test::TestRequest::post().uri("/logout").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get().uri("/revocations").to_request();
+    let revoked: Vec<RevokedToken> = test::call_and_read_body_json(&app, req).await;
+    assert!(revoked.is_empty());
+}
+
+#[actix_web::test]
+async fn logout_revokes_older_tokens_of_the_session() {
+    let app = init_app!();
+    let (first_access_token, refresh_token) = session!(&app, "alice", "correct horse");
+    let resp = test::call_service(&app, refresh_request(&refresh_token).to_request()).await;
+    let access_token = response_cookie(&resp, "access_token").unwrap();
+    let refresh_token = response_cookie(&resp, "refresh_token").unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/logout")
+        .cookie(access_token)
+        .cookie(refresh_token)
+        .to_request();
+    test::call_service(&app, req).await;
+
+    // Still held by, say, a game connection opened before the refresh
+    let req = test::TestRequest::get().uri("/me").cookie(first_access_token).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn logout_needs_the_refresh_tokens_secret() {
+    let app = init_app!();
+    let (access_token, refresh_token) = session!(&app, "alice", "correct horse");
+    let (family_id, _) = refresh_token.value().split_once('.').unwrap();
+
+    // The family id is no secret, a guessed one must not end alice's session
+    let forged = actix_web::cookie::Cookie::new("refresh_token", format!("{family_id}.forged"));
+    let req = test::TestRequest::post().uri("/logout").cookie(forged).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get().uri("/me").cookie(access_token).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let resp = test::call_service(&app, refresh_request(&refresh_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn logout_accepts_bearer_tokens() {
+    let app = init_app!();
+    let (access_token, refresh_token) = session!(&app, "alice", "correct horse");
+
+    let req = test::TestRequest::post()
+        .uri("/logout")
+        .insert_header(("Authorization", format!("Bearer {}", access_token.value())))
+        .to_request();
+    test::call_service(&app, req).await;
+
+    let req = test::TestRequest::get().uri("/me").cookie(access_token).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app, refresh_request(&refresh_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
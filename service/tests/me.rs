@@ -0,0 +1,220 @@
+mod common;
+
+use actix_web::cookie::Cookie;
+use actix_web::http::StatusCode;
+use actix_web::test;
+use common::{login_request, refresh_request, register_request, response_cookie, test_config};
+use service::application::account_purge::purge_deleted_accounts;
+use service::config::Config;
+use service::infrastructure::repository::Repositories;
+use shared::api::account::{AccountDeletion, ChangePasswordData, DeleteAccountData, Profile, UpdateProfileData};
+use shared::api::auth::{LoginData, LoginResponse};
+use shared::api::error::{ApiError, ErrorCode};
+
+fn change_password_request(access_token: &Cookie<'_>, current: &str, new: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/me/password")
+        .cookie(access_token.clone().into_owned())
+        .set_json(ChangePasswordData { current_password: current.to_string(), new_password: new.to_string() })
+}
+
+fn delete_request(access_token: &Cookie<'_>, password: &str) -> test::TestRequest {
+    test::TestRequest::delete()
+        .uri("/me")
+        .cookie(access_token.clone().into_owned())
+        .set_json(DeleteAccountData { password: password.to_string() })
+}
+
+#[actix_web::test]
+async fn profile_requires_a_session() {
+    let app = init_app!();
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/me").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::get()
+        .uri("/me")
+        .insert_header(("Authorization", "Bearer not-a-token"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn profile_returns_the_callers_account() {
+    let app = init_app!();
+    let (access_token, _) = session!(&app, "alice", "correct horse");
+
+    let req = test::TestRequest::get().uri("/me").cookie(access_token).to_request();
+    let profile: Profile = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(profile.username, "alice");
+    assert_eq!(profile.display_name, "alice");
+    assert_eq!(profile.roles, vec!["player".to_string()]);
+    assert!(profile.last_login_at.is_some());
+
+    // Clients without cookies send the token they asked for at login
+    let req = test::TestRequest::post().uri("/login").set_json(LoginData {
+        username: "alice".to_string(),
+        password: "correct horse".to_string(),
+        include_token: true,
+        new_password: None,
+    });
+    let login: LoginResponse = test::call_and_read_body_json(&app, req.to_request()).await;
+    let req = test::TestRequest::get()
+        .uri("/me")
+        .insert_header(("Authorization", format!("Bearer {}", login.token.unwrap())))
+        .to_request();
+    let by_bearer: Profile = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(by_bearer.id, profile.id);
+}
+
+#[actix_web::test]
+async fn logged_out_token_is_refused() {
+    let app = init_app!();
+    let (access_token, _) = session!(&app, "alice", "correct horse");
+
+    let req = test::TestRequest::post().uri("/logout").cookie(access_token.clone()).to_request();
+    test::call_service(&app, req).await;
+
+    let req = test::TestRequest::get().uri("/me").cookie(access_token).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn update_display_name() {
+    let app = init_app!();
+    let (access_token, _) = session!(&app, "alice", "correct horse");
+
+    let req = test::TestRequest::patch()
+        .uri("/me")
+        .cookie(access_token.clone())
+        .set_json(UpdateProfileData { display_name: Some("  Alice in Wonderland ".to_string()), ..Default::default() });
+    let profile: Profile = test::call_and_read_body_json(&app, req.to_request()).await;
+    assert_eq!(profile.display_name, "Alice in Wonderland");
+
+    for display_name in ["   ", "a\nb", &"a".repeat(33)] {
+        let req = test::TestRequest::patch()
+            .uri("/me")
+            .cookie(access_token.clone())
+            .set_json(UpdateProfileData { display_name: Some(display_name.to_string()), ..Default::default() });
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{:?} was accepted", display_name);
+        let error: ApiError = test::read_body_json(resp).await;
+        assert_eq!(error.field.as_deref(), Some("display_name"));
+    }
+
+    let req = test::TestRequest::get().uri("/me").cookie(access_token).to_request();
+    let profile: Profile = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(profile.display_name, "Alice in Wonderland");
+}
+
+#[actix_web::test]
+async fn change_password_checks_the_current_one() {
+    let app = init_app!();
+    let (access_token, _) = session!(&app, "alice", "correct horse");
+
+    let req = change_password_request(&access_token, "battery staple", "staple battery horse");
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let error: ApiError = test::read_body_json(resp).await;
+    assert_eq!(error.code, ErrorCode::InvalidCredentials);
+    assert_eq!(error.field.as_deref(), Some("current_password"));
+
+    let req = change_password_request(&access_token, "correct horse", "password123");
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let error: ApiError = test::read_body_json(resp).await;
+    assert_eq!(error.code, ErrorCode::WeakPassword);
+    assert_eq!(error.field.as_deref(), Some("new_password"));
+
+    // Nothing changed
+    let resp = test::call_service(&app, login_request("alice", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn change_password_ends_other_sessions() {
+    let app = init_app!();
+    let (access_token, refresh_token) = session!(&app, "alice", "correct horse");
+    let (_, other_refresh_token) = log_in!(&app, "alice", "correct horse");
+
+    let req = change_password_request(&access_token, "correct horse", "staple battery horse")
+        .cookie(refresh_token.clone());
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = test::call_service(&app, refresh_request(&other_refresh_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app, refresh_request(&refresh_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = test::call_service(&app, login_request("alice", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app, login_request("alice", "staple battery horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn delete_account_logs_out_everywhere() {
+    let app = init_app!();
+    let (access_token, refresh_token) = session!(&app, "alice", "correct horse");
+
+    let resp = test::call_service(&app, delete_request(&access_token, "battery staple").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = test::call_service(&app, delete_request(&access_token, "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(response_cookie(&resp, "access_token").unwrap().value(), "");
+    let deletion: AccountDeletion = test::read_body_json(resp).await;
+    assert!(deletion.deleted_at > chrono::Utc::now().timestamp());
+
+    let req = test::TestRequest::get().uri("/me").cookie(access_token).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app, refresh_request(&refresh_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn logging_in_during_the_grace_period_keeps_the_account() {
+    let app = init_app!();
+    let (access_token, _) = session!(&app, "alice", "correct horse");
+    test::call_service(&app, delete_request(&access_token, "correct horse").to_request()).await;
+
+    let (access_token, _) = log_in!(&app, "alice", "correct horse");
+    let req = test::TestRequest::get().uri("/me").cookie(access_token).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn deleted_account_cannot_log_in() {
+    let config = Config { account_deletion_grace: 0, ..test_config() };
+    let app = init_app!(Repositories::in_memory(), config);
+    let (access_token, _) = session!(&app, "alice", "correct horse");
+    test::call_service(&app, delete_request(&access_token, "correct horse").to_request()).await;
+
+    let resp = test::call_service(&app, login_request("alice", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let error: ApiError = test::read_body_json(resp).await;
+    assert_eq!(error.code, ErrorCode::InvalidCredentials);
+}
+
+#[actix_web::test]
+async fn purged_accounts_free_their_username() {
+    let repositories = Repositories::in_memory();
+    let config = Config { account_deletion_grace: 0, ..test_config() };
+    let app = init_app!(repositories.clone(), config);
+    let (access_token, _) = session!(&app, "alice", "correct horse");
+    session!(&app, "bob", "battery staple");
+    test::call_service(&app, delete_request(&access_token, "correct horse").to_request()).await;
+
+    let now = chrono::Utc::now().timestamp();
+    assert_eq!(purge_deleted_accounts(repositories.users.as_ref(), now).await.unwrap(), 1);
+    assert!(repositories.users.find_user_by_username("bob").await.unwrap().is_some());
+
+    let resp = test::call_service(&app, register_request("alice", "another horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    log_in!(&app, "alice", "another horse");
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test;
+use common::{login_request, refresh_request, register_request, response_cookie, test_config};
+use service::config::Config;
+use service::infrastructure::repository::Repositories;
+use shared::api::auth::RevokedToken;
+
+#[actix_web::test]
+async fn login_sets_refresh_token_cookie() {
+    let app = init_app!();
+    test::call_service(&app, register_request("alice", "correct horse").to_request()).await;
+
+    let resp = test::call_service(&app, login_request("alice", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let cookie = response_cookie(&resp, "refresh_token").expect("refresh_token cookie");
+    assert!(cookie.http_only().unwrap_or(false));
+}
+
+#[actix_web::test]
+async fn refresh_rotates_tokens() {
+    let app = init_app!();
+    test::call_service(&app, register_request("alice", "correct horse").to_request()).await;
+    let resp = test::call_service(&app, login_request("alice", "correct horse").to_request()).await;
+    let first = response_cookie(&resp, "refresh_token").unwrap();
+
+    let resp = test::call_service(&app, refresh_request(&first).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(response_cookie(&resp, "access_token").is_some());
+    let second = response_cookie(&resp, "refresh_token").unwrap();
+    assert_ne!(first.value(), second.value());
+
+    let resp = test::call_service(&app, refresh_request(&second).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn refresh_token_reuse_revokes_family() {
+    let app = init_app!();
+    test::call_service(&app, register_request("alice", "correct horse").to_request()).await;
+    let resp = test::call_service(&app, login_request("alice", "correct horse").to_request()).await;
+    let first = response_cookie(&resp, "refresh_token").unwrap();
+
+    let resp = test::call_service(&app, refresh_request(&first).to_request()).await;
+    let second = response_cookie(&resp, "refresh_token").unwrap();
+
+    // Replaying the exchanged token is rejected...
+    let resp = test::call_service(&app, refresh_request(&first).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // ...and takes the legitimate successor down with it
+    let resp = test::call_service(&app, refresh_request(&second).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn refresh_token_reuse_revokes_the_sessions_access_tokens() {
+    let app = init_app!();
+    let (access_token, first) = session!(&app, "alice", "correct horse");
+
+    // The thief refreshes first and gets an access token of their own
+    let resp = test::call_service(&app, refresh_request(&first).to_request()).await;
+    let stolen_access_token = response_cookie(&resp, "access_token").unwrap();
+    let resp = test::call_service(&app, refresh_request(&first).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    for token in [access_token, stolen_access_token] {
+        let req = test::TestRequest::get().uri("/me").cookie(token).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    // Game servers learn about it from the revocation list
+    let revoked: Vec<RevokedToken> =
+        test::call_and_read_body_json(&app, test::TestRequest::get().uri("/revocations").to_request()).await;
+    let family_id = first.value().split_once('.').unwrap().0;
+    assert!(revoked.iter().any(|token| token.jti == family_id));
+}
+
+#[actix_web::test]
+async fn reuse_in_one_family_leaves_other_logins_alone() {
+    let app = init_app!();
+    test::call_service(&app, register_request("alice", "correct horse").to_request()).await;
+    let resp = test::call_service(&app, login_request("alice", "correct horse").to_request()).await;
+    let stolen = response_cookie(&resp, "refresh_token").unwrap();
+    let resp = test::call_service(&app, login_request("alice", "correct horse").to_request()).await;
+    let other_device = response_cookie(&resp, "refresh_token").unwrap();
+
+    test::call_service(&app, refresh_request(&stolen).to_request()).await;
+    test::call_service(&app, refresh_request(&stolen).to_request()).await;
+
+    let resp = test::call_service(&app, refresh_request(&other_device).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn refresh_requires_cookie() {
+    let app = init_app!();
+
+    let resp = test::call_service(&app, test::TestRequest::post().uri("/refresh").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn tabs_refreshing_at_once_keep_the_session() {
+    let config = Config { refresh_reuse_grace: 10, ..test_config() };
+    let app = init_app!(Repositories::in_memory(), config);
+    let (_, refresh_token) = session!(&app, "alice", "correct horse");
+
+    let resp = test::call_service(&app, refresh_request(&refresh_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let rotated = response_cookie(&resp, "refresh_token").unwrap();
+
+    // The other tab sent the same token before the new one reached the browser
+    let resp = test::call_service(&app, refresh_request(&refresh_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(response_cookie(&resp, "access_token").is_some());
+    assert!(response_cookie(&resp, "refresh_token").is_none());
+
+    let resp = test::call_service(&app, refresh_request(&rotated).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // Two rotations on, the first token is no longer a race but a replay
+    let resp = test::call_service(&app, refresh_request(&refresh_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app, refresh_request(&rotated).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use actix_web::cookie::Cookie;
+use actix_web::http::{header, StatusCode};
+use actix_web::test;
+use common::{login_request, refresh_request, response_cookie};
+use shared::api::account::{ChangePasswordData, SessionInfo};
+use shared::api::auth::RevokedToken;
+
+fn sessions_request(access_token: &Cookie<'_>) -> test::TestRequest {
+    test::TestRequest::get().uri("/me/sessions").cookie(access_token.clone().into_owned())
+}
+
+fn revoke_request(access_token: &Cookie<'_>, id: &str) -> test::TestRequest {
+    test::TestRequest::delete()
+        .uri(&format!("/me/sessions/{}", id))
+        .cookie(access_token.clone().into_owned())
+}
+
+#[actix_web::test]
+async fn logins_are_listed_with_their_device() {
+    let app = init_app!();
+    let (access_token, _) = session!(&app, "alice", "correct horse");
+
+    let req = login_request("alice", "correct horse")
+        .insert_header((header::USER_AGENT, "Firefox on the laptop"))
+        .peer_addr("203.0.113.7:5000".parse().unwrap())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let sessions: Vec<SessionInfo> = test::call_and_read_body_json(&app, sessions_request(&access_token).to_request()).await;
+    assert_eq!(sessions.len(), 2);
+    let current: Vec<_> = sessions.iter().filter(|session| session.current).collect();
+    assert_eq!(current.len(), 1);
+    assert_eq!(current[0].user_agent, None);
+
+    let laptop = sessions.iter().find(|session| !session.current).unwrap();
+    assert_eq!(laptop.user_agent.as_deref(), Some("Firefox on the laptop"));
+    assert_eq!(laptop.ip.as_deref(), Some("203.0.113.7"));
+    assert!(laptop.created_at > 0 && laptop.last_seen_at >= laptop.created_at);
+}
+
+#[actix_web::test]
+async fn revoking_a_session_logs_it_out() {
+    let app = init_app!();
+    let (access_token, _) = session!(&app, "alice", "correct horse");
+    let (other_access_token, other_refresh_token) = log_in!(&app, "alice", "correct horse");
+
+    let sessions: Vec<SessionInfo> = test::call_and_read_body_json(&app, sessions_request(&access_token).to_request()).await;
+    let other = sessions.iter().find(|session| !session.current).unwrap();
+    let resp = test::call_service(&app, revoke_request(&access_token, &other.id).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(response_cookie(&resp, "access_token").is_none());
+
+    // Neither its refresh token nor the access token it still holds work any more
+    let resp = test::call_service(&app, refresh_request(&other_refresh_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let req = test::TestRequest::get().uri("/me").cookie(other_access_token).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // The game servers learn about it from the revocation list too
+    let revoked: Vec<RevokedToken> =
+        test::call_and_read_body_json(&app, test::TestRequest::get().uri("/revocations").to_request()).await;
+    assert!(revoked.iter().any(|token| token.jti == other.id));
+
+    // The caller's own session carries on
+    let sessions: Vec<SessionInfo> = test::call_and_read_body_json(&app, sessions_request(&access_token).to_request()).await;
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0].current);
+}
+
+#[actix_web::test]
+async fn revoking_the_current_session_clears_cookies() {
+    let app = init_app!();
+    let (access_token, refresh_token) = session!(&app, "alice", "correct horse");
+
+    let sessions: Vec<SessionInfo> = test::call_and_read_body_json(&app, sessions_request(&access_token).to_request()).await;
+    let resp = test::call_service(&app, revoke_request(&access_token, &sessions[0].id).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(response_cookie(&resp, "access_token").unwrap().value(), "");
+
+    let resp = test::call_service(&app, refresh_request(&refresh_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn other_users_sessions_cannot_be_revoked() {
+    let app = init_app!();
+    let (alice_token, _) = session!(&app, "alice", "correct horse");
+    let (bob_token, bob_refresh_token) = session!(&app, "bob", "battery staple");
+
+    let sessions: Vec<SessionInfo> = test::call_and_read_body_json(&app, sessions_request(&bob_token).to_request()).await;
+    let resp = test::call_service(&app, revoke_request(&alice_token, &sessions[0].id).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let resp = test::call_service(&app, revoke_request(&alice_token, "no-such-session").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let resp = test::call_service(&app, refresh_request(&bob_refresh_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn changing_the_password_revokes_other_access_tokens() {
+    let app = init_app!();
+    let (access_token, _) = session!(&app, "alice", "correct horse");
+    let (other_access_token, _) = log_in!(&app, "alice", "correct horse");
+
+    let req = test::TestRequest::post()
+        .uri("/me/password")
+        .cookie(access_token.clone())
+        .set_json(ChangePasswordData {
+            current_password: "correct horse".to_string(),
+            new_password: "staple battery horse".to_string(),
+        });
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get().uri("/me").cookie(other_access_token).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let req = test::TestRequest::get().uri("/me").cookie(access_token).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
@@ -0,0 +1,263 @@
+#![cfg(feature = "sqlite")]
+
+use service::domain::login_attempt_repository::{LoginAttemptRepository, ThrottlePolicy};
+use service::domain::refresh_token_repository::{RefreshTokenFamily, RefreshTokenRepository};
+use service::domain::used_challenge_repository::UsedChallengeRepository;
+use service::domain::user_repository::{Totp, User, UserRepository};
+use service::errors::AppError;
+use service::infrastructure::repository::sqlite_login_attempt_repository::SqliteLoginAttemptRepository;
+use service::infrastructure::repository::sqlite_refresh_token_repository::SqliteRefreshTokenRepository;
+use service::infrastructure::repository::sqlite_used_challenge_repository::SqliteUsedChallengeRepository;
+use service::infrastructure::repository::sqlite_user_repository::SqliteUserRepository;
+use service::infrastructure::sqlite::open_sqlite;
+use shared::api::admin::Ban;
+use std::sync::Arc;
+
+#[actix_web::test]
+async fn stores_and_finds_users() {
+    let repo = SqliteUserRepository::new(open_sqlite("sqlite::memory:").unwrap());
+
+    assert_eq!(repo.find_user_by_username("alice").await.unwrap(), None);
+
+    let alice = User { email: Some("alice@example.com".to_string()), ..User::new("alice", "hash".to_string()) };
+    repo.create_user(&alice).await.unwrap();
+    assert_eq!(repo.find_user_by_username("alice").await.unwrap(), Some(alice.clone()));
+    assert_eq!(repo.find_user_by_id(alice.id).await.unwrap(), Some(alice.clone()));
+
+    repo.update_password(alice.id, "new hash".to_string()).await.unwrap();
+    repo.record_login(alice.id, 1234).await.unwrap();
+    let found = repo.find_user_by_id(alice.id).await.unwrap().unwrap();
+    assert_eq!(found.password_hash, "new hash");
+    assert_eq!(found.last_login_at, Some(1234));
+    assert_eq!(found.roles, vec!["player".to_string()]);
+
+    repo.update_display_name(alice.id, "Alice".to_string()).await.unwrap();
+    repo.schedule_deletion(alice.id, Some(5678)).await.unwrap();
+    let found = repo.find_user_by_id(alice.id).await.unwrap().unwrap();
+    assert_eq!(found.display_name, "Alice");
+    assert_eq!(found.deletion_scheduled_at, Some(5678));
+
+    repo.set_roles(alice.id, vec!["player".to_string(), "admin".to_string()]).await.unwrap();
+    let ban = Ban { reason: "cheating".to_string(), banned_at: 1000, until: Some(2000) };
+    repo.set_ban(alice.id, Some(ban.clone())).await.unwrap();
+    repo.require_password_reset(alice.id).await.unwrap();
+    repo.mark_email_verified(alice.id).await.unwrap();
+    let found = repo.find_user_by_id(alice.id).await.unwrap().unwrap();
+    assert_eq!(found.roles, vec!["player".to_string(), "admin".to_string()]);
+    assert_eq!(found.ban, Some(ban.clone()));
+    assert!(found.password_reset_required);
+    assert!(found.email_verified);
+    assert_eq!(repo.list_active_bans(1500).await.unwrap(), vec![(alice.id, ban)]);
+    assert!(repo.list_active_bans(2500).await.unwrap().is_empty());
+
+    // A new password satisfies the reset
+    repo.update_password(alice.id, "newer hash".to_string()).await.unwrap();
+    assert!(!repo.find_user_by_id(alice.id).await.unwrap().unwrap().password_reset_required);
+}
+
+#[actix_web::test]
+async fn recovery_codes_are_used_once() {
+    let repo = SqliteUserRepository::new(open_sqlite("sqlite::memory:").unwrap());
+    let alice = User::new("alice", "hash".to_string());
+    repo.create_user(&alice).await.unwrap();
+
+    let totp = Totp {
+        secret: "JBSWY3DPEHPK3PXP".to_string(),
+        enabled: true,
+        recovery_codes: vec!["first".to_string(), "second".to_string()],
+        last_used_step: None,
+    };
+    repo.set_totp(alice.id, Some(totp.clone())).await.unwrap();
+    assert_eq!(repo.find_user_by_id(alice.id).await.unwrap().unwrap().totp, Some(totp));
+
+    assert!(repo.use_recovery_code(alice.id, "first").await.unwrap());
+    assert!(!repo.use_recovery_code(alice.id, "first").await.unwrap());
+    assert!(!repo.use_recovery_code(alice.id, "third").await.unwrap());
+    let found = repo.find_user_by_id(alice.id).await.unwrap().unwrap();
+    assert_eq!(found.totp.unwrap().recovery_codes, vec!["second".to_string()]);
+
+    assert!(repo.use_totp_step(alice.id, 10).await.unwrap());
+    assert!(!repo.use_totp_step(alice.id, 10).await.unwrap());
+    assert!(!repo.use_totp_step(alice.id, 9).await.unwrap());
+    assert!(repo.use_totp_step(alice.id, 11).await.unwrap());
+
+    repo.set_totp(alice.id, None).await.unwrap();
+    assert_eq!(repo.find_user_by_id(alice.id).await.unwrap().unwrap().totp, None);
+}
+
+#[actix_web::test]
+async fn lists_users_in_pages() {
+    let repo = SqliteUserRepository::new(open_sqlite("sqlite::memory:").unwrap());
+    for username in ["carol", "alice", "bob"] {
+        repo.create_user(&User::new(username, "hash".to_string())).await.unwrap();
+    }
+
+    let first: Vec<String> = repo.list_users(None, 2).await.unwrap().into_iter().map(|user| user.username).collect();
+    assert_eq!(first, ["alice", "bob"]);
+    let rest: Vec<String> = repo.list_users(Some("bob"), 2).await.unwrap().into_iter().map(|user| user.username).collect();
+    assert_eq!(rest, ["carol"]);
+}
+
+#[actix_web::test]
+async fn create_user_rejects_taken_username() {
+    let repo = SqliteUserRepository::new(open_sqlite("sqlite::memory:").unwrap());
+
+    repo.create_user(&User::new("alice", "hash".to_string())).await.unwrap();
+    let result = repo.create_user(&User::new("alice", "other".to_string())).await;
+
+    assert!(matches!(result, Err(AppError::ConflictError(_))));
+    let found = repo.find_user_by_username("alice").await.unwrap().unwrap();
+    assert_eq!(found.password_hash, "hash");
+}
+
+#[actix_web::test]
+async fn users_survive_reopening_the_database() {
+    let path = std::env::temp_dir().join(format!("inner_shelter_test_{}.db", std::process::id()));
+    let url = format!("sqlite://{}", path.display());
+
+    {
+        let repo = SqliteUserRepository::new(open_sqlite(&url).unwrap());
+        repo.create_user(&User::new("alice", "hash".to_string())).await.unwrap();
+    }
+
+    let repo = SqliteUserRepository::new(open_sqlite(&url).unwrap());
+    let found = repo.find_user_by_username("alice").await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(found.map(|user| user.password_hash), Some("hash".to_string()));
+}
+
+#[actix_web::test]
+async fn users_from_before_ids_get_one() {
+    let path = std::env::temp_dir().join(format!("inner_shelter_legacy_{}.db", std::process::id()));
+    {
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        connection.execute_batch("
+            CREATE TABLE users (username TEXT PRIMARY KEY, password TEXT NOT NULL);
+            INSERT INTO users VALUES ('alice', 'hash'), ('bob', 'other');
+        ").unwrap();
+    }
+
+    let repo = SqliteUserRepository::new(open_sqlite(&format!("sqlite://{}", path.display())).unwrap());
+    let alice = repo.find_user_by_username("alice").await.unwrap().unwrap();
+    let bob = repo.find_user_by_username("bob").await.unwrap().unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(alice.password_hash, "hash");
+    assert_eq!(alice.display_name, "alice");
+    assert_eq!(alice.id.get_version_num(), 4);
+    assert_ne!(alice.id, bob.id);
+}
+
+#[actix_web::test]
+async fn rotate_only_replaces_the_current_token() {
+    let repo = SqliteRefreshTokenRepository::new(open_sqlite("sqlite::memory:").unwrap());
+    repo.create_family(&RefreshTokenFamily {
+        family_id: "family".to_string(),
+        user_id: uuid::Uuid::new_v4(),
+        current_token_hash: "first".to_string(),
+        previous_token_hash: None,
+        revoked: false,
+        expires_at: 0,
+        created_at: 0,
+        last_seen_at: 0,
+        user_agent: Some("Firefox".to_string()),
+        ip: Some("203.0.113.7".to_string()),
+    }).await.unwrap();
+
+    assert!(repo.rotate("family", "first", "second", 1, 5).await.unwrap());
+    assert!(!repo.rotate("family", "first", "third", 1, 6).await.unwrap());
+
+    repo.revoke_family("family").await.unwrap();
+    assert!(!repo.rotate("family", "second", "third", 1, 7).await.unwrap());
+
+    let family = repo.find_family("family").await.unwrap().unwrap();
+    assert_eq!(family.current_token_hash, "second");
+    assert_eq!(family.previous_token_hash.as_deref(), Some("first"));
+    assert_eq!(family.last_seen_at, 5);
+    assert_eq!(family.user_agent.as_deref(), Some("Firefox"));
+    assert_eq!(family.ip.as_deref(), Some("203.0.113.7"));
+    assert!(family.revoked);
+}
+
+#[actix_web::test]
+async fn login_attempts_expire_and_clear() {
+    let repo = SqliteLoginAttemptRepository::new(open_sqlite("sqlite::memory:").unwrap());
+    let policy = ThrottlePolicy { free_attempts: 1, base_delay: 2, lockout_after: 10, lockout_duration: 60 };
+    let now = chrono::Utc::now().timestamp();
+
+    repo.record_failure("user:alice", &policy).await.unwrap();
+    let attempts = repo.record_failure("user:alice", &policy).await.unwrap();
+    assert_eq!(attempts.failures, 2);
+    assert!(attempts.blocked_until >= now + 2 && attempts.expires_at >= now + 60);
+    assert_eq!(repo.find("user:alice").await.unwrap(), Some(attempts));
+
+    // Failures outlive their block by the lockout's length at most
+    let forgetful = ThrottlePolicy { lockout_duration: 0, ..policy };
+    repo.record_failure("user:bob", &forgetful).await.unwrap();
+    assert_eq!(repo.find("user:bob").await.unwrap(), None);
+
+    repo.clear("user:alice").await.unwrap();
+    assert_eq!(repo.find("user:alice").await.unwrap(), None);
+}
+
+#[actix_web::test]
+async fn parallel_login_failures_all_count() {
+    let repo = Arc::new(SqliteLoginAttemptRepository::new(open_sqlite("sqlite::memory:").unwrap()));
+    let policy = ThrottlePolicy { free_attempts: 3, base_delay: 1, lockout_after: 100, lockout_duration: 60 };
+
+    let failures = (0..20).map(|_| {
+        let repo = repo.clone();
+        tokio::spawn(async move { repo.record_failure("ip:203.0.113.7", &policy).await.unwrap() })
+    });
+    for failure in failures {
+        failure.await.unwrap();
+    }
+
+    assert_eq!(repo.find("ip:203.0.113.7").await.unwrap().unwrap().failures, 20);
+}
+
+#[actix_web::test]
+async fn challenges_are_used_once() {
+    let repo = SqliteUsedChallengeRepository::new(open_sqlite("sqlite::memory:").unwrap());
+    let expires_at = chrono::Utc::now().timestamp() + 60;
+
+    assert!(repo.use_challenge("challenge", expires_at).await.unwrap());
+    assert!(!repo.use_challenge("challenge", expires_at).await.unwrap());
+
+    repo.release_challenge("challenge").await.unwrap();
+    assert!(repo.use_challenge("challenge", expires_at).await.unwrap());
+}
+
+#[actix_web::test]
+async fn revoke_user_families_keeps_the_given_one() {
+    let repo = SqliteRefreshTokenRepository::new(open_sqlite("sqlite::memory:").unwrap());
+    let alice = uuid::Uuid::new_v4();
+    for (family_id, user_id) in [("current", alice), ("other", alice), ("bobs", uuid::Uuid::new_v4())] {
+        repo.create_family(&RefreshTokenFamily {
+            family_id: family_id.to_string(),
+            user_id,
+            current_token_hash: "hash".to_string(),
+            previous_token_hash: None,
+            revoked: false,
+            expires_at: 0,
+            created_at: 0,
+            last_seen_at: 0,
+            user_agent: None,
+            ip: None,
+        }).await.unwrap();
+    }
+
+    let mut family_ids: Vec<_> = repo.list_user_families(alice).await.unwrap()
+        .into_iter()
+        .map(|family| family.family_id)
+        .collect();
+    family_ids.sort();
+    assert_eq!(family_ids, ["current", "other"]);
+
+    repo.revoke_user_families(alice, Some("current")).await.unwrap();
+
+    assert!(!repo.find_family("current").await.unwrap().unwrap().revoked);
+    assert!(repo.find_family("other").await.unwrap().unwrap().revoked);
+    assert!(!repo.find_family("bobs").await.unwrap().unwrap().revoked);
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use actix_web::http::{header, StatusCode};
+use actix_web::test;
+use common::{login_request, register_request};
+use service::domain::login_attempt_repository::{LoginAttempts, ThrottlePolicy};
+use shared::api::error::{ApiError, ErrorCode};
+
+const ATTACKER: &str = "203.0.113.7:40000";
+
+#[actix_web::test]
+async fn backoff_doubles_then_locks_out() {
+    let policy = ThrottlePolicy { free_attempts: 2, base_delay: 1, lockout_after: 6, lockout_duration: 600 };
+    let mut attempts = LoginAttempts::default();
+    let mut delays = Vec::new();
+    for _ in 0..6 {
+        attempts = policy.record_failure(&attempts, 0);
+        delays.push(attempts.blocked_until);
+    }
+    assert_eq!(delays, vec![0, 0, 1, 2, 4, 600]);
+}
+
+#[actix_web::test]
+async fn repeated_failures_are_rate_limited() {
+    let app = init_app!();
+    test::call_service(&app, register_request("alice", "correct horse").to_request()).await;
+
+    for _ in 0..4 {
+        let req = login_request("alice", "wrong").peer_addr(ATTACKER.parse().unwrap()).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    // Even the right password waits out the backoff
+    let req = login_request("alice", "correct horse").peer_addr(ATTACKER.parse().unwrap()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: i64 = resp.headers().get(header::RETRY_AFTER).unwrap().to_str().unwrap().parse().unwrap();
+    assert!(retry_after > 0 && retry_after <= 60);
+    let error: ApiError = test::read_body_json(resp).await;
+    assert_eq!(error.code, ErrorCode::RateLimited);
+
+    // The account is throttled from other addresses too
+    let req = login_request("alice", "correct horse").peer_addr("198.51.100.1:50000".parse().unwrap()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[actix_web::test]
+async fn address_is_throttled_across_usernames() {
+    let app = init_app!();
+    test::call_service(&app, register_request("alice", "correct horse").to_request()).await;
+
+    for i in 0..11 {
+        let req = login_request(&format!("guess{}", i), "wrong").peer_addr(ATTACKER.parse().unwrap()).to_request();
+        test::call_service(&app, req).await;
+    }
+
+    let req = login_request("alice", "correct horse").peer_addr(ATTACKER.parse().unwrap()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[actix_web::test]
+async fn successful_login_resets_account_failures() {
+    let app = init_app!();
+    test::call_service(&app, register_request("alice", "correct horse").to_request()).await;
+
+    for _ in 0..3 {
+        test::call_service(&app, login_request("alice", "wrong").to_request()).await;
+    }
+    let resp = test::call_service(&app, login_request("alice", "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // Three more free attempts, not a backoff
+    for _ in 0..3 {
+        let resp = test::call_service(&app, login_request("alice", "wrong").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,235 @@
+mod common;
+
+use actix_web::cookie::Cookie;
+use actix_web::http::StatusCode;
+use actix_web::test;
+use common::{login_request, response_cookie};
+use shared::api::account::{ChangePasswordData, ConfirmTotpData, Profile, TotpEnrolment, TotpPasswordData};
+use shared::api::auth::{MfaChallenge, MfaLoginData, RevokedToken};
+use shared::api::error::{ApiError, ErrorCode};
+use totp_rs::TOTP;
+
+fn enrol_request(access_token: &Cookie<'_>, password: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/me/totp")
+        .cookie(access_token.clone().into_owned())
+        .set_json(TotpPasswordData { password: password.to_string() })
+}
+
+fn confirm_request(access_token: &Cookie<'_>, code: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/me/totp/confirm")
+        .cookie(access_token.clone().into_owned())
+        .set_json(ConfirmTotpData { code: code.to_string() })
+}
+
+fn mfa_request(challenge: &MfaChallenge, code: &str) -> test::TestRequest {
+    test::TestRequest::post().uri("/login/mfa").set_json(MfaLoginData {
+        mfa_token: challenge.token.clone(),
+        code: code.to_string(),
+        include_token: false,
+        new_password: None,
+    })
+}
+
+fn current_code(enrolment: &TotpEnrolment) -> String {
+    TOTP::from_url(&enrolment.otpauth_uri).unwrap().generate_current().unwrap()
+}
+
+/// The code after the current one, still accepted for clock drift. Confirming the
+/// enrolment spends the current one.
+fn next_code(enrolment: &TotpEnrolment) -> String {
+    let totp = TOTP::from_url(&enrolment.otpauth_uri).unwrap();
+    totp.generate(totp.next_step_current().unwrap())
+}
+
+/// Enrols and confirms, so the next login asks for a code.
+macro_rules! enable_totp {
+    ($app:expr, $access_token:expr, $password:expr) => {{
+        let enrolment: TotpEnrolment = test::call_and_read_body_json($app, enrol_request($access_token, $password).to_request()).await;
+        let resp = test::call_service($app, confirm_request($access_token, &current_code(&enrolment)).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        enrolment
+    }};
+}
+
+/// Logs in with the password, expecting to be asked for the second factor.
+macro_rules! challenge {
+    ($app:expr, $username:expr, $password:expr) => {{
+        let resp = test::call_service($app, login_request($username, $password).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert!(response_cookie(&resp, "access_token").is_none());
+        let error: ApiError = test::read_body_json(resp).await;
+        assert_eq!(error.code, ErrorCode::MfaRequired);
+        *error.mfa_challenge.unwrap()
+    }};
+}
+
+#[actix_web::test]
+async fn enrolment_takes_effect_once_confirmed() {
+    let app = init_app!();
+    let (access_token, _) = session!(&app, "alice", "correct horse");
+
+    let resp = test::call_service(&app, enrol_request(&access_token, "battery staple").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let enrolment: TotpEnrolment = test::call_and_read_body_json(&app, enrol_request(&access_token, "correct horse").to_request()).await;
+    assert!(enrolment.otpauth_uri.starts_with("otpauth://totp/Inner%20Shelter:alice?secret="));
+    assert_eq!(enrolment.recovery_codes.len(), 10);
+
+    // Not confirmed yet, the password still suffices
+    log_in!(&app, "alice", "correct horse");
+
+    let resp = test::call_service(&app, confirm_request(&access_token, "000000").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let error: ApiError = test::read_body_json(resp).await;
+    assert_eq!(error.field.as_deref(), Some("code"));
+
+    let resp = test::call_service(&app, confirm_request(&access_token, &current_code(&enrolment)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let req = test::TestRequest::get().uri("/me").cookie(access_token.clone()).to_request();
+    let profile: Profile = test::call_and_read_body_json(&app, req).await;
+    assert!(profile.totp_enabled);
+
+    // Enrolling again would silently replace the secret
+    let resp = test::call_service(&app, enrol_request(&access_token, "correct horse").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+}
+
+#[actix_web::test]
+async fn login_asks_for_the_code() {
+    let app = init_app!();
+    let (access_token, _) = session!(&app, "alice", "correct horse");
+    let enrolment = enable_totp!(&app, &access_token, "correct horse");
+
+    // A wrong password gets no further than before
+    let resp = test::call_service(&app, login_request("alice", "battery staple").to_request()).await;
+    let error: ApiError = test::read_body_json(resp).await;
+    assert_eq!(error.code, ErrorCode::InvalidCredentials);
+
+    let challenge = challenge!(&app, "alice", "correct horse");
+    let resp = test::call_service(&app, mfa_request(&challenge, "000000").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let error: ApiError = test::read_body_json(resp).await;
+    assert_eq!(error.code, ErrorCode::InvalidCredentials);
+
+    // Already spent on confirming the enrolment
+    let resp = test::call_service(&app, mfa_request(&challenge, &current_code(&enrolment)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = test::call_service(&app, mfa_request(&challenge, &next_code(&enrolment)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let access_token = response_cookie(&resp, "access_token").unwrap();
+    let req = test::TestRequest::get().uri("/me").cookie(access_token).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let forged = MfaChallenge { token: "not-a-token".to_string(), expires_at: challenge.expires_at };
+    let resp = test::call_service(&app, mfa_request(&forged, &current_code(&enrolment)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn recovery_codes_work_once() {
+    let app = init_app!();
+    let (access_token, _) = session!(&app, "alice", "correct horse");
+    let enrolment = enable_totp!(&app, &access_token, "correct horse");
+    let recovery_code = enrolment.recovery_codes[0].to_uppercase();
+
+    let challenge = challenge!(&app, "alice", "correct horse");
+    let resp = test::call_service(&app, mfa_request(&challenge, &recovery_code).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let challenge = challenge!(&app, "alice", "correct horse");
+    let resp = test::call_service(&app, mfa_request(&challenge, &recovery_code).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = test::call_service(&app, mfa_request(&challenge, &enrolment.recovery_codes[1]).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn wrong_codes_are_throttled() {
+    let app = init_app!();
+    let (access_token, _) = session!(&app, "alice", "correct horse");
+    let enrolment = enable_totp!(&app, &access_token, "correct horse");
+
+    let challenge = challenge!(&app, "alice", "correct horse");
+    for _ in 0..3 {
+        let resp = test::call_service(&app, mfa_request(&challenge, "not-a-code").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    // The right password alone does not reset the count
+    let challenge = challenge!(&app, "alice", "correct horse");
+    let resp = test::call_service(&app, mfa_request(&challenge, "not-a-code").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = test::call_service(&app, mfa_request(&challenge, &next_code(&enrolment)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[actix_web::test]
+async fn changing_the_password_voids_challenges() {
+    let app = init_app!();
+    let (access_token, _) = session!(&app, "alice", "correct horse");
+    let enrolment = enable_totp!(&app, &access_token, "correct horse");
+    let challenge = challenge!(&app, "alice", "correct horse");
+
+    let req = test::TestRequest::post()
+        .uri("/me/password")
+        .cookie(access_token.clone())
+        .set_json(ChangePasswordData {
+            current_password: "correct horse".to_string(),
+            new_password: "staple battery horse".to_string(),
+        });
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = test::call_service(&app, mfa_request(&challenge, &next_code(&enrolment)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn codes_and_challenges_work_once() {
+    let app = init_app!();
+    let (access_token, _) = session!(&app, "alice", "correct horse");
+    let enrolment = enable_totp!(&app, &access_token, "correct horse");
+    let code = next_code(&enrolment);
+
+    let challenge = challenge!(&app, "alice", "correct horse");
+    let resp = test::call_service(&app, mfa_request(&challenge, &code).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // The challenge is spent, whatever the code, and does not cost the recovery code
+    let resp = test::call_service(&app, mfa_request(&challenge, &enrolment.recovery_codes[0]).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let fresh = challenge!(&app, "alice", "correct horse");
+    let resp = test::call_service(&app, mfa_request(&fresh, &enrolment.recovery_codes[0]).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // Challenges stay out of the published access token revocations
+    let req = test::TestRequest::get().uri("/revocations").to_request();
+    let revoked: Vec<RevokedToken> = test::call_and_read_body_json(&app, req).await;
+    assert!(revoked.is_empty());
+
+    // And so is the code, though it is still current
+    let challenge = challenge!(&app, "alice", "correct horse");
+    let resp = test::call_service(&app, mfa_request(&challenge, &code).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn disabling_restores_password_logins() {
+    let app = init_app!();
+    let (access_token, _) = session!(&app, "alice", "correct horse");
+    enable_totp!(&app, &access_token, "correct horse");
+
+    let req = test::TestRequest::delete()
+        .uri("/me/totp")
+        .cookie(access_token.clone())
+        .set_json(TotpPasswordData { password: "correct horse".to_string() });
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    log_in!(&app, "alice", "correct horse");
+}
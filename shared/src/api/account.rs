@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+use crate::api::error::ApiError;
+use crate::policy::check_email;
+
+/// Longest display name, in characters.
+pub const MAX_DISPLAY_NAME_LENGTH: usize = 32;
+
+/// The logged in user's own account, as returned by `GET /me`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub id: String,
+    pub username: String,
+    pub display_name: String,
+    pub roles: Vec<String>,
+    /// Unix timestamps in seconds.
+    pub created_at: i64,
+    pub last_login_at: Option<i64>,
+    pub email: Option<String>,
+    /// Whether the user proved they receive mail at `email`.
+    pub email_verified: bool,
+    /// Whether logging in asks for a code from an authenticator app.
+    pub totp_enabled: bool,
+}
+
+/// Body of `PATCH /me`. Fields left out stay as they are.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct UpdateProfileData {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// A new address is unverified until the user follows the link mailed there.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// The current password, needed to change the email, since whoever controls the
+    /// email can reset the password.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+}
+
+impl UpdateProfileData {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if let Some(display_name) = &self.display_name {
+            check_display_name(display_name)?;
+        }
+        if let Some(email) = &self.email {
+            check_email(email)?;
+            if self.password.is_none() {
+                return Err(ApiError::invalid("password", "Changing the email takes the current password"));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_display_name(display_name: &str) -> Result<(), ApiError> {
+    let display_name = display_name.trim();
+    if display_name.is_empty() {
+        return Err(ApiError::invalid("display_name", "Display name cannot be empty"));
+    }
+    if display_name.chars().count() > MAX_DISPLAY_NAME_LENGTH {
+        return Err(ApiError::invalid(
+            "display_name",
+            format!("Display name must be at most {} characters long", MAX_DISPLAY_NAME_LENGTH),
+        ));
+    }
+    if display_name.chars().any(char::is_control) {
+        return Err(ApiError::invalid("display_name", "Display name cannot contain control characters"));
+    }
+    Ok(())
+}
+
+/// Body of `POST /me/password`. The new password is checked against the same policy
+/// as at registration.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChangePasswordData {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+/// Body of `DELETE /me`, the password confirms it is really the owner asking.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeleteAccountData {
+    pub password: String,
+}
+
+/// Answer to `DELETE /me`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AccountDeletion {
+    /// Unix timestamp in seconds when the account is gone for good. Logging in before
+    /// then cancels the deletion.
+    pub deleted_at: i64,
+}
+
+/// Body of `POST /verify-email`, with the token from the verification mail.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VerifyEmailData {
+    pub token: String,
+}
+
+/// Body of `POST /password-reset/request`. The answer is the same whether or not the
+/// account exists, the mail only goes out to a verified address.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PasswordResetRequestData {
+    pub username: String,
+}
+
+/// Body of `POST /password-reset/confirm`, with the token from the reset mail.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PasswordResetData {
+    pub token: String,
+    pub new_password: String,
+}
+
+/// Body of `POST /me/totp` and `DELETE /me/totp`, the password confirms it is really the
+/// owner asking.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TotpPasswordData {
+    pub password: String,
+}
+
+/// Answer to `POST /me/totp`. Nothing changes at login until a code from the app is sent
+/// to `POST /me/totp/confirm`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TotpEnrolment {
+    /// For authenticator apps, usually shown as a QR code.
+    pub otpauth_uri: String,
+    /// The same secret in base32, for typing in by hand.
+    pub secret: String,
+    /// Each logs in once in place of a code. They are only shown now.
+    pub recovery_codes: Vec<String>,
+}
+
+/// Body of `POST /me/totp/confirm`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConfirmTotpData {
+    pub code: String,
+}
+
+/// One place the user is logged in, as listed by `GET /me/sessions`. Revoke it with
+/// `DELETE /me/sessions/{id}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionInfo {
+    pub id: String,
+    /// The `User-Agent` of the browser or app that logged in.
+    pub user_agent: Option<String>,
+    /// The address the session was started from.
+    pub ip: Option<String>,
+    /// Unix timestamps in seconds of the login and of the latest token renewal.
+    pub created_at: i64,
+    pub last_seen_at: i64,
+    /// Whether this is the session making the request.
+    pub current: bool,
+}
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+use crate::api::auth::Role;
+use crate::api::error::ApiError;
+
+/// Header carrying the shared secret on calls between the auth service and game servers.
+pub const INTERNAL_TOKEN_HEADER: &str = "X-Internal-Token";
+
+/// Default and largest page of `GET /admin/users`.
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+pub const MAX_PAGE_SIZE: usize = 200;
+
+/// Why and until when an account is kept out.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Ban {
+    pub reason: String,
+    /// Unix timestamps in seconds, a ban without `until` is permanent.
+    pub banned_at: i64,
+    pub until: Option<i64>,
+}
+
+impl Ban {
+    pub fn is_active(&self, now: i64) -> bool {
+        self.until.is_none_or(|until| until > now)
+    }
+}
+
+/// Longest ban reason, in characters.
+pub const MAX_BAN_REASON_LENGTH: usize = 500;
+/// Longest timed ban, ten years in seconds. Longer ones are made permanent instead.
+pub const MAX_BAN_DURATION: i64 = 10 * 365 * 24 * 60 * 60;
+
+/// Body of `POST /admin/users/{id}/ban`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BanData {
+    /// Shown to the banned player.
+    pub reason: String,
+    /// In seconds, the ban is permanent without one.
+    pub duration: Option<i64>,
+}
+
+impl BanData {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        let reason = self.reason.trim();
+        if reason.is_empty() {
+            return Err(ApiError::invalid("reason", "A ban needs a reason"));
+        }
+        if reason.chars().count() > MAX_BAN_REASON_LENGTH {
+            return Err(ApiError::invalid(
+                "reason",
+                format!("Reason must be at most {} characters long", MAX_BAN_REASON_LENGTH),
+            ));
+        }
+        if self.duration.is_some_and(|duration| duration <= 0) {
+            return Err(ApiError::invalid("duration", "Duration must be positive"));
+        }
+        if self.duration.is_some_and(|duration| duration > MAX_BAN_DURATION) {
+            return Err(ApiError::invalid(
+                "duration",
+                "Duration must be at most ten years, leave it out for a permanent ban",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A change to a user's ban, pushed by the auth service to game servers and listed for
+/// them at `/internal/bans`. `ban: None` means it was lifted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BanUpdate {
+    pub user_id: String,
+    pub ban: Option<Ban>,
+}
+
+/// An account as staff see it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserSummary {
+    pub id: String,
+    pub username: String,
+    pub display_name: String,
+    pub roles: Vec<String>,
+    /// Unix timestamps in seconds.
+    pub created_at: i64,
+    pub last_login_at: Option<i64>,
+    pub disabled: bool,
+    pub password_reset_required: bool,
+    /// The current ban, if any, expired ones are left out.
+    pub ban: Option<Ban>,
+}
+
+/// Query of `GET /admin/users`. Pages are ordered by the store, pass the previous
+/// page's `next` as `after` to continue.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ListUsersQuery {
+    pub after: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserPage {
+    pub users: Vec<UserSummary>,
+    /// Where the next page starts, `None` on the last one.
+    pub next: Option<String>,
+}
+
+/// Body of `PUT /admin/users/{id}/roles`, replacing all of the user's roles.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SetRolesData {
+    pub roles: Vec<Role>,
+}
@@ -1,37 +1,154 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use crate::api::error::ApiError;
+use crate::policy::{check_email, RegistrationPolicy};
+
+/// What an account may do, each role includes everything the ones before it may.
+/// Tokens and stores carry roles as their lowercase names, unknown names grant nothing.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Player,
+    /// Can look up and ban players.
+    Moderator,
+    /// Can also grant and take away roles.
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Player => "player",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+
+    /// The most powerful of the named roles, `None` if none is known.
+    pub fn highest<S: AsRef<str>>(roles: &[S]) -> Option<Role> {
+        roles.iter().filter_map(|role| role.as_ref().parse().ok()).max()
+    }
+}
+
+impl FromStr for Role {
+    type Err = ApiError;
+
+    fn from_str(role: &str) -> Result<Self, Self::Err> {
+        match role {
+            "player" => Ok(Role::Player),
+            "moderator" => Ok(Role::Moderator),
+            "admin" => Ok(Role::Admin),
+            _ => Err(ApiError::invalid("roles", format!("Unknown role {}", role))),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct LoginData {
     pub username: String,
     pub password: String,
+    /// Also return the access token in the body, for native clients without a cookie jar.
+    /// Browsers should leave this off so scripts never see the token.
+    #[serde(default)]
+    pub include_token: bool,
+    /// Replaces the password in the same step, only needed and only used when the login
+    /// failed with `ErrorCode::PasswordResetRequired`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_password: Option<String>,
 }
 
 impl LoginData {
-    pub fn validate(&self) -> Result<(), String> {
+    pub fn validate(&self) -> Result<(), ApiError> {
         if self.username.trim().is_empty() {
-            return Err("Username cannot be empty".into());
+            return Err(ApiError::invalid("username", "Username cannot be empty"));
         }
         if self.password.is_empty() {
-            return Err("Password cannot be empty".into());
+            return Err(ApiError::invalid("password", "Password cannot be empty"));
+        }
+        Ok(())
+    }
+}
+
+/// Handed out when the password was right but the account has two-factor authentication.
+/// The login finishes at `POST /login/mfa` with the token and a code.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MfaChallenge {
+    pub token: String,
+    /// Unix timestamp in seconds, after which the login has to start over.
+    pub expires_at: i64,
+}
+
+/// Body of `POST /login/mfa`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MfaLoginData {
+    /// `MfaChallenge::token` from the first step.
+    pub mfa_token: String,
+    /// The six digits from the authenticator app, or an unused recovery code.
+    pub code: String,
+    /// As in `LoginData`.
+    #[serde(default)]
+    pub include_token: bool,
+    /// As in `LoginData`, for accounts that also have to choose a new password.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_password: Option<String>,
+}
+
+impl MfaLoginData {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.code.trim().is_empty() {
+            return Err(ApiError::invalid("code", "Code cannot be empty"));
         }
         Ok(())
     }
 }
 
+/// The body of a successful login, the session itself travels in cookies.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LoginResponse {
+    pub user_id: String,
+    pub username: String,
+    pub display_name: String,
+    /// When the access token expires, as a Unix timestamp in seconds.
+    pub expires_at: i64,
+    pub roles: Vec<String>,
+    /// The access token, only when `LoginData::include_token` was set. Send it as
+    /// `Authorization: Bearer <token>` to the game server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RegisterData {
     pub username: String,
     pub password: String,
+    /// Optional, but without a verified email a forgotten password cannot be reset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
 }
 
 impl RegisterData {
-    pub fn validate(&self) -> Result<(), String> {
-        if self.username.trim().is_empty() {
-            return Err("Username cannot be empty".into());
+    pub fn validate(&self, policy: &RegistrationPolicy) -> Result<(), ApiError> {
+        policy.check_username(&self.username)?;
+        policy.check_password(&self.password, &self.username)?;
+        match &self.email {
+            Some(email) => check_email(email),
+            None => Ok(()),
         }
-        if self.username.len() < 3 {
-            return Err("Username must be at least 3 characters long".into());
-        }
-        Ok(())
     }
 }
+
+/// An access token revoked before its expiry, identified by its `jti` claim, or every
+/// access token of a revoked session, identified by their `sid` claim. It only needs to
+/// be listed until `expires_at`, after that it is rejected anyway.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RevokedToken {
+    pub jti: String,
+    pub expires_at: i64,
+}
@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use crate::api::admin::Ban;
+use crate::api::auth::MfaChallenge;
+
+/// Stable, machine-readable reasons for a failed request. Clients match on these rather
+/// than on messages, which may change wording.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    /// The request was malformed or a field failed validation, see `ApiError::field`.
+    InvalidInput,
+    WeakPassword,
+    UsernameTaken,
+    InvalidCredentials,
+    /// Missing, expired or revoked session.
+    Unauthorized,
+    /// The session is fine but its roles do not allow the request.
+    Forbidden,
+    NotFound,
+    /// The account is banned, see `ApiError::ban` for why and until when.
+    Banned,
+    /// An administrator requires a new password, send it as `LoginData::new_password`.
+    PasswordResetRequired,
+    /// The password was right, finish with the second factor using `ApiError::mfa_challenge`.
+    MfaRequired,
+    RateLimited,
+    /// The service is overloaded, retry shortly.
+    ServiceBusy,
+    Internal,
+    /// A code this build does not know yet, or a failure that never reached the service.
+    #[serde(other)]
+    Unknown,
+}
+
+/// The body of every failed response from the auth service.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    /// Human-readable detail, safe to show but not meant to be matched on.
+    pub message: String,
+    /// The request field at fault, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    /// The ban behind an `ErrorCode::Banned`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ban: Option<Ban>,
+    /// The challenge behind an `ErrorCode::MfaRequired`, boxed to keep errors small.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mfa_challenge: Option<Box<MfaChallenge>>,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self { code, message: message.into(), field: None, ban: None, mfa_challenge: None }
+    }
+
+    pub fn with_field(mut self, field: impl Into<String>) -> Self {
+        self.field = Some(field.into());
+        self
+    }
+
+    pub fn with_ban(mut self, ban: Ban) -> Self {
+        self.ban = Some(ban);
+        self
+    }
+
+    pub fn with_mfa_challenge(mut self, challenge: MfaChallenge) -> Self {
+        self.mfa_challenge = Some(Box::new(challenge));
+        self
+    }
+
+    pub fn invalid(field: &str, message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InvalidInput, message).with_field(field)
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
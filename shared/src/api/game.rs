@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+
+/// Longest chat message in characters the game server passes on.
+pub const MAX_CHAT_LENGTH: usize = 500;
+
+/// Messages sent by a client to the game server over the WebSocket.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Move { dx: i32, dy: i32 },
+    Chat { text: String },
+    Ping { nonce: u64 },
+}
+
+/// Messages sent by the game server to a client over the WebSocket.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Welcome { username: String },
+    Snapshot { players: Vec<PlayerState> },
+    Chat { from: String, text: String },
+    Pong { nonce: u64 },
+    Error { message: String },
+    /// Sent right before the server closes the connection on its own initiative.
+    Disconnected { reason: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerState {
+    pub username: String,
+    pub x: i32,
+    pub y: i32,
+}
@@ -1,3 +1,12 @@
 pub mod api {
+    pub mod account;
+    pub mod admin;
     pub mod auth;
+    pub mod error;
+    pub mod game;
 }
+
+pub mod policy;
+
+#[cfg(feature = "token")]
+pub mod token;
@@ -0,0 +1,125 @@
+use crate::api::error::{ApiError, ErrorCode};
+
+/// Widely used passwords that are the first guesses of any attacker, one per line.
+const COMMON_PASSWORDS: &str = include_str!("common_passwords.txt");
+
+/// bcrypt ignores everything past the first 72 bytes of a password.
+pub const BCRYPT_MAX_PASSWORD_BYTES: usize = 72;
+
+/// The longest address SMTP can deliver to.
+pub const MAX_EMAIL_LENGTH: usize = 254;
+
+/// What a new account's username and password must satisfy. The client checks the form
+/// against the default policy before sending it and the service checks again, against
+/// the default unless its environment overrides some limits. Overrides only the service
+/// knows about surface as errors from the service rather than in the form.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegistrationPolicy {
+    pub min_username_length: usize,
+    pub max_username_length: usize,
+    /// Names nobody may register, compared case-insensitively.
+    pub reserved_usernames: Vec<String>,
+    pub min_password_length: usize,
+    /// In bytes, not characters, and never above `BCRYPT_MAX_PASSWORD_BYTES`.
+    pub max_password_bytes: usize,
+}
+
+impl Default for RegistrationPolicy {
+    fn default() -> Self {
+        Self {
+            min_username_length: 3,
+            max_username_length: 32,
+            reserved_usernames: ["admin", "administrator", "moderator", "root", "system", "server", "service", "support", "me"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            min_password_length: 8,
+            max_password_bytes: BCRYPT_MAX_PASSWORD_BYTES,
+        }
+    }
+}
+
+impl RegistrationPolicy {
+    /// Usernames are ASCII letters, digits, `_` and `-`, so they read the same everywhere
+    /// and cannot impersonate each other with lookalike characters.
+    pub fn check_username(&self, username: &str) -> Result<(), ApiError> {
+        if username.trim().is_empty() {
+            return Err(ApiError::invalid("username", "Username cannot be empty"));
+        }
+        if username.len() < self.min_username_length {
+            return Err(ApiError::invalid(
+                "username",
+                format!("Username must be at least {} characters long", self.min_username_length),
+            ));
+        }
+        if username.len() > self.max_username_length {
+            return Err(ApiError::invalid(
+                "username",
+                format!("Username must be at most {} characters long", self.max_username_length),
+            ));
+        }
+        if !username.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            return Err(ApiError::invalid(
+                "username",
+                "Username may only contain letters, digits, '_' and '-'",
+            ));
+        }
+        if self.reserved_usernames.iter().any(|reserved| reserved.eq_ignore_ascii_case(username)) {
+            return Err(ApiError::invalid("username", "This username is reserved"));
+        }
+        Ok(())
+    }
+
+    pub fn check_password(&self, password: &str, username: &str) -> Result<(), ApiError> {
+        if password.chars().count() < self.min_password_length {
+            return Err(weak_password(format!(
+                "Password must be at least {} characters long",
+                self.min_password_length
+            )));
+        }
+        if password.len() > self.max_password_bytes.min(BCRYPT_MAX_PASSWORD_BYTES) {
+            return Err(weak_password(format!(
+                "Password must be at most {} bytes long",
+                self.max_password_bytes.min(BCRYPT_MAX_PASSWORD_BYTES)
+            )));
+        }
+
+        let lowercase = password.to_lowercase();
+        if !username.is_empty() && lowercase.contains(&username.to_lowercase()) {
+            return Err(weak_password("Password must not contain the username"));
+        }
+        if COMMON_PASSWORDS.lines().any(|common| common == lowercase) {
+            return Err(weak_password("This password is too common, choose another one"));
+        }
+        Ok(())
+    }
+}
+
+/// A loose check that catches typos, not a full RFC 5322 parser. Whether the address
+/// really exists only shows when the verification mail arrives.
+pub fn check_email(email: &str) -> Result<(), ApiError> {
+    if email.len() > MAX_EMAIL_LENGTH {
+        return Err(ApiError::invalid(
+            "email",
+            format!("Email must be at most {} characters long", MAX_EMAIL_LENGTH),
+        ));
+    }
+    let valid = match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !domain.contains('@')
+        }
+        None => false,
+    };
+    if !valid || email.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(ApiError::invalid("email", "This is not a valid email address"));
+    }
+    Ok(())
+}
+
+fn weak_password(message: impl Into<String>) -> ApiError {
+    ApiError::new(ErrorCode::WeakPassword, message).with_field("password")
+}
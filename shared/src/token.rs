@@ -0,0 +1,156 @@
+use chrono::Utc;
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+pub use jsonwebtoken::jwk::{self, JwkSet};
+
+/// Who issues access tokens, checked by everyone validating them.
+pub const ISSUER: &str = "inner-shelter-service";
+/// Who access tokens are meant for.
+pub const AUDIENCE: &str = "inner-shelter";
+/// The cookie browsers carry their access token in.
+pub const ACCESS_TOKEN_COOKIE: &str = "access_token";
+
+/// Tokens are signed with Ed25519 keys. Only the service holds private keys, everyone
+/// else verifies against the public keys it publishes as a JWK set.
+const ALGORITHM: Algorithm = Algorithm::EdDSA;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Claims {
+    /// The user's id, stable across username changes.
+    pub sub: String,
+    /// The user's username when the token was issued.
+    pub username: String,
+    pub iss: String,
+    pub aud: String,
+    /// Issued at, Unix timestamp in seconds.
+    pub iat: i64,
+    /// Expires at, Unix timestamp in seconds.
+    pub exp: i64,
+    /// Unique token id, used to revoke this token before it expires.
+    pub jti: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// The session the token was issued for, so revoking the session revokes it too.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenError(pub String);
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Token error: {}", self.0)
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+/// A private key tokens are signed with, identified by the `kid` header so verifiers
+/// know which public key to check against while keys are being rotated.
+#[derive(Clone)]
+pub struct SigningKey {
+    pub kid: String,
+    key: EncodingKey,
+}
+
+impl SigningKey {
+    /// `der` is an Ed25519 private key in PKCS#8 DER encoding.
+    pub fn from_ed_der(kid: &str, der: &[u8]) -> Self {
+        Self { kid: kid.to_string(), key: EncodingKey::from_ed_der(der) }
+    }
+}
+
+/// The public keys tokens are verified against, by key id.
+#[derive(Clone, Default)]
+pub struct VerificationKeys {
+    keys: HashMap<String, DecodingKey>,
+}
+
+impl VerificationKeys {
+    /// Takes every key with a key id from a JWK set, as published at `/.well-known/jwks.json`.
+    pub fn from_jwks(jwks: &JwkSet) -> Result<Self, TokenError> {
+        let mut keys = HashMap::new();
+        for jwk in &jwks.keys {
+            let Some(kid) = &jwk.common.key_id else { continue };
+            let key = DecodingKey::from_jwk(jwk).map_err(|e| TokenError(e.to_string()))?;
+            keys.insert(kid.clone(), key);
+        }
+        Ok(Self { keys })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+/// Issues an access token for the user with id `subject`, valid for `ttl` seconds.
+pub fn issue(
+    subject: &str,
+    username: &str,
+    roles: Vec<String>,
+    session_id: Option<&str>,
+    ttl: i64,
+    signing_key: &SigningKey,
+) -> Result<(String, Claims), TokenError> {
+    let now = Utc::now().timestamp();
+    let claims = Claims {
+        sub: subject.to_string(),
+        username: username.to_string(),
+        iss: ISSUER.to_string(),
+        aud: AUDIENCE.to_string(),
+        iat: now,
+        exp: now + ttl,
+        jti: uuid::Uuid::new_v4().to_string(),
+        roles,
+        sid: session_id.map(str::to_string),
+    };
+
+    let token = sign(&claims, signing_key)?;
+    Ok((token, claims))
+}
+
+/// Checks the signature, expiry, issuer and audience of an access token.
+pub fn validate(token: &str, keys: &VerificationKeys) -> Result<Claims, TokenError> {
+    verify(token, keys, AUDIENCE)
+}
+
+/// The access token a request carries, given the value of its `access_token` cookie and
+/// of its `Authorization` header. Browsers send the cookie, other clients a `Bearer`
+/// header. Takes plain values so that this crate needs no web framework.
+pub fn request_access_token(cookie: Option<&str>, authorization: Option<&str>) -> Option<String> {
+    if let Some(cookie) = cookie {
+        return Some(cookie.to_string());
+    }
+    authorization?.strip_prefix("Bearer ").map(|token| token.trim().to_string())
+}
+
+/// Signs any claims with the same keys as access tokens. Other kinds of tokens must
+/// carry their own audience, so that none of them passes for an access token.
+pub fn sign<T: Serialize>(claims: &T, signing_key: &SigningKey) -> Result<String, TokenError> {
+    let mut header = Header::new(ALGORITHM);
+    header.kid = Some(signing_key.kid.clone());
+
+    encode(&header, claims, &signing_key.key).map_err(|e| TokenError(e.to_string()))
+}
+
+/// Checks the signature, expiry and issuer of a token and that it is meant for `audience`.
+pub fn verify<T: DeserializeOwned>(token: &str, keys: &VerificationKeys, audience: &str) -> Result<T, TokenError> {
+    let header = decode_header(token).map_err(|e| TokenError(e.to_string()))?;
+    let kid = header.kid.ok_or_else(|| TokenError("Token has no key id".into()))?;
+    let key = keys.keys.get(&kid).ok_or_else(|| TokenError(format!("Unknown key id: {}", kid)))?;
+
+    // Pinning the algorithm keeps a token from choosing how it is verified
+    let mut validation = Validation::new(ALGORITHM);
+    validation.set_issuer(&[ISSUER]);
+    validation.set_audience(&[audience]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+    decode::<T>(token, key, &validation)
+        .map(|data| data.claims)
+        .map_err(|e| TokenError(e.to_string()))
+}